@@ -0,0 +1,12 @@
+CREATE TABLE newsletter_issues (
+    newsletter_issue_id UUID NOT NULL PRIMARY KEY,
+    title TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    status TEXT NOT NULL,
+    send_at TIMESTAMP WITH TIME ZONE NULL,
+    published_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    started_at TIMESTAMP WITH TIME ZONE NULL,
+    finished_at TIMESTAMP WITH TIME ZONE NULL
+);
+
+CREATE INDEX newsletter_issues_due_idx ON newsletter_issues (send_at) WHERE status = 'scheduled';
@@ -0,0 +1,14 @@
+CREATE TABLE deliveries (
+    newsletter_issue_id UUID NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id UUID NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    error TEXT NULL,
+    queued_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    sent_at TIMESTAMP WITH TIME ZONE NULL,
+    failed_at TIMESTAMP WITH TIME ZONE NULL,
+    bounced_at TIMESTAMP WITH TIME ZONE NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+
+CREATE INDEX deliveries_provider_message_id_idx ON deliveries (provider_message_id);
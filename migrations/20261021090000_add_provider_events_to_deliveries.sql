@@ -0,0 +1,3 @@
+ALTER TABLE deliveries ADD COLUMN bounce_type TEXT NULL;
+ALTER TABLE deliveries ADD COLUMN delivered_at TIMESTAMP WITH TIME ZONE NULL;
+ALTER TABLE deliveries ADD COLUMN complained_at TIMESTAMP WITH TIME ZONE NULL;
@@ -0,0 +1,12 @@
+CREATE TABLE suppressions (
+    email TEXT NOT NULL PRIMARY KEY,
+    reason TEXT NOT NULL,
+    source TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+INSERT INTO suppressions (email, reason, source, created_at)
+SELECT lower(email), 'unknown', 'migration', now()
+FROM subscriptions
+WHERE status = 'suppressed'
+ON CONFLICT DO NOTHING;
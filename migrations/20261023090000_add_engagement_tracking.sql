@@ -0,0 +1,14 @@
+ALTER TABLE newsletter_issues ADD COLUMN tracking_enabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE delivery_events (
+    id BIGSERIAL PRIMARY KEY,
+    newsletter_issue_id UUID NOT NULL,
+    subscriber_id UUID NOT NULL,
+    kind TEXT NOT NULL,
+    url TEXT NULL,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    FOREIGN KEY (newsletter_issue_id, subscriber_id)
+        REFERENCES deliveries (newsletter_issue_id, subscriber_id)
+);
+
+CREATE INDEX delivery_events_newsletter_issue_id_idx ON delivery_events (newsletter_issue_id);
@@ -0,0 +1,31 @@
+CREATE TABLE lists (
+    list_id UUID NOT NULL PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE list_memberships (
+    list_id UUID NOT NULL REFERENCES lists (list_id),
+    subscriber_id UUID NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+CREATE TABLE newsletter_issue_lists (
+    newsletter_issue_id UUID NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    list_id UUID NOT NULL REFERENCES lists (list_id),
+    PRIMARY KEY (newsletter_issue_id, list_id)
+);
+
+INSERT INTO lists (list_id, slug, name, created_at)
+VALUES (gen_random_uuid(), 'newsletter', 'Newsletter', now());
+
+INSERT INTO list_memberships (list_id, subscriber_id, status, updated_at)
+SELECT l.list_id, s.id, 'subscribed', now()
+FROM lists l CROSS JOIN subscriptions s;
+
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT i.newsletter_issue_id, l.list_id
+FROM lists l CROSS JOIN newsletter_issues i;
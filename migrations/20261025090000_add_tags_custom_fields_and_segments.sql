@@ -0,0 +1,19 @@
+ALTER TABLE subscriptions ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE subscriptions ADD COLUMN custom_fields JSONB NOT NULL DEFAULT '{}';
+
+CREATE INDEX subscriptions_tags_idx ON subscriptions USING GIN (tags);
+
+CREATE TABLE custom_fields (
+    name TEXT NOT NULL PRIMARY KEY,
+    field_type TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE segments (
+    segment_id UUID NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    query TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+ALTER TABLE newsletter_issues ADD COLUMN segment_query TEXT;
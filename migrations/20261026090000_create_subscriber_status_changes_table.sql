@@ -0,0 +1,12 @@
+CREATE TABLE subscriber_status_changes (
+    id BIGSERIAL PRIMARY KEY,
+    subscriber_id UUID NOT NULL REFERENCES subscriptions (id),
+    old_status TEXT NOT NULL,
+    new_status TEXT NOT NULL,
+    changed_by UUID NOT NULL REFERENCES users (user_id),
+    changed_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX subscriber_status_changes_subscriber_id_idx ON subscriber_status_changes (subscriber_id);
+
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at, id);
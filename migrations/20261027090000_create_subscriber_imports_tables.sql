@@ -0,0 +1,24 @@
+ALTER TABLE subscriptions ADD COLUMN consent_source TEXT;
+
+CREATE TABLE subscriber_imports (
+    import_id UUID NOT NULL PRIMARY KEY,
+    format TEXT NOT NULL,
+    initial_status TEXT NOT NULL,
+    consent_source TEXT,
+    list_id UUID NOT NULL REFERENCES lists (list_id),
+    created_by UUID REFERENCES users (user_id),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    finished_at TIMESTAMP WITH TIME ZONE,
+    last_row BIGINT NOT NULL DEFAULT 0,
+    imported BIGINT NOT NULL DEFAULT 0,
+    duplicates BIGINT NOT NULL DEFAULT 0,
+    failed BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE subscriber_import_errors (
+    import_id UUID NOT NULL REFERENCES subscriber_imports (import_id),
+    row_number BIGINT NOT NULL,
+    email TEXT,
+    error TEXT NOT NULL,
+    PRIMARY KEY (import_id, row_number)
+);
@@ -0,0 +1,7 @@
+ALTER TABLE suppressions ADD COLUMN email_hash TEXT;
+UPDATE suppressions SET email_hash = encode(sha256(convert_to(email, 'UTF8')), 'hex');
+ALTER TABLE suppressions ALTER COLUMN email_hash SET NOT NULL;
+
+ALTER TABLE suppressions DROP CONSTRAINT suppressions_pkey;
+ALTER TABLE suppressions ALTER COLUMN email DROP NOT NULL;
+ALTER TABLE suppressions ADD PRIMARY KEY (email_hash);
@@ -0,0 +1,36 @@
+CREATE TABLE consent_records (
+    id BIGSERIAL PRIMARY KEY,
+    subscriber_id UUID NOT NULL REFERENCES subscriptions (id),
+    event TEXT NOT NULL,
+    source TEXT NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    consent_text TEXT,
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX consent_records_subscriber_id_idx ON consent_records (subscriber_id);
+
+-- Consent evidence is append-only. Rows are only deleted together with their subscriber.
+CREATE FUNCTION reject_consent_record_update() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'consent records cannot be modified';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_records_immutable
+    BEFORE UPDATE ON consent_records
+    FOR EACH ROW EXECUTE FUNCTION reject_consent_record_update();
+
+INSERT INTO consent_records (subscriber_id, event, source, recorded_at)
+SELECT
+    id,
+    CASE WHEN consent_source IS NULL THEN 'subscribed' ELSE 'imported' END,
+    COALESCE(consent_source, 'unknown'),
+    subscribed_at
+FROM subscriptions;
+
+INSERT INTO consent_records (subscriber_id, event, source, recorded_at)
+SELECT id, 'confirmed', 'unknown', confirmed_at
+FROM subscriptions
+WHERE confirmed_at IS NOT NULL;
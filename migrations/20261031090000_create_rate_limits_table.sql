@@ -0,0 +1,6 @@
+CREATE TABLE rate_limits (
+    key TEXT NOT NULL,
+    window_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    attempts BIGINT NOT NULL,
+    PRIMARY KEY (key, window_start)
+);
@@ -0,0 +1,32 @@
+ALTER TABLE subscriptions ADD COLUMN normalised_email TEXT;
+
+-- Existing addresses are normalised like `email_validation.local_part_case: lowercase`, the
+-- default. Deployments which preserve the case re-normalise them with
+-- `newsletter-admin normalise-emails`.
+UPDATE subscriptions SET normalised_email = lower(email);
+
+-- Of the subscribers sharing an address, a confirmed one is kept before any other and then
+-- the oldest one. The others are deleted together with their records.
+CREATE TEMPORARY TABLE duplicate_subscribers ON COMMIT DROP AS
+SELECT id
+FROM (
+    SELECT id, row_number() OVER (
+        PARTITION BY normalised_email
+        ORDER BY status <> 'confirmed', subscribed_at, id
+    ) AS position
+    FROM subscriptions
+) ranked
+WHERE position > 1;
+
+DELETE FROM delivery_events WHERE subscriber_id IN (SELECT id FROM duplicate_subscribers);
+DELETE FROM deliveries WHERE subscriber_id IN (SELECT id FROM duplicate_subscribers);
+DELETE FROM subscription_tokens WHERE subscriber_id IN (SELECT id FROM duplicate_subscribers);
+DELETE FROM list_memberships WHERE subscriber_id IN (SELECT id FROM duplicate_subscribers);
+DELETE FROM consent_records WHERE subscriber_id IN (SELECT id FROM duplicate_subscribers);
+DELETE FROM subscriber_status_changes
+WHERE subscriber_id IN (SELECT id FROM duplicate_subscribers);
+DELETE FROM subscriptions WHERE id IN (SELECT id FROM duplicate_subscribers);
+
+ALTER TABLE subscriptions ALTER COLUMN normalised_email SET NOT NULL;
+
+CREATE UNIQUE INDEX subscriptions_normalised_email_key ON subscriptions (normalised_email);
@@ -0,0 +1,9 @@
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
+
+CREATE TABLE newsletter_issue_variants (
+    newsletter_issue_id UUID NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    locale TEXT NOT NULL,
+    title TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, locale)
+);
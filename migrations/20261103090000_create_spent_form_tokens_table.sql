@@ -0,0 +1,14 @@
+CREATE TABLE spent_form_tokens (
+    nonce TEXT PRIMARY KEY,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX spent_form_tokens_expires_at_idx ON spent_form_tokens (expires_at);
+
+-- Stale windows are purged across every key.
+CREATE INDEX rate_limits_window_start_idx ON rate_limits (window_start);
+
+-- Email domains are only rate limited while they have no confirmed subscribers.
+CREATE INDEX subscriptions_confirmed_domain_idx
+    ON subscriptions (split_part(normalised_email, '@', 2))
+    WHERE status = 'confirmed';
@@ -0,0 +1,10 @@
+-- Confirmation emails for imported subscribers are sent by the worker rather than in the
+-- upload request. A queued email goes away with its subscription token.
+CREATE TABLE confirmation_email_queue (
+    subscription_token TEXT PRIMARY KEY
+        REFERENCES subscription_tokens (subscription_token) ON DELETE CASCADE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    send_after TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX confirmation_email_queue_send_after_idx ON confirmation_email_queue (send_after);
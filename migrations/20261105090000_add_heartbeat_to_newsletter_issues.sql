@@ -0,0 +1,8 @@
+-- Touched while an issue is being delivered. An issue left in 'sending' by a crashed worker
+-- stops being touched and is reclaimed once its lease has run out.
+ALTER TABLE newsletter_issues ADD COLUMN heartbeat_at TIMESTAMP WITH TIME ZONE NULL;
+
+UPDATE newsletter_issues SET heartbeat_at = started_at WHERE status = 'sending';
+
+CREATE INDEX newsletter_issues_sending_idx ON newsletter_issues (heartbeat_at)
+    WHERE status = 'sending';
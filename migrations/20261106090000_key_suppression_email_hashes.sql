@@ -0,0 +1,5 @@
+-- The email hashes so far are plain SHA-256 digests. The database does not know
+-- `application.hmac_secret`, so they are keyed by `suppression::key_legacy_email_hashes` right
+-- after the migrations have been applied.
+ALTER TABLE suppressions ADD COLUMN legacy_email_hash BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE suppressions SET legacy_email_hash = TRUE;
@@ -0,0 +1,7 @@
+-- Confirmation emails to suppressed addresses stay in the queue as 'skipped', like the
+-- deliveries of an issue, so that it can be told why they were never sent.
+ALTER TABLE confirmation_email_queue ADD COLUMN status TEXT NOT NULL DEFAULT 'queued';
+
+DROP INDEX confirmation_email_queue_send_after_idx;
+CREATE INDEX confirmation_email_queue_send_after_idx ON confirmation_email_queue (send_after)
+    WHERE status = 'queued';
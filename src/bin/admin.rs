@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context};
+use futures_util::TryStreamExt;
+use tokio::fs::File;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufReader};
+use uuid::Uuid;
+
+use newsletter::{
+    configuration::get_configuration,
+    custom_fields::get_field_types,
+    email_normalisation::{normalise_subscriber_emails, DuplicateResolution},
+    lists::{get_list_id, DEFAULT_LIST},
+    segments::{get_segment_query, Segment},
+    startup::get_app_state,
+    subscriber_export::{export_subscribers, ExportFilter, ExportFormat},
+    subscriber_import::{create_import, run_import, ImportOptions},
+    telemetry::{get_subscriber, initialize_subscriber},
+};
+
+const USAGE: &str = "Usage:
+    newsletter-admin import --format <csv|jsonl> --status <confirmed|pending>
+        [--consent-source <source>] [--list <slug>] <file>
+    newsletter-admin import --resume <import_id> <file>
+    newsletter-admin export [--format <csv|ndjson>] [--segment <name>] [--list <slug>]
+        [--status <status>] [--output <file>]
+    newsletter-admin normalise-emails [--duplicates <report|delete>]";
+
+#[tokio::main]
+async fn main() {
+    let subscriber = get_subscriber(
+        "newsletter-admin".into(),
+        "warn".into(),
+        std::io::stderr,
+        None,
+    );
+    initialize_subscriber(subscriber);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(error) = run(&args).await {
+        eprintln!("{:?}", error);
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: &[String]) -> Result<(), anyhow::Error> {
+    match args.split_first() {
+        Some((command, args)) if command == "import" => import(args).await,
+        Some((command, args)) if command == "export" => export(args).await,
+        Some((command, args)) if command == "normalise-emails" => normalise_emails(args).await,
+        _ => Err(anyhow!(USAGE)),
+    }
+}
+
+async fn import(args: &[String]) -> Result<(), anyhow::Error> {
+    let (options, paths) = parse_arguments(args)?;
+    let path = match paths.as_slice() {
+        [path] => path,
+        _ => return Err(anyhow!(USAGE)),
+    };
+
+    let configuration = get_configuration().context("Failed to read configuration")?;
+    let app_state = get_app_state(&configuration).await;
+
+    let import_id = match options.get("resume") {
+        Some(import_id) => Uuid::parse_str(import_id).context("Invalid import id")?,
+        None => {
+            let list = options.get("list").map_or(DEFAULT_LIST, String::as_str);
+            let list_id = get_list_id(&app_state.pool, list)
+                .await?
+                .ok_or_else(|| anyhow!("{} is not a known list", list))?;
+            let options = ImportOptions {
+                format: parse_option(&options, "format")?,
+                status: parse_option(&options, "status")?,
+                consent_source: options.get("consent-source").cloned(),
+                list_id,
+            };
+            create_import(&app_state.pool, &options, None).await?
+        }
+    };
+    eprintln!("Importing {} as import {}", path, import_id);
+
+    let file = File::open(path)
+        .await
+        .with_context(|| format!("Failed to open {}", path))?;
+    let report = run_import(
+        &app_state.pool,
+        &app_state.email_policy,
+        &app_state.hmac_secret,
+        import_id,
+        BufReader::new(file),
+    )
+    .await?
+    .ok_or_else(|| anyhow!("Import {} not found", import_id))?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
+
+async fn export(args: &[String]) -> Result<(), anyhow::Error> {
+    let (options, paths) = parse_arguments(args)?;
+    if !paths.is_empty() {
+        return Err(anyhow!(USAGE));
+    }
+
+    let configuration = get_configuration().context("Failed to read configuration")?;
+    let app_state = get_app_state(&configuration).await;
+    let pool = app_state.pool;
+
+    let format = match options.get("format") {
+        Some(_) => parse_option(&options, "format")?,
+        None => ExportFormat::Csv,
+    };
+    let list_id = match options.get("list") {
+        Some(list) => Some(
+            get_list_id(&pool, list)
+                .await?
+                .ok_or_else(|| anyhow!("{} is not a known list", list))?,
+        ),
+        None => None,
+    };
+    let segment = match options.get("segment") {
+        Some(name) => {
+            let query = get_segment_query(&pool, name)
+                .await?
+                .ok_or_else(|| anyhow!("{} is not a known segment", name))?;
+            Some(Segment::parse(&query, &get_field_types(&pool).await?)?)
+        }
+        None => None,
+    };
+    let filter = ExportFilter {
+        status: options.get("status").cloned(),
+        list_id,
+        segment,
+    };
+
+    let mut output: Box<dyn AsyncWrite + Unpin> = match options.get("output") {
+        Some(path) => Box::new(
+            File::create(path)
+                .await
+                .with_context(|| format!("Failed to create {}", path))?,
+        ),
+        None => Box::new(tokio::io::stdout()),
+    };
+    let mut lines = std::pin::pin!(export_subscribers(pool, format, filter));
+    while let Some(line) = lines.try_next().await? {
+        output.write_all(line.as_bytes()).await?;
+    }
+    output.flush().await?;
+
+    Ok(())
+}
+
+async fn normalise_emails(args: &[String]) -> Result<(), anyhow::Error> {
+    let (options, paths) = parse_arguments(args)?;
+    if !paths.is_empty() {
+        return Err(anyhow!(USAGE));
+    }
+    let resolution = match options.get("duplicates") {
+        Some(_) => parse_option(&options, "duplicates")?,
+        None => DuplicateResolution::Report,
+    };
+
+    let configuration = get_configuration().context("Failed to read configuration")?;
+    let app_state = get_app_state(&configuration).await;
+
+    let report =
+        normalise_subscriber_emails(&app_state.pool, &app_state.email_policy, resolution).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
+
+fn parse_arguments(
+    args: &[String],
+) -> Result<(HashMap<String, String>, Vec<String>), anyhow::Error> {
+    let mut options = HashMap::new();
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --{}\n\n{}", name, USAGE))?;
+                options.insert(name.to_string(), value.clone());
+            }
+            None => positional.push(arg.clone()),
+        }
+    }
+
+    Ok((options, positional))
+}
+
+fn parse_option<T: serde::de::DeserializeOwned>(
+    options: &HashMap<String, String>,
+    name: &str,
+) -> Result<T, anyhow::Error> {
+    let value = options
+        .get(name)
+        .ok_or_else(|| anyhow!("Missing --{}\n\n{}", name, USAGE))?;
+
+    serde_json::from_value(serde_json::Value::String(value.clone()))
+        .with_context(|| format!("Invalid value for --{}: {}", name, value))
+}
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgExecutor;
+
+use crate::configuration::BotProtectionSettings;
+use crate::consent::ClientInfo;
+use crate::signature::{sign, verify};
+use crate::startup::HmacSecret;
+use crate::suppression::email_hash;
+
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    Honeypot,
+    MissingFormToken,
+    InvalidFormToken,
+    ReusedFormToken,
+    SubmittedTooQuickly,
+    FormExpired,
+    ChallengeFailed,
+    RateLimited(&'static str),
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::Honeypot => write!(f, "honeypot field was filled in"),
+            Rejection::MissingFormToken => write!(f, "form token is missing"),
+            Rejection::InvalidFormToken => write!(f, "form token is invalid"),
+            Rejection::ReusedFormToken => write!(f, "form token was already used"),
+            Rejection::SubmittedTooQuickly => write!(f, "form was submitted too quickly"),
+            Rejection::FormExpired => write!(f, "form token has expired"),
+            Rejection::ChallengeFailed => write!(f, "challenge was not solved"),
+            Rejection::RateLimited(key) => write!(f, "too many submissions per {}", key),
+        }
+    }
+}
+
+#[async_trait]
+pub trait ChallengeVerifier: Send + Sync {
+    async fn verify(&self, response: &str, ip_address: Option<&str>)
+        -> Result<bool, anyhow::Error>;
+}
+
+// Speaks the `siteverify` protocol shared by Turnstile, hCaptcha and reCAPTCHA.
+pub struct SiteVerifyChallenge {
+    http_client: Client,
+    verify_url: String,
+    secret: Secret<String>,
+}
+
+impl SiteVerifyChallenge {
+    pub fn new(verify_url: String, secret: Secret<String>, timeout: Duration) -> Self {
+        Self {
+            http_client: Client::builder().timeout(timeout).build().unwrap(),
+            verify_url,
+            secret,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+#[async_trait]
+impl ChallengeVerifier for SiteVerifyChallenge {
+    async fn verify(
+        &self,
+        response: &str,
+        ip_address: Option<&str>,
+    ) -> Result<bool, anyhow::Error> {
+        let mut form = vec![
+            ("secret", self.secret.expose_secret().as_str()),
+            ("response", response),
+        ];
+        if let Some(ip_address) = ip_address {
+            form.push(("remoteip", ip_address));
+        }
+
+        let verification: SiteVerifyResponse = self
+            .http_client
+            .post(&self.verify_url)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(verification.success)
+    }
+}
+
+#[derive(Clone)]
+pub struct BotProtection {
+    pub hmac_secret: HmacSecret,
+    pub honeypot_field: String,
+    pub min_submit_time: chrono::Duration,
+    pub max_form_age: chrono::Duration,
+    pub rate_limit_window_in_seconds: i64,
+    pub max_attempts_per_ip: i64,
+    pub max_attempts_per_domain: i64,
+    pub max_emails_per_recipient: i64,
+    pub challenge_response_field: String,
+    pub challenge_verifier: Option<Arc<dyn ChallengeVerifier>>,
+}
+
+impl BotProtection {
+    pub fn from_settings(settings: &BotProtectionSettings, hmac_secret: HmacSecret) -> Self {
+        let challenge_verifier = settings.challenge.as_ref().map(|challenge| {
+            Arc::new(SiteVerifyChallenge::new(
+                challenge.verify_url.clone(),
+                challenge.secret.clone(),
+                Duration::from_millis(challenge.timeout_in_milliseconds),
+            )) as Arc<dyn ChallengeVerifier>
+        });
+
+        Self {
+            hmac_secret,
+            honeypot_field: settings.honeypot_field.clone(),
+            min_submit_time: chrono::Duration::seconds(settings.min_submit_time_in_seconds),
+            max_form_age: chrono::Duration::seconds(settings.max_form_age_in_seconds),
+            rate_limit_window_in_seconds: settings.rate_limit_window_in_seconds,
+            max_attempts_per_ip: settings.max_attempts_per_ip,
+            max_attempts_per_domain: settings.max_attempts_per_domain,
+            max_emails_per_recipient: settings.max_emails_per_recipient,
+            challenge_response_field: settings
+                .challenge
+                .as_ref()
+                .map(|challenge| challenge.response_field.clone())
+                .unwrap_or_default(),
+            challenge_verifier,
+        }
+    }
+
+    // Honeypot, form token and challenge. The form token is not spent here but by
+    // `spend_form_token` once the submission is valid, so that a submission rejected for a
+    // typo can be corrected.
+    pub async fn check_form(
+        &self,
+        form_token: Option<&str>,
+        fields: &HashMap<String, String>,
+        client: &ClientInfo,
+    ) -> Result<Option<Rejection>, anyhow::Error> {
+        if fields
+            .get(&self.honeypot_field)
+            .is_some_and(|value| !value.is_empty())
+        {
+            return Ok(Some(Rejection::Honeypot));
+        }
+
+        let form_token = match form_token {
+            Some(form_token) => form_token,
+            None => return Ok(Some(Rejection::MissingFormToken)),
+        };
+        if let Err(rejection) = verify_form_token(
+            &self.hmac_secret,
+            form_token,
+            Utc::now(),
+            self.min_submit_time,
+            self.max_form_age,
+        ) {
+            return Ok(Some(rejection));
+        }
+
+        if let Some(challenge_verifier) = &self.challenge_verifier {
+            let response = fields
+                .get(&self.challenge_response_field)
+                .map(String::as_str)
+                .unwrap_or_default();
+            if response.is_empty()
+                || !challenge_verifier
+                    .verify(response, client.ip_address.as_deref())
+                    .await?
+            {
+                return Ok(Some(Rejection::ChallengeFailed));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Makes every form token single use. Only tokens which passed `check_form` are spent.
+    pub async fn spend_form_token(
+        &self,
+        executor: impl PgExecutor<'_> + Copy,
+        form_token: Option<&str>,
+    ) -> Result<Option<Rejection>, sqlx::Error> {
+        let form_token = match form_token {
+            Some(form_token) => form_token,
+            None => return Ok(Some(Rejection::MissingFormToken)),
+        };
+        let (issued_at, nonce) = match parse_form_token(&self.hmac_secret, form_token) {
+            Ok(parsed) => parsed,
+            Err(rejection) => return Ok(Some(rejection)),
+        };
+
+        let spent =
+            record_spent_nonce(executor, &nonce, issued_at + self.max_form_age, Utc::now()).await?;
+        if !spent {
+            return Ok(Some(Rejection::ReusedFormToken));
+        }
+
+        Ok(None)
+    }
+
+    // Every address is limited per IP address. Email domains are only limited while nobody
+    // on them has confirmed a subscription, which throttles mail-bombing a single
+    // organisation without locking out the readers of large providers.
+    pub async fn check_rate_limits(
+        &self,
+        executor: impl PgExecutor<'_> + Copy,
+        client: &ClientInfo,
+        normalised_email: &str,
+    ) -> Result<Option<Rejection>, sqlx::Error> {
+        if let Some(ip_address) = &client.ip_address {
+            let attempts = record_attempt(
+                executor,
+                &format!("ip:{}", ip_address),
+                self.rate_limit_window_in_seconds,
+            )
+            .await?;
+            if attempts > self.max_attempts_per_ip {
+                return Ok(Some(Rejection::RateLimited("IP address")));
+            }
+        }
+
+        let domain = normalised_email
+            .rsplit_once('@')
+            .map_or(normalised_email, |(_, domain)| domain);
+        if has_confirmed_subscribers(executor, domain).await? {
+            return Ok(None);
+        }
+        let attempts = record_attempt(
+            executor,
+            &format!("domain:{}", domain),
+            self.rate_limit_window_in_seconds,
+        )
+        .await?;
+        if attempts > self.max_attempts_per_domain {
+            return Ok(Some(Rejection::RateLimited("email domain")));
+        }
+
+        Ok(None)
+    }
+
+    // Caps the emails a single address receives however many clients ask for them. The
+    // address is hashed, so that the rate limits do not hold it in clear text.
+    pub async fn check_recipient_limit(
+        &self,
+        executor: impl PgExecutor<'_> + Copy,
+        normalised_email: &str,
+    ) -> Result<Option<Rejection>, sqlx::Error> {
+        let attempts = record_attempt(
+            executor,
+            &format!(
+                "recipient:{}",
+                email_hash(&self.hmac_secret, normalised_email)
+            ),
+            self.rate_limit_window_in_seconds,
+        )
+        .await?;
+        if attempts > self.max_emails_per_recipient {
+            return Ok(Some(Rejection::RateLimited("recipient")));
+        }
+
+        Ok(None)
+    }
+}
+
+// The nonce makes every token single use, see `BotProtection::spend_form_token`.
+pub fn form_token(secret: &HmacSecret, issued_at: DateTime<Utc>) -> String {
+    let nonce: String = thread_rng()
+        .sample_iter(Alphanumeric)
+        .map(char::from)
+        .take(16)
+        .collect();
+
+    sign(secret, &format!("form:{}:{}", issued_at.timestamp(), nonce))
+}
+
+// Returns when the token was issued and its nonce.
+pub fn verify_form_token(
+    secret: &HmacSecret,
+    token: &str,
+    now: DateTime<Utc>,
+    min_submit_time: chrono::Duration,
+    max_form_age: chrono::Duration,
+) -> Result<(DateTime<Utc>, String), Rejection> {
+    let (issued_at, nonce) = parse_form_token(secret, token)?;
+
+    if now - issued_at < min_submit_time {
+        return Err(Rejection::SubmittedTooQuickly);
+    }
+    if now - issued_at > max_form_age {
+        return Err(Rejection::FormExpired);
+    }
+
+    Ok((issued_at, nonce))
+}
+
+fn parse_form_token(
+    secret: &HmacSecret,
+    token: &str,
+) -> Result<(DateTime<Utc>, String), Rejection> {
+    verify(secret, token)
+        .ok()
+        .and_then(|payload| {
+            let (timestamp, nonce) = payload.strip_prefix("form:")?.split_once(':')?;
+            let issued_at = DateTime::from_timestamp(timestamp.parse().ok()?, 0)?;
+            Some((issued_at, nonce.to_string()))
+        })
+        .ok_or(Rejection::InvalidFormToken)
+}
+
+// Returns false when the nonce was spent before. Nonces are kept until their token would
+// have expired anyway.
+#[tracing::instrument(name = "Spend a form token", skip(executor))]
+async fn record_spent_nonce(
+    executor: impl PgExecutor<'_> + Copy,
+    nonce: &str,
+    expires_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query!("DELETE FROM spent_form_tokens WHERE expires_at < $1", now)
+        .execute(executor)
+        .await?;
+    let result = sqlx::query!(
+        r#"
+            INSERT INTO spent_form_tokens (nonce, expires_at)
+            VALUES ($1, $2)
+            ON CONFLICT (nonce) DO NOTHING
+        "#,
+        nonce,
+        expires_at,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Check for confirmed subscribers on a domain", skip(executor))]
+async fn has_confirmed_subscribers(
+    executor: impl PgExecutor<'_>,
+    domain: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT EXISTS (
+                SELECT 1 FROM subscriptions
+                WHERE split_part(normalised_email, '@', 2) = $1 AND status = 'confirmed'
+            ) AS "exists!"
+        "#,
+        domain,
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(row.exists)
+}
+
+// Fixed-window counter: returns the number of attempts for `key` in the current window.
+#[tracing::instrument(name = "Record a rate limited attempt", skip(executor))]
+async fn record_attempt(
+    executor: impl PgExecutor<'_> + Copy,
+    key: &str,
+    window_in_seconds: i64,
+) -> Result<i64, sqlx::Error> {
+    let now = Utc::now().timestamp();
+    let window_start = DateTime::from_timestamp(now - now.rem_euclid(window_in_seconds), 0)
+        .expect("The current time is a valid timestamp");
+
+    // Stale windows of every key are purged, not only those of `key`, so that addresses
+    // which are never seen again do not stay around.
+    sqlx::query!(
+        "DELETE FROM rate_limits WHERE window_start < $1",
+        window_start,
+    )
+    .execute(executor)
+    .await?;
+    let row = sqlx::query!(
+        r#"
+            INSERT INTO rate_limits (key, window_start, attempts)
+            VALUES ($1, $2, 1)
+            ON CONFLICT (key, window_start) DO UPDATE SET attempts = rate_limits.attempts + 1
+            RETURNING attempts
+        "#,
+        key,
+        window_start,
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(row.attempts)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use claims::{assert_err_eq, assert_ok};
+    use secrecy::Secret;
+
+    use crate::bot_protection::*;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("super-secret".into()))
+    }
+
+    fn check(issued_seconds_ago: i64) -> Result<(DateTime<Utc>, String), Rejection> {
+        let now = Utc::now();
+        let token = form_token(&secret(), now - Duration::seconds(issued_seconds_ago));
+        verify_form_token(
+            &secret(),
+            &token,
+            now,
+            Duration::seconds(3),
+            Duration::hours(1),
+        )
+    }
+
+    #[test]
+    fn forms_submitted_within_the_allowed_time_are_accepted() {
+        assert_ok!(check(10));
+    }
+
+    #[test]
+    fn forms_submitted_too_quickly_are_rejected() {
+        assert_err_eq!(check(1), Rejection::SubmittedTooQuickly);
+    }
+
+    #[test]
+    fn stale_forms_are_rejected() {
+        assert_err_eq!(check(7200), Rejection::FormExpired);
+    }
+
+    #[test]
+    fn every_form_token_has_its_own_nonce() {
+        let issued_at = Utc::now() - Duration::seconds(10);
+        let verify = |token: &str| {
+            verify_form_token(
+                &secret(),
+                token,
+                Utc::now(),
+                Duration::seconds(3),
+                Duration::hours(1),
+            )
+            .unwrap()
+        };
+
+        let first = verify(&form_token(&secret(), issued_at));
+        let second = verify(&form_token(&secret(), issued_at));
+
+        assert_eq!(first.0, second.0);
+        assert_ne!(first.1, second.1);
+    }
+
+    #[test]
+    fn forged_form_tokens_are_rejected() {
+        let token = form_token(
+            &HmacSecret(Secret::new("another-secret".into())),
+            Utc::now() - Duration::seconds(10),
+        );
+        assert_err_eq!(
+            verify_form_token(
+                &secret(),
+                &token,
+                Utc::now(),
+                Duration::seconds(3),
+                Duration::hours(1)
+            ),
+            Rejection::InvalidFormToken
+        );
+    }
+}
@@ -1,17 +1,38 @@
+use std::path::Path;
 use std::time::Duration;
 
-use config::{Config, Environment, File, FileFormat};
+use config::builder::DefaultState;
+use config::{Config, ConfigBuilder, File};
+use ipnet::IpNet;
+use reqwest::Url;
 use secrecy::{ExposeSecret, Secret};
-use serde::Deserialize;
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde::{Deserialize, Deserializer};
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
 
+use crate::deliverability::DeliverabilityMode;
 use crate::domain::SubscriberEmail;
+use crate::email_policy::LocalPartCase;
+use crate::issue_delivery::ISSUE_HEARTBEAT_INTERVAL;
+use crate::telemetry::OtlpProtocol;
 
 #[derive(Deserialize, Debug)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub scheduler: SchedulerSettings,
+    pub webhooks: WebhookSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub email_validation: EmailValidationSettings,
+    pub deliverability_check: DeliverabilitySettings,
+    pub health: HealthSettings,
+    #[serde(default)]
+    pub metrics: MetricsSettings,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
 }
 
 #[derive(Deserialize, Debug)]
@@ -22,50 +43,383 @@ pub struct DatabaseSettings {
     pub username: String,
     pub password: Secret<String>,
     pub database: String,
+    // One of disable, allow, prefer, require, verify-ca or verify-full.
+    #[serde(deserialize_with = "deserialize_ssl_mode")]
+    pub ssl_mode: PgSslMode,
+    pub ssl_root_cert: Option<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_connections: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_connections: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub acquire_timeout_in_milliseconds: u64,
+    pub idle_timeout_in_seconds: Option<u64>,
+    pub max_lifetime_in_seconds: Option<u64>,
+    pub statement_timeout_in_milliseconds: Option<u64>,
 }
 
 impl DatabaseSettings {
-    pub fn connection_string(&self) -> Secret<String> {
-        Secret::new(format!(
-            "postgres://{}:{}@{}:{}/{}",
-            self.username,
-            self.password.expose_secret(),
-            self.host,
-            self.port,
-            self.database,
-        ))
+    pub fn without_db(&self) -> PgConnectOptions {
+        let mut options = PgConnectOptions::new()
+            .host(&self.host)
+            .port(self.port)
+            .username(&self.username)
+            .password(self.password.expose_secret())
+            .ssl_mode(self.ssl_mode);
+        if let Some(ssl_root_cert) = &self.ssl_root_cert {
+            options = options.ssl_root_cert(ssl_root_cert);
+        }
+        if let Some(statement_timeout) = self.statement_timeout_in_milliseconds {
+            options = options.options([("statement_timeout", statement_timeout.to_string())]);
+        }
+        options
     }
 
-    pub fn connection_string_without_database(&self) -> Secret<String> {
-        Secret::new(format!(
-            "postgres://{}:{}@{}:{}",
-            self.username,
-            self.password.expose_secret(),
-            self.host,
-            self.port,
-        ))
+    pub fn with_db(&self) -> PgConnectOptions {
+        self.without_db().database(&self.database)
+    }
+
+    pub fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(Duration::from_millis(self.acquire_timeout_in_milliseconds))
+            .idle_timeout(self.idle_timeout_in_seconds.map(Duration::from_secs))
+            .max_lifetime(self.max_lifetime_in_seconds.map(Duration::from_secs))
     }
 }
 
+fn deserialize_ssl_mode<'de, D>(deserializer: D) -> Result<PgSslMode, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ApplicationSettings {
     pub host: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub access_url: String,
+    pub hmac_secret: Secret<String>,
+    pub consent_statement: String,
+    pub run_migrations: bool,
+    // How long in-flight requests and workers get to finish after SIGTERM or SIGINT.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_timeout_in_seconds: u64,
+    // Networks of the reverse proxies whose `X-Forwarded-For` is believed, e.g. `10.0.0.0/8`.
+    // A comma separated list in `APP_APPLICATION__TRUSTED_PROXIES`.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
 }
 
-pub fn get_configuration() -> Result<Settings, config::ConfigError> {
-    let settings = Config::builder()
-        .add_source(File::new("configuration.yaml", FileFormat::Yaml))
-        .add_source(
-            Environment::with_prefix("APP")
-                .prefix_separator("_")
-                .separator("_"),
-        )
-        .build()?;
+impl ApplicationSettings {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_in_seconds)
+    }
+}
+
+pub enum Environment {
+    Local,
+    Production,
+}
+
+impl Environment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Environment::Local => "local",
+            Environment::Production => "production",
+        }
+    }
+}
+
+impl TryFrom<String> for Environment {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(Self::Local),
+            "production" => Ok(Self::Production),
+            other => Err(format!(
+                "{} is not a supported environment. Use either `local` or `production`",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigurationError {
+    #[error("Invalid configuration:\n  {}", .0.join("\n  "))]
+    Invalid(Vec<String>),
+}
+
+// `configuration/base.yaml` is layered with the file of the environment selected by
+// `APP_ENVIRONMENT` and then with `APP_` environment variables, where `__` separates
+// nested keys, e.g. `APP_DATABASE__PORT=5433`. Appending `_FILE` reads the value from a
+// file instead, e.g. `APP_DATABASE__PASSWORD_FILE=/run/secrets/database_password`. An
+// `APP_` variable which matches no setting is an error.
+pub fn get_configuration() -> Result<Settings, ConfigurationError> {
+    let environment: Environment = std::env::var("APP_ENVIRONMENT")
+        .unwrap_or_else(|_| "local".into())
+        .try_into()
+        .map_err(|error| ConfigurationError::Invalid(vec![error]))?;
+    let directory = Path::new("configuration");
+
+    let mut builder = Config::builder()
+        .add_source(File::from(directory.join("base.yaml")))
+        .add_source(File::from(
+            directory.join(format!("{}.yaml", environment.as_str())),
+        ))
+        .add_source(environment_variables());
+    let mut errors = vec![];
+    let variables: Vec<(String, String)> = std::env::vars().collect();
+    for (key, path) in secret_files(variables.iter().cloned()) {
+        match std::fs::read_to_string(&path) {
+            Ok(secret) => match builder.clone().set_override(&key, secret.trim_end()) {
+                Ok(overridden) => builder = overridden,
+                Err(error) => errors.push(format!("{}: {}", key, error)),
+            },
+            Err(error) => errors.push(format!("{}: failed to read {}: {}", key, path, error)),
+        }
+    }
+
+    build_settings(builder, errors, &variables)
+}
+
+fn environment_variables() -> config::Environment {
+    config::Environment::with_prefix("APP")
+        .prefix_separator("_")
+        .separator("__")
+        .try_parsing(true)
+        .list_separator(",")
+        .with_list_parse_key("application.trusted_proxies")
+}
+
+// Errors found while reading the files, e.g. a missing file or a value of the wrong type,
+// are reported together with `errors`, the variables which match no setting and the
+// validation errors.
+fn build_settings(
+    builder: ConfigBuilder<DefaultState>,
+    mut errors: Vec<String>,
+    variables: &[(String, String)],
+) -> Result<Settings, ConfigurationError> {
+    let mut ignored = vec![];
+    let settings = match builder.build() {
+        Ok(config) => {
+            let mut ignore = |path: serde_ignored::Path| {
+                ignored.push(path.to_string().replace(".?", ""));
+            };
+            serde_path_to_error::deserialize::<_, Settings>(serde_ignored::Deserializer::new(
+                config,
+                &mut ignore,
+            ))
+            .map_err(|error| format!("{}: {}", error.path(), error.inner()))
+        }
+        Err(error) => Err(error.to_string()),
+    };
+
+    match settings {
+        Ok(settings) => {
+            errors.extend(unknown_variables(variables, &ignored));
+            errors.extend(settings.validate());
+            match errors.is_empty() {
+                true => Ok(settings),
+                false => Err(ConfigurationError::Invalid(errors)),
+            }
+        }
+        Err(error) => {
+            errors.push(error);
+            Err(ConfigurationError::Invalid(errors))
+        }
+    }
+}
+
+// Maps `APP_DATABASE__PASSWORD_FILE=<path>` to the key `database.password` and the path.
+fn secret_files(variables: impl Iterator<Item = (String, String)>) -> Vec<(String, String)> {
+    variables
+        .filter_map(|(name, path)| {
+            let key = name.strip_prefix("APP_")?.strip_suffix("_FILE")?;
+            Some((key.to_lowercase().replace("__", "."), path))
+        })
+        .collect()
+}
 
-    settings.try_deserialize::<Settings>()
+// Catches e.g. `APP_DATABASE_PORT`, written with the single `_` that separated nested keys
+// before `__` did, which would otherwise be ignored silently.
+fn unknown_variables(variables: &[(String, String)], ignored: &[String]) -> Vec<String> {
+    variables
+        .iter()
+        .filter(|(name, _)| name != "APP_ENVIRONMENT")
+        .filter_map(|(name, _)| {
+            let key = name.strip_prefix("APP_")?;
+            let key = key.strip_suffix("_FILE").unwrap_or(key);
+            let key = key.to_lowercase().replace("__", ".");
+            ignored
+                .iter()
+                .any(|path| key == *path || key.starts_with(&format!("{}.", path)))
+                .then(|| {
+                    format!(
+                        "{}: matches no setting, nested keys are separated by __",
+                        name
+                    )
+                })
+        })
+        .collect()
+}
+
+impl Settings {
+    // Reports every invalid value at once rather than failing on the first one.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+        let mut check = |valid: bool, key: &str, message: &str| {
+            if !valid {
+                errors.push(format!("{}: {}", key, message));
+            }
+        };
+
+        check(
+            self.application.port != 0,
+            "application.port",
+            "must not be 0",
+        );
+        if let Some(port) = self.metrics.port {
+            check(
+                port != 0 && port != self.application.port,
+                "metrics.port",
+                "must not be 0 or the application port",
+            );
+        }
+        if let Some(otlp) = &self.telemetry.otlp {
+            check(
+                is_http_url(&otlp.endpoint),
+                "telemetry.otlp.endpoint",
+                "must be an http(s) URL",
+            );
+            check(
+                otlp.timeout_in_milliseconds > 0,
+                "telemetry.otlp.timeout_in_milliseconds",
+                "must be positive",
+            );
+        }
+        check(
+            self.application.shutdown_timeout_in_seconds > 0,
+            "application.shutdown_timeout_in_seconds",
+            "must be positive",
+        );
+        check(
+            is_http_url(&self.application.access_url),
+            "application.access_url",
+            "must be an http(s) URL",
+        );
+        check(
+            self.application.hmac_secret.expose_secret().len() >= 32,
+            "application.hmac_secret",
+            "must be at least 32 characters long",
+        );
+        check(self.database.port != 0, "database.port", "must not be 0");
+        check(
+            self.database.max_connections > 0,
+            "database.max_connections",
+            "must be positive",
+        );
+        check(
+            self.database.min_connections <= self.database.max_connections,
+            "database.min_connections",
+            "must not exceed database.max_connections",
+        );
+        check(
+            self.database.acquire_timeout_in_milliseconds > 0,
+            "database.acquire_timeout_in_milliseconds",
+            "must be positive",
+        );
+        if let Some(ssl_root_cert) = &self.database.ssl_root_cert {
+            check(
+                Path::new(ssl_root_cert).is_file(),
+                "database.ssl_root_cert",
+                "must be an existing file",
+            );
+        }
+        check(
+            is_http_url(&self.email_client.access_url),
+            "email_client.access_url",
+            "must be an http(s) URL",
+        );
+        check(
+            self.email_client.sender().is_ok(),
+            "email_client.sender_email",
+            "must be a valid email address",
+        );
+        check(
+            self.email_client.timeout_in_milliseconds > 0,
+            "email_client.timeout_in_milliseconds",
+            "must be positive",
+        );
+        check(
+            self.scheduler.poll_interval_in_milliseconds > 0,
+            "scheduler.poll_interval_in_milliseconds",
+            "must be positive",
+        );
+        // Workers touch the issue they deliver every `ISSUE_HEARTBEAT_INTERVAL`.
+        check(
+            self.scheduler.issue_lease() >= 3 * ISSUE_HEARTBEAT_INTERVAL,
+            "scheduler.issue_lease_in_seconds",
+            "must be at least 30",
+        );
+        check(
+            self.health.timeout_in_milliseconds > 0,
+            "health.timeout_in_milliseconds",
+            "must be positive",
+        );
+        check(
+            self.health.worker_heartbeat_timeout_in_seconds > 0,
+            "health.worker_heartbeat_timeout_in_seconds",
+            "must be positive",
+        );
+        check(
+            self.webhooks.soft_bounce_window_in_days > 0,
+            "webhooks.soft_bounce_window_in_days",
+            "must be positive",
+        );
+        check(
+            self.bot_protection.rate_limit_window_in_seconds > 0,
+            "bot_protection.rate_limit_window_in_seconds",
+            "must be positive",
+        );
+        if let Some(challenge) = &self.bot_protection.challenge {
+            check(
+                is_http_url(&challenge.verify_url),
+                "bot_protection.challenge.verify_url",
+                "must be an http(s) URL",
+            );
+            check(
+                challenge.timeout_in_milliseconds > 0,
+                "bot_protection.challenge.timeout_in_milliseconds",
+                "must be positive",
+            );
+        }
+        if let Some(path) = &self.email_validation.disposable_domains_path {
+            check(
+                Path::new(path).is_file(),
+                "email_validation.disposable_domains_path",
+                "must be an existing file",
+            );
+        }
+        check(
+            self.deliverability_check.timeout_in_milliseconds > 0,
+            "deliverability_check.timeout_in_milliseconds",
+            "must be positive",
+        );
+
+        errors
+    }
+}
+
+fn is_http_url(s: &str) -> bool {
+    Url::parse(s).is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
 }
 
 #[derive(Deserialize, Debug)]
@@ -86,3 +440,269 @@ impl EmailClientSettings {
         Duration::from_millis(self.timeout_in_milliseconds)
     }
 }
+
+#[derive(Deserialize, Debug)]
+pub struct SchedulerSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_in_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub issue_lease_in_seconds: u64,
+}
+
+impl SchedulerSettings {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_in_milliseconds)
+    }
+
+    pub fn issue_lease(&self) -> Duration {
+        Duration::from_secs(self.issue_lease_in_seconds)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub soft_bounce_threshold: i64,
+    // Only soft bounces within this many days count towards the threshold.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub soft_bounce_window_in_days: i64,
+}
+
+impl WebhookSettings {
+    pub fn soft_bounce_window(&self) -> chrono::Duration {
+        chrono::Duration::days(self.soft_bounce_window_in_days)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BotProtectionSettings {
+    pub honeypot_field: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_submit_time_in_seconds: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_form_age_in_seconds: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub rate_limit_window_in_seconds: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts_per_ip: i64,
+    // Only applies to email domains without confirmed subscribers.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts_per_domain: i64,
+    // How many data request emails a single address receives per window.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_emails_per_recipient: i64,
+    pub challenge: Option<ChallengeSettings>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChallengeSettings {
+    pub verify_url: String,
+    pub secret: Secret<String>,
+    pub response_field: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_in_milliseconds: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EmailValidationSettings {
+    pub local_part_case: LocalPartCase,
+    pub disposable_domains_path: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeliverabilitySettings {
+    pub mode: DeliverabilityMode,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_in_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cache_ttl_in_seconds: u64,
+}
+
+impl DeliverabilitySettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_in_milliseconds)
+    }
+
+    pub fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl_in_seconds)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HealthSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_in_milliseconds: u64,
+    // How long the background workers may go without a heartbeat before the application
+    // stops reporting itself as ready.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub worker_heartbeat_timeout_in_seconds: u64,
+}
+
+impl HealthSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_in_milliseconds)
+    }
+
+    pub fn worker_heartbeat_timeout(&self) -> Duration {
+        Duration::from_secs(self.worker_heartbeat_timeout_in_seconds)
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct MetricsSettings {
+    // `/metrics` is served on the application port unless a separate port is set.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub port: Option<u16>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct TelemetrySettings {
+    // Spans are only exported when a collector is configured.
+    pub otlp: Option<OtlpSettings>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OtlpSettings {
+    pub protocol: OtlpProtocol,
+    pub endpoint: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_in_milliseconds: u64,
+}
+
+impl OtlpSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_in_milliseconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::*;
+
+    fn settings() -> Settings {
+        Config::builder()
+            .add_source(File::from(Path::new("configuration/base.yaml")))
+            .add_source(File::from(Path::new("configuration/local.yaml")))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn the_local_configuration_is_valid() {
+        assert_eq!(settings().validate(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_invalid_value_is_reported() {
+        let mut settings = settings();
+        settings.application.access_url = "127.0.0.1".into();
+        settings.email_client.sender_email = "not-an-email".into();
+        settings.email_client.timeout_in_milliseconds = 0;
+
+        let errors = settings.validate();
+
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].starts_with("application.access_url"));
+        assert!(errors[1].starts_with("email_client.sender_email"));
+        assert!(errors[2].starts_with("email_client.timeout_in_milliseconds"));
+    }
+
+    #[test]
+    fn load_errors_are_reported_with_the_other_errors() {
+        let builder = Config::builder()
+            .add_source(File::from(Path::new("configuration/base.yaml")))
+            .add_source(File::from(Path::new("configuration/local.yaml")))
+            .set_override("application.port", "not-a-port")
+            .unwrap();
+
+        let error = build_settings(
+            builder,
+            vec!["database.password: failed to read".into()],
+            &[],
+        )
+        .err()
+        .unwrap();
+
+        let ConfigurationError::Invalid(errors) = error;
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].starts_with("database.password"));
+        assert!(errors[1].starts_with("application.port"), "{}", errors[1]);
+    }
+
+    #[test]
+    fn only_the_connect_options_with_db_select_the_database() {
+        let settings = settings();
+
+        assert_eq!(
+            settings.database.with_db().get_database(),
+            Some("newsletter")
+        );
+        assert_eq!(settings.database.without_db().get_database(), None);
+    }
+
+    #[test]
+    fn unknown_ssl_modes_are_rejected() {
+        let result = Config::builder()
+            .add_source(File::from(Path::new("configuration/base.yaml")))
+            .add_source(File::from(Path::new("configuration/local.yaml")))
+            .set_override("database.ssl_mode", "sometimes")
+            .unwrap()
+            .build()
+            .unwrap()
+            .try_deserialize::<Settings>();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn file_variables_are_mapped_to_configuration_keys() {
+        let variables = [
+            ("APP_DATABASE__PASSWORD_FILE", "/run/secrets/db"),
+            ("APP_APPLICATION__HMAC_SECRET_FILE", "/run/secrets/hmac"),
+            ("APP_DATABASE__PASSWORD", "ignored"),
+            ("HOME_FILE", "ignored"),
+        ]
+        .map(|(name, path)| (name.to_string(), path.to_string()));
+
+        assert_eq!(
+            secret_files(variables.into_iter()),
+            vec![
+                (
+                    "database.password".to_string(),
+                    "/run/secrets/db".to_string()
+                ),
+                (
+                    "application.hmac_secret".to_string(),
+                    "/run/secrets/hmac".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_which_match_no_setting_are_reported() {
+        let variables = [
+            ("APP_ENVIRONMENT", "local"),
+            ("APP_DATABASE__PORT", "5433"),
+            ("APP_DATABASE_PORT", "5433"),
+            ("APP_APPLICATION__HOST", "0.0.0.0"),
+            ("APP_APPLICATION__UNKNOWN__KEY", "value"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let builder = Config::builder()
+            .add_source(File::from(Path::new("configuration/base.yaml")))
+            .add_source(File::from(Path::new("configuration/local.yaml")))
+            .add_source(environment_variables().source(Some(variables.iter().cloned().collect())));
+
+        let error = build_settings(builder, vec![], &variables).err().unwrap();
+
+        let ConfigurationError::Invalid(errors) = error;
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].starts_with("APP_DATABASE_PORT"), "{}", errors[0]);
+        assert!(errors[1].starts_with("APP_APPLICATION__UNKNOWN__KEY"));
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::Context;
+use sqlx::{PgExecutor, Pool, Postgres};
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::i18n::Locale;
+use crate::routes::send_confirmation_email;
+use crate::scheduler::ExecutionOutcome;
+use crate::startup::HmacSecret;
+use crate::suppression::is_suppressed;
+
+// A confirmation email which cannot be sent after this many attempts is dropped. The
+// subscriber can still ask for another one by subscribing again.
+const MAX_ATTEMPTS: i32 = 5;
+
+#[tracing::instrument(
+    name = "Enqueue a confirmation email",
+    skip(executor, subscription_token)
+)]
+pub async fn enqueue_confirmation_email(
+    executor: impl PgExecutor<'_>,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO confirmation_email_queue (subscription_token) VALUES ($1)",
+        subscription_token,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// The queued email stays locked while it is being sent, so replicas polling at the same
+// time never send it twice. Subscribers who have confirmed or been erased in the meantime
+// are passed over, and emails to addresses suppressed in the meantime are kept as skipped.
+#[tracing::instrument(name = "Send a queued confirmation email", skip_all, err)]
+pub async fn try_send_confirmation_email(
+    pool: &Pool<Postgres>,
+    email_client: &EmailClient,
+    access_url: &str,
+    hmac_secret: &HmacSecret,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a PostgreSQL connection from the pool")?;
+    let queued = match sqlx::query!(
+        r#"
+            SELECT q.subscription_token, q.attempts, s.email, s.normalised_email, s.status,
+                s.locale
+            FROM confirmation_email_queue q
+            JOIN subscription_tokens t ON t.subscription_token = q.subscription_token
+            JOIN subscriptions s ON s.id = t.subscriber_id
+            WHERE q.status = 'queued' AND q.send_after <= now()
+            ORDER BY q.send_after
+            FOR UPDATE OF q
+            SKIP LOCKED
+            LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve a queued confirmation email")?
+    {
+        Some(queued) => queued,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+
+    if is_suppressed(&mut *transaction, hmac_secret, &queued.normalised_email)
+        .await
+        .context("Failed to check the suppression list")?
+    {
+        sqlx::query!(
+            "UPDATE confirmation_email_queue SET status = 'skipped' WHERE subscription_token = $1",
+            queued.subscription_token,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to skip a confirmation email")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to skip a confirmation email")?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+
+    let dequeue = if queued.status != "pending_confirmation" {
+        true
+    } else {
+        match SubscriberEmail::parse(queued.email) {
+            Err(error) => {
+                tracing::warn!(
+                    error.cause_chain = ?error,
+                    "Dropping a confirmation email. The stored email address is invalid",
+                );
+                true
+            }
+            Ok(email) => match send_confirmation_email(
+                email_client,
+                access_url,
+                &email,
+                &queued.subscription_token,
+                Locale::parse(&queued.locale).unwrap_or_default(),
+            )
+            .await
+            {
+                Ok(()) => true,
+                Err(error) => {
+                    tracing::warn!(
+                        error.cause_chain = ?error,
+                        attempts = queued.attempts + 1,
+                        "Failed to send a queued confirmation email",
+                    );
+                    queued.attempts + 1 >= MAX_ATTEMPTS
+                }
+            },
+        }
+    };
+
+    if dequeue {
+        sqlx::query!(
+            "DELETE FROM confirmation_email_queue WHERE subscription_token = $1",
+            queued.subscription_token,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to dequeue a confirmation email")?;
+    } else {
+        // Back off a minute longer after every failed attempt.
+        sqlx::query!(
+            r#"
+                UPDATE confirmation_email_queue
+                SET attempts = attempts + 1,
+                    send_after = now() + make_interval(mins => attempts + 1)
+                WHERE subscription_token = $1
+            "#,
+            queued.subscription_token,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to retry a confirmation email later")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to send a confirmation email")?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
@@ -0,0 +1,211 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts};
+use axum::http::header::USER_AGENT;
+use axum::http::request::Parts;
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+// The reverse proxies in front of the application, whose `X-Forwarded-For` is believed.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpNet>);
+
+// The peer address is used unless it is a trusted proxy, in which case `X-Forwarded-For`
+// is walked from the right and the first hop that is not a trusted proxy is the client.
+// Anything left of that hop was written by the client and cannot be believed.
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+    TrustedProxies: FromRef<S>,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let TrustedProxies(trusted_proxies) = TrustedProxies::from_ref(state);
+        let peer_address = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        let forwarded_for = parts
+            .headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        Ok(ClientInfo {
+            ip_address: peer_address
+                .map(|peer_address| client_address(peer_address, &forwarded_for, &trusted_proxies))
+                .map(|address| address.to_string()),
+            user_agent,
+        })
+    }
+}
+
+fn client_address(peer_address: IpAddr, forwarded_for: &str, trusted_proxies: &[IpNet]) -> IpAddr {
+    let is_trusted = |address: &IpAddr| trusted_proxies.iter().any(|net| net.contains(address));
+
+    let mut client = peer_address;
+    for hop in forwarded_for.rsplit(',') {
+        if !is_trusted(&client) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(address) => client = address,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsentEvent {
+    Subscribed,
+    Imported,
+    Confirmed,
+}
+
+impl ConsentEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEvent::Subscribed => "subscribed",
+            ConsentEvent::Imported => "imported",
+            ConsentEvent::Confirmed => "confirmed",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConsentEvidence<'a> {
+    pub event: ConsentEvent,
+    pub source: &'a str,
+    pub client: &'a ClientInfo,
+    pub consent_text: Option<&'a str>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct ConsentRecord {
+    pub event: String,
+    pub source: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub consent_text: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Record consent evidence", skip(executor))]
+pub async fn record_consent(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    evidence: &ConsentEvidence<'_>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO consent_records (
+                subscriber_id, event, source, ip_address, user_agent, consent_text, recorded_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        subscriber_id,
+        evidence.event.as_str(),
+        evidence.source,
+        evidence.client.ip_address,
+        evidence.client.user_agent,
+        evidence.consent_text,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get consent evidence", skip(executor))]
+pub async fn get_consent_records(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+            SELECT event, source, ip_address, user_agent, consent_text, recorded_at
+            FROM consent_records
+            WHERE subscriber_id = $1
+            ORDER BY recorded_at, id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use crate::consent::*;
+
+    fn address(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn proxies() -> Vec<IpNet> {
+        vec!["10.0.0.0/8".parse().unwrap()]
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_peers() {
+        assert_eq!(
+            client_address(address("203.0.113.7"), "198.51.100.1", &proxies()),
+            address("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn the_right_most_untrusted_hop_is_the_client() {
+        assert_eq!(
+            client_address(
+                address("10.0.0.2"),
+                "198.51.100.1, 203.0.113.7, 10.0.0.1",
+                &proxies()
+            ),
+            address("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn a_trusted_peer_without_forwarded_for_is_the_client() {
+        assert_eq!(
+            client_address(address("10.0.0.2"), "", &proxies()),
+            address("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn walking_stops_at_a_malformed_hop() {
+        assert_eq!(
+            client_address(
+                address("10.0.0.2"),
+                "198.51.100.1, garbage, 10.0.0.1",
+                &proxies()
+            ),
+            address("10.0.0.1")
+        );
+    }
+}
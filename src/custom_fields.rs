@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Text,
+    Number,
+    Boolean,
+    Date,
+}
+
+impl FieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::Text => "text",
+            FieldType::Number => "number",
+            FieldType::Boolean => "boolean",
+            FieldType::Date => "date",
+        }
+    }
+
+    fn from_column(s: &str) -> Self {
+        match s {
+            "number" => FieldType::Number,
+            "boolean" => FieldType::Boolean,
+            "date" => FieldType::Date,
+            _ => FieldType::Text,
+        }
+    }
+
+    pub fn parse_str(&self, value: &str) -> Result<Value, String> {
+        let parsed = match self {
+            FieldType::Text => Some(Value::String(value.into())),
+            FieldType::Number => value
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number),
+            FieldType::Boolean => value.parse::<bool>().ok().map(Value::Bool),
+            FieldType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(|date| Value::String(date.to_string())),
+        };
+
+        parsed.ok_or_else(|| format!("{} is not a valid {}", value, self.as_str()))
+    }
+
+    pub fn parse_value(&self, value: &Value) -> Result<Value, String> {
+        match (self, value) {
+            (FieldType::Number, Value::Number(_)) | (FieldType::Boolean, Value::Bool(_)) => {
+                Ok(value.clone())
+            }
+            (_, Value::String(s)) => self.parse_str(s),
+            _ => Err(format!("{} is not a valid {}", value, self.as_str())),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CustomField {
+    pub name: String,
+    pub field_type: FieldType,
+    pub created_at: DateTime<Utc>,
+}
+
+pub fn is_valid_field_name(name: &str) -> bool {
+    let reserved = ["tag", "email", "subscribed_at", "and", "or", "not"];
+
+    name.len() <= 64
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !reserved.contains(&name)
+}
+
+// A `null` value removes the field from the subscriber.
+pub fn parse_custom_fields<'a>(
+    values: impl IntoIterator<Item = (&'a str, &'a Value)>,
+    field_types: &HashMap<String, FieldType>,
+) -> Result<Map<String, Value>, String> {
+    values
+        .into_iter()
+        .map(|(name, value)| {
+            let field_type = field_types
+                .get(name)
+                .ok_or_else(|| format!("{} is not a known custom field", name))?;
+            let value = match value {
+                Value::Null => Value::Null,
+                value => field_type.parse_value(value)?,
+            };
+            Ok((name.to_string(), value))
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "List custom fields", skip(executor))]
+pub async fn list_custom_fields(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<CustomField>, sqlx::Error> {
+    let rows = sqlx::query!("SELECT name, field_type, created_at FROM custom_fields ORDER BY name")
+        .fetch_all(executor)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| CustomField {
+            name: r.name,
+            field_type: FieldType::from_column(&r.field_type),
+            created_at: r.created_at,
+        })
+        .collect())
+}
+
+pub async fn get_field_types(
+    executor: impl PgExecutor<'_>,
+) -> Result<HashMap<String, FieldType>, sqlx::Error> {
+    Ok(list_custom_fields(executor)
+        .await?
+        .into_iter()
+        .map(|field| (field.name, field.field_type))
+        .collect())
+}
+
+#[tracing::instrument(name = "Create custom field", skip(executor))]
+pub async fn create_custom_field(
+    executor: impl PgExecutor<'_>,
+    name: &str,
+    field_type: FieldType,
+) -> Result<Option<CustomField>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            INSERT INTO custom_fields (name, field_type, created_at)
+            VALUES ($1, $2, now())
+            ON CONFLICT DO NOTHING
+            RETURNING created_at
+        "#,
+        name,
+        field_type.as_str(),
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|r| CustomField {
+        name: name.into(),
+        field_type,
+        created_at: r.created_at,
+    }))
+}
+
+#[tracing::instrument(name = "Replace subscriber tags", skip(executor))]
+pub async fn set_tags(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    tags: &[String],
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET tags = ARRAY(SELECT DISTINCT UNNEST($2::text[]) ORDER BY 1)
+            WHERE id = $1
+        "#,
+        subscriber_id,
+        tags,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Update subscriber custom fields", skip(executor))]
+pub async fn update_custom_fields(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    values: Map<String, Value>,
+) -> Result<Option<Value>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET custom_fields = jsonb_strip_nulls(custom_fields || $2)
+            WHERE id = $1
+            RETURNING custom_fields
+        "#,
+        subscriber_id,
+        Value::Object(values),
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|r| r.custom_fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use serde_json::json;
+
+    use crate::custom_fields::*;
+
+    #[test]
+    fn values_are_parsed_according_to_the_field_type() {
+        assert_ok_eq!(FieldType::Text.parse_str("DE"), json!("DE"));
+        assert_ok_eq!(FieldType::Number.parse_str("42.5"), json!(42.5));
+        assert_ok_eq!(FieldType::Boolean.parse_str("true"), json!(true));
+        assert_ok_eq!(FieldType::Date.parse_str("2026-10-18"), json!("2026-10-18"));
+        assert_ok_eq!(FieldType::Number.parse_value(&json!(3)), json!(3));
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_rejected() {
+        assert_err!(FieldType::Number.parse_str("many"));
+        assert_err!(FieldType::Boolean.parse_str("yes"));
+        assert_err!(FieldType::Date.parse_str("18/10/2026"));
+        assert_err!(FieldType::Text.parse_value(&json!(1)));
+        assert_err!(FieldType::Boolean.parse_value(&json!([true])));
+    }
+
+    #[test]
+    fn unknown_custom_fields_are_rejected() {
+        let field_types = HashMap::from([("country".to_string(), FieldType::Text)]);
+        let country = json!("DE");
+        let age = json!(30);
+
+        assert_err!(parse_custom_fields(
+            [("country", &country), ("age", &age)],
+            &field_types
+        ));
+    }
+
+    #[test]
+    fn reserved_names_are_not_valid_field_names() {
+        assert!(is_valid_field_name("country"));
+        assert!(!is_valid_field_name("tag"));
+        assert!(!is_valid_field_name("Country"));
+        assert!(!is_valid_field_name("1st"));
+    }
+}
@@ -0,0 +1,390 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::{Pool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_policy::EmailPolicy;
+use crate::signature::{sign, verify, InvalidSignature};
+use crate::startup::HmacSecret;
+use crate::suppression::{add_tombstone, email_hash};
+
+pub const DATA_REQUEST_LINK_LIFETIME_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataRequestKind {
+    Access,
+    Erasure,
+}
+
+impl DataRequestKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DataRequestKind::Access => "access",
+            DataRequestKind::Erasure => "erasure",
+        }
+    }
+}
+
+pub fn data_request_token(
+    secret: &HmacSecret,
+    kind: DataRequestKind,
+    subscriber_id: Uuid,
+    expires_at: DateTime<Utc>,
+) -> String {
+    sign(
+        secret,
+        &format!(
+            "data-{}:{}:{}",
+            kind.as_str(),
+            subscriber_id,
+            expires_at.timestamp()
+        ),
+    )
+}
+
+pub fn verify_data_request_token(
+    secret: &HmacSecret,
+    kind: DataRequestKind,
+    token: &str,
+    now: DateTime<Utc>,
+) -> Result<Uuid, InvalidSignature> {
+    let payload = verify(secret, token)?;
+    let (subscriber_id, expires_at) = payload
+        .strip_prefix(&format!("data-{}:", kind.as_str()))
+        .and_then(|rest| rest.split_once(':'))
+        .ok_or(InvalidSignature)?;
+    let expires_at = expires_at
+        .parse::<i64>()
+        .ok()
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+        .ok_or(InvalidSignature)?;
+    if expires_at < now {
+        return Err(InvalidSignature);
+    }
+
+    Uuid::parse_str(subscriber_id).map_err(|_| InvalidSignature)
+}
+
+pub fn data_request_link(
+    access_url: &str,
+    secret: &HmacSecret,
+    kind: DataRequestKind,
+    subscriber_id: Uuid,
+) -> String {
+    let path = match kind {
+        DataRequestKind::Access => "data",
+        DataRequestKind::Erasure => "erasure",
+    };
+
+    format!(
+        "{}/subscriptions/{}?token={}",
+        access_url,
+        path,
+        data_request_token(
+            secret,
+            kind,
+            subscriber_id,
+            Utc::now() + Duration::hours(DATA_REQUEST_LINK_LIFETIME_HOURS)
+        )
+    )
+}
+
+// Collects every row tied to the subscriber's normalised address, including import errors
+// for any spelling of it.
+#[tracing::instrument(
+    name = "Collect the data held on a subscriber",
+    skip(pool, email_policy, hmac_secret)
+)]
+pub async fn collect_subscriber_data(
+    pool: &Pool<Postgres>,
+    email_policy: &EmailPolicy,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+) -> Result<Option<Value>, sqlx::Error> {
+    let (email, normalised_email) = match get_email(pool, subscriber_id).await? {
+        Some(email) => email,
+        None => return Ok(None),
+    };
+    let (import_ids, row_numbers): (Vec<Uuid>, Vec<i64>) =
+        get_import_errors(pool, email_policy, &normalised_email)
+            .await?
+            .into_iter()
+            .unzip();
+
+    let row = sqlx::query!(
+        r#"
+            WITH subscribers AS (
+                SELECT id FROM subscriptions WHERE normalised_email = $3
+            )
+            SELECT jsonb_build_object(
+                'email', $1::text,
+                'generated_at', now(),
+                'subscriptions', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(s) ORDER BY s.subscribed_at), '[]')
+                    FROM subscriptions s
+                    WHERE s.id IN (SELECT id FROM subscribers)
+                ),
+                'subscription_tokens', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(t)), '[]')
+                    FROM subscription_tokens t
+                    WHERE t.subscriber_id IN (SELECT id FROM subscribers)
+                ),
+                'consent', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(c) - 'id' ORDER BY c.recorded_at, c.id), '[]')
+                    FROM consent_records c
+                    WHERE c.subscriber_id IN (SELECT id FROM subscribers)
+                ),
+                'list_memberships', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(m) || jsonb_build_object('list', l.slug) ORDER BY l.slug), '[]')
+                    FROM list_memberships m
+                    JOIN lists l ON l.list_id = m.list_id
+                    WHERE m.subscriber_id IN (SELECT id FROM subscribers)
+                ),
+                'status_changes', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(c) - 'changed_by' ORDER BY c.changed_at), '[]')
+                    FROM subscriber_status_changes c
+                    WHERE c.subscriber_id IN (SELECT id FROM subscribers)
+                ),
+                'deliveries', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(d) ORDER BY d.queued_at), '[]')
+                    FROM deliveries d
+                    WHERE d.subscriber_id IN (SELECT id FROM subscribers)
+                ),
+                'engagement', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(e) - 'id' ORDER BY e.occurred_at), '[]')
+                    FROM delivery_events e
+                    WHERE e.subscriber_id IN (SELECT id FROM subscribers)
+                ),
+                'suppressions', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(p) - 'legacy_email_hash'), '[]')
+                    FROM suppressions p
+                    WHERE p.email_hash = $2
+                ),
+                'import_errors', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(i) ORDER BY i.import_id, i.row_number), '[]')
+                    FROM subscriber_import_errors i
+                    WHERE (i.import_id, i.row_number) IN (
+                        SELECT * FROM unnest($4::uuid[], $5::bigint[])
+                    )
+                )
+            ) AS "data!"
+        "#,
+        email,
+        email_hash(hmac_secret, &normalised_email),
+        normalised_email,
+        &import_ids,
+        &row_numbers,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Some(row.data))
+}
+
+// Deletes everything tied to the subscriber's address and keeps a hashed tombstone in the
+// suppression list, so that the address cannot be imported or subscribed again by mistake.
+#[tracing::instrument(
+    name = "Erase the data held on a subscriber",
+    skip(pool, email_policy, hmac_secret)
+)]
+pub async fn erase_subscriber_data(
+    pool: &Pool<Postgres>,
+    email_policy: &EmailPolicy,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let (_, normalised_email) = match get_email(pool, subscriber_id).await? {
+        Some(email) => email,
+        None => return Ok(false),
+    };
+    let (import_ids, row_numbers): (Vec<Uuid>, Vec<i64>) =
+        get_import_errors(pool, email_policy, &normalised_email)
+            .await?
+            .into_iter()
+            .unzip();
+
+    let mut transaction = pool.begin().await?;
+    let subscriber_ids: Vec<Uuid> = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE normalised_email = $1 FOR UPDATE",
+        normalised_email
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+    for subscriber_id in &subscriber_ids {
+        delete_subscriber_records(&mut transaction, *subscriber_id).await?;
+    }
+    sqlx::query!(
+        r#"
+            UPDATE subscriber_import_errors SET email = NULL
+            WHERE (import_id, row_number) IN (SELECT * FROM unnest($1::uuid[], $2::bigint[]))
+        "#,
+        &import_ids,
+        &row_numbers,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    add_tombstone(
+        &mut *transaction,
+        hmac_secret,
+        &normalised_email,
+        "erased",
+        "erasure_request",
+    )
+    .await?;
+    transaction.commit().await?;
+
+    Ok(true)
+}
+
+#[tracing::instrument(name = "Delete a subscriber", skip(transaction))]
+pub async fn delete_subscriber_records(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM delivery_events WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM deliveries WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM list_memberships WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM consent_records WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscriber_status_changes WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    let result = sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Returns the address as it was given and its normalised form.
+async fn get_email(
+    pool: &Pool<Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<(String, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT email, normalised_email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| (r.email, r.normalised_email)))
+}
+
+// Import errors keep the address as it was in the file, so candidates sharing the local part
+// are normalised here rather than in SQL.
+async fn get_import_errors(
+    pool: &Pool<Postgres>,
+    email_policy: &EmailPolicy,
+    normalised_email: &str,
+) -> Result<Vec<(Uuid, i64)>, sqlx::Error> {
+    let local_part = normalised_email
+        .rsplit_once('@')
+        .map_or(normalised_email, |(local_part, _)| local_part);
+    let rows = sqlx::query!(
+        r#"
+            SELECT import_id, row_number, email AS "email!"
+            FROM subscriber_import_errors
+            WHERE starts_with(lower(btrim(email)), $1)
+        "#,
+        format!("{}@", local_part.to_lowercase()),
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter(|row| {
+            SubscriberEmail::parse(row.email.clone())
+                .is_ok_and(|email| email_policy.normalise(&email) == normalised_email)
+        })
+        .map(|row| (row.import_id, row.row_number))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+
+    use crate::data_requests::*;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("super-secret".into()))
+    }
+
+    #[test]
+    fn data_request_token_is_verified_until_it_expires() {
+        let subscriber_id = Uuid::new_v4();
+        let now = Utc::now();
+        let token = data_request_token(
+            &secret(),
+            DataRequestKind::Access,
+            subscriber_id,
+            now + Duration::hours(1),
+        );
+
+        assert_ok_eq!(
+            verify_data_request_token(&secret(), DataRequestKind::Access, &token, now),
+            subscriber_id
+        );
+        assert_err!(verify_data_request_token(
+            &secret(),
+            DataRequestKind::Access,
+            &token,
+            now + Duration::hours(2)
+        ));
+    }
+
+    #[test]
+    fn access_tokens_cannot_be_used_for_erasure() {
+        let now = Utc::now();
+        let token = data_request_token(
+            &secret(),
+            DataRequestKind::Access,
+            Uuid::new_v4(),
+            now + Duration::hours(1),
+        );
+
+        assert_err!(verify_data_request_token(
+            &secret(),
+            DataRequestKind::Erasure,
+            &token,
+            now
+        ));
+    }
+}
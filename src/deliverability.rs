@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::async_trait;
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::TokioAsyncResolver;
+use serde::Deserialize;
+
+use crate::configuration::DeliverabilitySettings;
+use crate::domain::SubscriberEmail;
+use crate::email_policy::EmailRejection;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliverabilityMode {
+    Reject,
+    Warn,
+    Off,
+}
+
+#[async_trait]
+pub trait MailDomainResolver: Send + Sync {
+    // Whether the domain has mail servers, either MX records or A/AAAA records to fall back to.
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error>;
+}
+
+pub struct DnsResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsResolver {
+    pub fn from_system_conf() -> Result<Self, ResolveError> {
+        Ok(Self {
+            resolver: TokioAsyncResolver::tokio_from_system_conf()?,
+        })
+    }
+}
+
+#[async_trait]
+impl MailDomainResolver for DnsResolver {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        // Appending the root label keeps the resolver from trying the local search domains.
+        let domain = format!("{}.", domain);
+
+        match self.resolver.mx_lookup(domain.as_str()).await {
+            // A single MX record pointing at the root is a "null MX", see RFC 7505.
+            Ok(lookup) => return Ok(lookup.iter().any(|mx| !mx.exchange().is_root())),
+            Err(error) if !is_no_records_found(&error) => return Err(error.into()),
+            Err(_) => {}
+        }
+
+        match self.resolver.lookup_ip(domain.as_str()).await {
+            Ok(lookup) => Ok(lookup.iter().next().is_some()),
+            Err(error) if is_no_records_found(&error) => Ok(false),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+fn is_no_records_found(error: &ResolveError) -> bool {
+    matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+#[derive(Clone)]
+pub struct DeliverabilityCheck {
+    pub mode: DeliverabilityMode,
+    pub resolver: Arc<dyn MailDomainResolver>,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache: Arc<Mutex<HashMap<String, (bool, Instant)>>>,
+}
+
+impl DeliverabilityCheck {
+    pub fn new(
+        mode: DeliverabilityMode,
+        resolver: Arc<dyn MailDomainResolver>,
+        timeout: Duration,
+        cache_ttl: Duration,
+    ) -> Self {
+        Self {
+            mode,
+            resolver,
+            timeout,
+            cache_ttl,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn from_settings(settings: &DeliverabilitySettings) -> Result<Self, ResolveError> {
+        Ok(Self::new(
+            settings.mode,
+            Arc::new(DnsResolver::from_system_conf()?),
+            settings.timeout(),
+            settings.cache_ttl(),
+        ))
+    }
+
+    // Lookup failures and timeouts let the address through: a slow resolver must not stop
+    // people from subscribing.
+    #[tracing::instrument(name = "Check the deliverability of an email domain", skip(self))]
+    pub async fn check(&self, email: &SubscriberEmail) -> Result<(), EmailRejection> {
+        if self.mode == DeliverabilityMode::Off {
+            return Ok(());
+        }
+
+        let domain = email.domain();
+        let accepts_mail = match self.cached(domain) {
+            Some(accepts_mail) => accepts_mail,
+            None => {
+                match tokio::time::timeout(self.timeout, self.resolver.accepts_mail(domain)).await {
+                    Ok(Ok(accepts_mail)) => {
+                        self.cache_result(domain, accepts_mail);
+                        accepts_mail
+                    }
+                    Ok(Err(error)) => {
+                        tracing::warn!(
+                            error.cause_chain = ?error,
+                            "Failed to look up the mail servers of {}",
+                            domain
+                        );
+                        return Ok(());
+                    }
+                    Err(_) => {
+                        tracing::warn!("Timed out looking up the mail servers of {}", domain);
+                        return Ok(());
+                    }
+                }
+            }
+        };
+
+        match (accepts_mail, self.mode) {
+            (true, _) => Ok(()),
+            (false, DeliverabilityMode::Reject) => Err(EmailRejection::Undeliverable {
+                domain: domain.to_string(),
+            }),
+            (false, _) => {
+                tracing::warn!("{} has no mail servers", domain);
+                Ok(())
+            }
+        }
+    }
+
+    fn cached(&self, domain: &str) -> Option<bool> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(domain)
+            .filter(|(_, cached_at)| cached_at.elapsed() < self.cache_ttl)
+            .map(|(accepts_mail, _)| *accepts_mail)
+    }
+
+    fn cache_result(&self, domain: &str, accepts_mail: bool) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, (_, cached_at)| cached_at.elapsed() < self.cache_ttl);
+        cache.insert(domain.to_string(), (accepts_mail, Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use claims::{assert_err, assert_ok};
+
+    use crate::deliverability::*;
+
+    struct StubResolver {
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl MailDomainResolver for StubResolver {
+        async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            match domain {
+                "slow.example" => {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    Ok(false)
+                }
+                "broken.example" => Err(anyhow::anyhow!("SERVFAIL")),
+                domain => Ok(domain == "example.com"),
+            }
+        }
+    }
+
+    fn check(mode: DeliverabilityMode) -> (DeliverabilityCheck, Arc<StubResolver>) {
+        let resolver = Arc::new(StubResolver {
+            lookups: AtomicUsize::new(0),
+        });
+        let check = DeliverabilityCheck::new(
+            mode,
+            resolver.clone(),
+            Duration::from_millis(50),
+            Duration::from_secs(60),
+        );
+        (check, resolver)
+    }
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn domains_without_mail_servers_are_rejected_or_let_through_by_mode() {
+        let (reject, _) = check(DeliverabilityMode::Reject);
+        let (warn, _) = check(DeliverabilityMode::Warn);
+        let (off, resolver) = check(DeliverabilityMode::Off);
+
+        assert_ok!(reject.check(&email("arine@example.com")).await);
+        assert_err!(reject.check(&email("arine@nowhere.example")).await);
+        assert_ok!(warn.check(&email("arine@nowhere.example")).await);
+        assert_ok!(off.check(&email("arine@nowhere.example")).await);
+        assert_eq!(resolver.lookups.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn lookup_results_are_cached() {
+        let (check, resolver) = check(DeliverabilityMode::Reject);
+
+        for _ in 0..3 {
+            assert_err!(check.check(&email("arine@nowhere.example")).await);
+        }
+        assert_eq!(resolver.lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_or_slow_lookups_let_the_address_through() {
+        let (check, _) = check(DeliverabilityMode::Reject);
+
+        assert_ok!(check.check(&email("arine@broken.example")).await);
+        assert_ok!(check.check(&email("arine@slow.example")).await);
+    }
+}
@@ -6,12 +6,30 @@ use validator::validate_email;
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
+    // The domain is lowercased and converted to its IDNA (punycode) form. The local part is
+    // kept as typed, see `EmailPolicy` for how it is folded when comparing addresses.
     pub fn parse(s: String) -> Result<Self, String> {
-        match validate_email(&s) {
-            true => Ok(Self(s)),
-            false => Err(format!("{} is not a valid subscriber email", s)),
+        let invalid = || format!("{} is not a valid subscriber email", s);
+
+        let (local_part, domain) = s.trim().rsplit_once('@').ok_or_else(invalid)?;
+        let domain = idna::domain_to_ascii(domain).map_err(|_| invalid())?;
+        let email = format!("{}@{}", local_part, domain);
+
+        match validate_email(&email) {
+            true => Ok(Self(email)),
+            false => Err(invalid()),
         }
     }
+
+    pub fn local_part(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map_or("", |(local_part, _)| local_part)
+    }
+
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map_or("", |(_, domain)| domain)
+    }
 }
 
 impl Display for SubscriberEmail {
@@ -70,6 +88,14 @@ mod tests {
         assert_ok!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn domain_is_lowercased_and_converted_to_punycode() {
+        let email = SubscriberEmail::parse(" Arine@Bücher.Example ".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Arine@xn--bcher-kva.example");
+        assert_eq!(email.local_part(), "Arine");
+        assert_eq!(email.domain(), "xn--bcher-kva.example");
+    }
+
     #[quickcheck]
     fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(valid_email.0).is_ok()
@@ -0,0 +1,55 @@
+#[derive(Debug)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: String) -> Result<Self, String> {
+        let tag = s.trim().to_lowercase();
+        let is_valid = !tag.is_empty()
+            && tag.len() <= 64
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if is_valid {
+            Ok(Self(tag))
+        } else {
+            Err(format!("{} is not a valid tag", s))
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::*;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = SubscriberTag::parse(" Beta-Testers ".to_string()).unwrap();
+        assert_eq!(tag.as_ref(), "beta-testers");
+    }
+
+    #[test]
+    fn empty_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse("  ".to_string()));
+    }
+
+    #[test]
+    fn tags_longer_than_64_characters_are_rejected() {
+        assert_ok!(SubscriberTag::parse("a".repeat(64)));
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn tags_containing_an_invalid_character_are_rejected() {
+        for tag in ["beta testers", "beta,testers", "bêta", "beta\"", "(beta)"] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+}
@@ -1,10 +1,34 @@
 use std::time::Duration;
 
+use prometheus::IntCounterVec;
+use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::domain::SubscriberEmail;
+use crate::metrics::Metrics;
+use crate::request_id::{current_request_id, RequestId, REQUEST_ID_HEADER};
+use crate::telemetry::trace_context_headers;
+
+const TRANSPORT: &str = "postmark";
+
+#[derive(Debug, Clone, Copy)]
+pub enum EmailKind {
+    Confirmation,
+    Newsletter,
+    DataRequest,
+}
+
+impl EmailKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailKind::Confirmation => "confirmation",
+            EmailKind::Newsletter => "newsletter",
+            EmailKind::DataRequest => "data_request",
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct EmailClient {
@@ -12,6 +36,7 @@ pub struct EmailClient {
     base_url: String,
     sender: SubscriberEmail,
     authorization_token: Secret<String>,
+    sent_emails: Option<IntCounterVec>,
 }
 
 impl EmailClient {
@@ -28,26 +53,64 @@ impl EmailClient {
             base_url,
             sender,
             authorization_token,
+            sent_emails: None,
         }
     }
+
+    pub fn with_metrics(mut self, metrics: &Metrics) -> Self {
+        self.sent_emails = Some(metrics.emails.clone());
+        self
+    }
+
     pub async fn send_email(
         &self,
+        kind: EmailKind,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<SentEmail, reqwest::Error> {
+        let result = self
+            .post_email(recipient, subject, html_content, text_content)
+            .await;
+
+        if let Some(sent_emails) = &self.sent_emails {
+            let outcome = match result {
+                Ok(_) => "sent",
+                Err(_) => "failed",
+            };
+            sent_emails
+                .with_label_values(&[TRANSPORT, kind.as_str(), outcome])
+                .inc();
+        }
+
+        result
+    }
+
+    async fn post_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<SentEmail, reqwest::Error> {
         let url = format!("{}/email", self.base_url);
+        let request_id = current_request_id();
         let body = SendEmailRequest {
             from: self.sender.as_ref(),
             to: recipient.as_ref(),
             subject,
             html_body: html_content,
             text_body: text_content,
+            metadata: request_id.as_ref().map(|request_id| Metadata {
+                request_id: request_id.as_ref(),
+            }),
         };
 
-        self.http_client
+        let response = self
+            .http_client
             .post(url)
+            .headers(correlation_headers(request_id.as_ref()))
             .header(
                 "X-Postmark-Server-Token",
                 self.authorization_token.expose_secret(),
@@ -57,10 +120,53 @@ impl EmailClient {
             .await?
             .error_for_status()?;
 
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .map(|response| response.message_id);
+
+        Ok(SentEmail { message_id })
+    }
+
+    // Fetches the server details, which only succeeds when the provider is reachable and
+    // accepts the token.
+    pub async fn check_connection(&self) -> Result<(), reqwest::Error> {
+        let url = format!("{}/server", self.base_url);
+
+        self.http_client
+            .get(url)
+            .headers(correlation_headers(current_request_id().as_ref()))
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .send()
+            .await?
+            .error_for_status()?;
+
         Ok(())
     }
 }
 
+// Lets a send be traced back to the request which caused it, both in our traces and in the
+// provider's logs.
+fn correlation_headers(request_id: Option<&RequestId>) -> HeaderMap {
+    let mut headers = trace_context_headers();
+    if let Some(request_id) = request_id {
+        headers.insert(
+            REQUEST_ID_HEADER,
+            HeaderValue::from_str(request_id.as_ref()).unwrap(),
+        );
+    }
+    headers
+}
+
+#[derive(Debug)]
+pub struct SentEmail {
+    pub message_id: Option<String>,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
@@ -69,6 +175,21 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    // Returned by the provider in its webhooks, so that bounces and complaints can be traced
+    // back to the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Metadata<'a>>,
+}
+
+#[derive(Serialize)]
+struct Metadata<'a> {
+    request_id: &'a str,
+}
+
+#[derive(Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
 }
 
 #[cfg(test)]
@@ -130,6 +251,22 @@ mod tests {
         )
     }
 
+    #[tokio::test]
+    async fn check_connection_fails_if_the_token_is_rejected() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/server"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_err!(email_client.check_connection().await);
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_server_returns_200() {
         let mock_server = MockServer::start().await;
@@ -146,12 +283,51 @@ mod tests {
             .await;
 
         let response = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(
+                EmailKind::Newsletter,
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+            )
             .await;
 
         assert_ok!(response);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_message_id_given_by_server() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "SubmittedAt": "2014-02-17T07:25:01.4178645-05:00",
+                "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client
+            .send_email(
+                EmailKind::Newsletter,
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+            )
+            .await;
+
+        assert_eq!(
+            assert_ok!(response).message_id.as_deref(),
+            Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+        );
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_server_returns_500() {
         let mock_server = MockServer::start().await;
@@ -164,7 +340,13 @@ mod tests {
             .await;
 
         let response = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(
+                EmailKind::Newsletter,
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+            )
             .await;
 
         assert_err!(response);
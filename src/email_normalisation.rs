@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::data_requests::delete_subscriber_records;
+use crate::domain::SubscriberEmail;
+use crate::email_policy::EmailPolicy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateResolution {
+    Report,
+    Delete,
+}
+
+#[derive(Serialize)]
+pub struct InvalidEmail {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateSubscribers {
+    pub normalised_email: String,
+    pub kept: Uuid,
+    pub duplicates: Vec<Uuid>,
+    pub deleted: bool,
+}
+
+#[derive(Serialize)]
+pub struct NormalisationReport {
+    pub applied: bool,
+    pub subscribers: usize,
+    pub changed: usize,
+    pub invalid: Vec<InvalidEmail>,
+    pub duplicates: Vec<DuplicateSubscribers>,
+}
+
+struct StoredSubscriber {
+    id: Uuid,
+    email: String,
+    status: String,
+    normalised_email: String,
+}
+
+// Works out the normalised address of every subscriber with the configured email policy, e.g.
+// after upgrading or changing `email_validation.local_part_case`. Of the subscribers which
+// share an address, a confirmed one is kept before any other and then the oldest one. Nothing
+// is changed while subscribers share an address, unless the others are deleted with
+// `DuplicateResolution::Delete`.
+#[tracing::instrument(name = "Normalise subscriber emails", skip(pool, email_policy))]
+pub async fn normalise_subscriber_emails(
+    pool: &Pool<Postgres>,
+    email_policy: &EmailPolicy,
+    resolution: DuplicateResolution,
+) -> Result<NormalisationReport, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let subscribers = sqlx::query_as!(
+        StoredSubscriber,
+        r#"
+            SELECT id, email, status, normalised_email
+            FROM subscriptions
+            ORDER BY subscribed_at, id
+            FOR UPDATE
+        "#,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    let mut invalid = vec![];
+    let mut by_address: BTreeMap<String, Vec<&StoredSubscriber>> = BTreeMap::new();
+    for subscriber in &subscribers {
+        match SubscriberEmail::parse(subscriber.email.clone()) {
+            Ok(email) => by_address
+                .entry(email_policy.normalise(&email))
+                .or_default()
+                .push(subscriber),
+            Err(error) => invalid.push(InvalidEmail {
+                subscriber_id: subscriber.id,
+                email: subscriber.email.clone(),
+                error,
+            }),
+        }
+    }
+
+    let mut ids = vec![];
+    let mut normalised_emails = vec![];
+    let mut duplicates = vec![];
+    for (normalised_email, mut group) in by_address {
+        // The sort is stable, so the oldest subscriber comes first within each status.
+        group.sort_by_key(|subscriber| subscriber.status != "confirmed");
+        let kept = group[0];
+        ids.push(kept.id);
+        normalised_emails.push(normalised_email.clone());
+        if group.len() > 1 {
+            duplicates.push(DuplicateSubscribers {
+                normalised_email,
+                kept: kept.id,
+                duplicates: group[1..].iter().map(|subscriber| subscriber.id).collect(),
+                deleted: resolution == DuplicateResolution::Delete,
+            });
+        }
+    }
+    let assigned: HashMap<Uuid, &String> = ids.iter().copied().zip(&normalised_emails).collect();
+    let changed = subscribers
+        .iter()
+        .filter(|subscriber| {
+            assigned
+                .get(&subscriber.id)
+                .is_some_and(|email| **email != subscriber.normalised_email)
+        })
+        .count();
+
+    let applied = duplicates.is_empty() || resolution == DuplicateResolution::Delete;
+    if !applied {
+        return Ok(NormalisationReport {
+            applied,
+            subscribers: subscribers.len(),
+            changed,
+            invalid,
+            duplicates,
+        });
+    }
+
+    // The addresses being assigned are replaced by the subscriber ids first, which are never
+    // addresses, so that subscribers can swap addresses without tripping the unique index.
+    sqlx::query!(
+        "UPDATE subscriptions SET normalised_email = id::text WHERE id = ANY($1)",
+        &ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    for group in &duplicates {
+        for subscriber_id in &group.duplicates {
+            delete_subscriber_records(&mut transaction, *subscriber_id).await?;
+        }
+    }
+    sqlx::query!(
+        r#"
+            UPDATE subscriptions s
+            SET normalised_email = n.normalised_email
+            FROM UNNEST($1::uuid[], $2::text[]) AS n(id, normalised_email)
+            WHERE s.id = n.id
+        "#,
+        &ids,
+        &normalised_emails,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(NormalisationReport {
+        applied,
+        subscribers: subscribers.len(),
+        changed,
+        invalid,
+        duplicates,
+    })
+}
@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::configuration::EmailValidationSettings;
+use crate::domain::SubscriberEmail;
+
+// Domains which are checked for typos. A domain in this list is never reported as a typo
+// of another one, e.g. `gmx.com` is not a misspelled `gmx.de`.
+const POPULAR_DOMAINS: &[&str] = &[
+    "aol.com",
+    "gmail.com",
+    "gmx.com",
+    "gmx.de",
+    "gmx.net",
+    "googlemail.com",
+    "hotmail.co.uk",
+    "hotmail.com",
+    "hotmail.fr",
+    "icloud.com",
+    "live.com",
+    "mac.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "web.de",
+    "yahoo.co.uk",
+    "yahoo.com",
+    "yahoo.fr",
+    "ymail.com",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum EmailRejection {
+    Disposable { domain: String },
+    Misspelled { email: String, suggestion: String },
+    Undeliverable { domain: String },
+}
+
+impl std::fmt::Display for EmailRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmailRejection::Disposable { domain } => write!(
+                f,
+                "{} is a disposable email domain, please use a permanent address",
+                domain
+            ),
+            EmailRejection::Misspelled { email, suggestion } => {
+                write!(
+                    f,
+                    "{} looks misspelled. Did you mean {}?",
+                    email, suggestion
+                )
+            }
+            EmailRejection::Undeliverable { domain } => write!(
+                f,
+                "{} does not accept email, please check the address",
+                domain
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LocalPartCase {
+    Preserve,
+    Lowercase,
+}
+
+#[derive(Clone)]
+pub struct EmailPolicy {
+    pub local_part_case: LocalPartCase,
+    disposable_domains: Arc<HashSet<String>>,
+}
+
+impl EmailPolicy {
+    pub fn new(
+        local_part_case: LocalPartCase,
+        disposable_domains: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            local_part_case,
+            disposable_domains: Arc::new(disposable_domains.into_iter().collect()),
+        }
+    }
+
+    pub fn from_settings(settings: &EmailValidationSettings) -> Result<Self, std::io::Error> {
+        let disposable_domains = match &settings.disposable_domains_path {
+            Some(path) => parse_domain_list(&std::fs::read_to_string(path)?),
+            None => vec![],
+        };
+
+        Ok(Self::new(settings.local_part_case, disposable_domains))
+    }
+
+    // The form used to tell whether two addresses belong to the same subscriber.
+    pub fn normalise(&self, email: &SubscriberEmail) -> String {
+        match self.local_part_case {
+            LocalPartCase::Preserve => email.as_ref().to_string(),
+            LocalPartCase::Lowercase => {
+                format!("{}@{}", email.local_part().to_lowercase(), email.domain())
+            }
+        }
+    }
+
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), EmailRejection> {
+        let domain = email.domain();
+
+        if self.is_disposable(domain) {
+            return Err(EmailRejection::Disposable {
+                domain: domain.to_string(),
+            });
+        }
+        if let Some(suggestion) = suggest_domain(domain) {
+            return Err(EmailRejection::Misspelled {
+                email: email.to_string(),
+                suggestion: format!("{}@{}", email.local_part(), suggestion),
+            });
+        }
+
+        Ok(())
+    }
+
+    // Subdomains of a blocked domain are blocked as well.
+    fn is_disposable(&self, domain: &str) -> bool {
+        let mut domain = domain;
+        loop {
+            if self.disposable_domains.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => domain = parent,
+                _ => return false,
+            }
+        }
+    }
+}
+
+// One domain per line, blank lines and lines starting with `#` are ignored.
+fn parse_domain_list(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+pub fn suggest_domain(domain: &str) -> Option<&'static str> {
+    if POPULAR_DOMAINS.contains(&domain) {
+        return None;
+    }
+
+    POPULAR_DOMAINS
+        .iter()
+        .copied()
+        .find(|popular| edit_distance(domain, popular) == 1)
+}
+
+// Optimal string alignment distance, so that swapped letters count as a single edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distances[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_err_eq, assert_none, assert_ok, assert_some_eq};
+
+    use crate::domain::SubscriberEmail;
+    use crate::email_policy::*;
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn local_parts_are_folded_according_to_the_policy() {
+        let lowercase = EmailPolicy::new(LocalPartCase::Lowercase, vec![]);
+        let preserve = EmailPolicy::new(LocalPartCase::Preserve, vec![]);
+
+        assert_eq!(
+            lowercase.normalise(&email("Foo@Example.com")),
+            "foo@example.com"
+        );
+        assert_eq!(
+            preserve.normalise(&email("Foo@Example.com")),
+            "Foo@example.com"
+        );
+    }
+
+    #[test]
+    fn common_typo_domains_get_a_suggestion() {
+        assert_some_eq!(suggest_domain("gmial.com"), "gmail.com");
+        assert_some_eq!(suggest_domain("hotmal.com"), "hotmail.com");
+        assert_some_eq!(suggest_domain("yahoo.con"), "yahoo.com");
+        assert_none!(suggest_domain("gmail.com"));
+        assert_none!(suggest_domain("gmx.com"));
+        assert_none!(suggest_domain("example.com"));
+    }
+
+    #[test]
+    fn typo_suggestions_keep_the_local_part() {
+        let policy = EmailPolicy::new(LocalPartCase::Lowercase, vec![]);
+
+        assert_err_eq!(
+            policy.check(&email("Arine@gmial.com")),
+            EmailRejection::Misspelled {
+                email: "Arine@gmial.com".into(),
+                suggestion: "Arine@gmail.com".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn disposable_domains_and_their_subdomains_are_rejected() {
+        let policy = EmailPolicy::new(
+            LocalPartCase::Lowercase,
+            parse_domain_list("# blocklist\n\nMailinator.com\n"),
+        );
+
+        assert_err!(policy.check(&email("arine@mailinator.com")));
+        assert_err!(policy.check(&email("arine@eu.mailinator.com")));
+        assert_ok!(policy.check(&email("arine@example.com")));
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+
+use crate::configuration::HealthSettings;
+use crate::email_client::EmailClient;
+use crate::migrations::{latest_version, schema_version};
+
+// Touched by the background workers on every iteration of their loop and after every email
+// of a newsletter issue they are delivering.
+#[derive(Clone)]
+pub struct Heartbeat {
+    last_beat: Arc<Mutex<Instant>>,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self {
+            last_beat: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn beat(&self) {
+        *self.last_beat.lock().unwrap() = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.last_beat.lock().unwrap().elapsed()
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Up,
+    Down,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ComponentReport {
+    pub status: ComponentStatus,
+    pub latency_in_milliseconds: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HealthReport {
+    pub status: ComponentStatus,
+    pub components: BTreeMap<&'static str, ComponentReport>,
+    // Left empty when the database can't be reached.
+    pub schema_version: Option<i64>,
+    pub latest_schema_version: Option<i64>,
+}
+
+#[derive(Clone)]
+pub struct HealthCheck {
+    pub pool: Pool<Postgres>,
+    pub email_client: EmailClient,
+    pub heartbeat: Heartbeat,
+    pub timeout: Duration,
+    pub worker_heartbeat_timeout: Duration,
+}
+
+impl HealthCheck {
+    pub fn from_settings(
+        settings: &HealthSettings,
+        pool: Pool<Postgres>,
+        email_client: EmailClient,
+    ) -> Self {
+        Self {
+            pool,
+            email_client,
+            heartbeat: Heartbeat::new(),
+            timeout: settings.timeout(),
+            worker_heartbeat_timeout: settings.worker_heartbeat_timeout(),
+        }
+    }
+
+    #[tracing::instrument(name = "Check the readiness of the application", skip(self))]
+    pub async fn report(&self) -> HealthReport {
+        let mut applied_version = None;
+        let (database, email_transport) = tokio::join!(
+            self.check(async {
+                applied_version = schema_version(&self.pool).await?;
+                Ok(())
+            }),
+            self.check(async {
+                self.email_client.check_connection().await?;
+                Ok(())
+            }),
+        );
+        let workers = self
+            .check(async {
+                let elapsed = self.heartbeat.elapsed();
+                match elapsed <= self.worker_heartbeat_timeout {
+                    true => Ok(()),
+                    false => Err(anyhow::anyhow!(
+                        "No heartbeat for {} seconds",
+                        elapsed.as_secs()
+                    )),
+                }
+            })
+            .await;
+
+        let components = BTreeMap::from([
+            ("database", database),
+            ("email_transport", email_transport),
+            ("workers", workers),
+        ]);
+        let status = match components
+            .values()
+            .all(|component| component.status == ComponentStatus::Up)
+        {
+            true => ComponentStatus::Up,
+            false => ComponentStatus::Down,
+        };
+
+        HealthReport {
+            status,
+            components,
+            schema_version: applied_version,
+            latest_schema_version: latest_version(),
+        }
+    }
+
+    async fn check(
+        &self,
+        check: impl Future<Output = Result<(), anyhow::Error>>,
+    ) -> ComponentReport {
+        let started_at = Instant::now();
+        let error = match tokio::time::timeout(self.timeout, check).await {
+            Ok(Ok(())) => None,
+            Ok(Err(error)) => Some(error.to_string()),
+            Err(_) => Some(format!(
+                "Timed out after {} milliseconds",
+                self.timeout.as_millis()
+            )),
+        };
+
+        if let Some(error) = &error {
+            tracing::warn!("Health check failed: {}", error);
+        }
+
+        ComponentReport {
+            status: match error {
+                None => ComponentStatus::Up,
+                Some(_) => ComponentStatus::Down,
+            },
+            latency_in_milliseconds: started_at.elapsed().as_millis(),
+            error,
+        }
+    }
+}
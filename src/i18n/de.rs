@@ -0,0 +1,52 @@
+use crate::email_policy::EmailRejection;
+use crate::i18n::Message;
+
+pub(super) fn translate(message: &Message<'_>) -> String {
+    match message {
+        Message::ConfirmationEmailSubject => "Willkommen!".into(),
+        Message::ConfirmationEmailHtml { confirmation_link } => format!(
+            "Willkommen bei unserem Newsletter!<br />Klicken Sie <a href=\"{}\">hier</a>, um Ihr Abonnement zu bestätigen.",
+            confirmation_link
+        ),
+        Message::ConfirmationEmailText { confirmation_link } => format!(
+            "Willkommen bei unserem Newsletter!\nBesuchen Sie {}, um Ihr Abonnement zu bestätigen.",
+            confirmation_link
+        ),
+        Message::ListInvitationEmailSubject => "Einer weiteren Liste beitreten".into(),
+        Message::ListInvitationEmailHtml { invitation_link } => format!(
+            "Jemand möchte diese Adresse einer weiteren unserer Listen hinzufügen.<br />Klicken Sie <a href=\"{}\">hier</a>, um beizutreten, oder ignorieren Sie diese E-Mail.",
+            invitation_link
+        ),
+        Message::ListInvitationEmailText { invitation_link } => format!(
+            "Jemand möchte diese Adresse einer weiteren unserer Listen hinzufügen.\nBesuchen Sie {}, um beizutreten, oder ignorieren Sie diese E-Mail.",
+            invitation_link
+        ),
+        Message::SubscriptionConfirmed => "Abonnement bestätigt".into(),
+        Message::SubscriptionConfirmedDetails => {
+            "Vielen Dank, Ihr Abonnement ist bestätigt.".into()
+        }
+        Message::InvalidConfirmationLink => {
+            "Dieser Bestätigungslink ist ungültig oder abgelaufen.".into()
+        }
+        Message::PreferencesTitle => "Abonnement-Einstellungen".into(),
+        Message::PreferencesSaved => "Ihre Einstellungen wurden gespeichert.".into(),
+        Message::SavePreferences => "Speichern".into(),
+        Message::ManagePreferences => "Abonnement-Einstellungen verwalten".into(),
+        Message::InvalidName { name } => format!("{} ist kein gültiger Name", name),
+        Message::InvalidEmail { email } => {
+            format!("{} ist keine gültige E-Mail-Adresse", email)
+        }
+        Message::EmailRejected(EmailRejection::Disposable { domain }) => format!(
+            "{} ist ein Wegwerf-E-Mail-Anbieter, bitte verwenden Sie eine dauerhafte Adresse",
+            domain
+        ),
+        Message::EmailRejected(EmailRejection::Misspelled { email, suggestion }) => format!(
+            "{} scheint falsch geschrieben zu sein. Meinten Sie {}?",
+            email, suggestion
+        ),
+        Message::EmailRejected(EmailRejection::Undeliverable { domain }) => format!(
+            "{} nimmt keine E-Mails an, bitte überprüfen Sie die Adresse",
+            domain
+        ),
+    }
+}
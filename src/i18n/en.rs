@@ -0,0 +1,38 @@
+use crate::i18n::Message;
+
+pub(super) fn translate(message: &Message<'_>) -> String {
+    match message {
+        Message::ConfirmationEmailSubject => "Welcome!".into(),
+        Message::ConfirmationEmailHtml { confirmation_link } => format!(
+            "Welcome to our newsletter!<br />Click <a href=\"{}\">here</a> to confirm your subscription.",
+            confirmation_link
+        ),
+        Message::ConfirmationEmailText { confirmation_link } => format!(
+            "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
+            confirmation_link
+        ),
+        Message::ListInvitationEmailSubject => "Join another list".into(),
+        Message::ListInvitationEmailHtml { invitation_link } => format!(
+            "Somebody asked to add this address to another of our lists.<br />Click <a href=\"{}\">here</a> to join it, or ignore this email.",
+            invitation_link
+        ),
+        Message::ListInvitationEmailText { invitation_link } => format!(
+            "Somebody asked to add this address to another of our lists.\nVisit {} to join it, or ignore this email.",
+            invitation_link
+        ),
+        Message::SubscriptionConfirmed => "Subscription confirmed".into(),
+        Message::SubscriptionConfirmedDetails => {
+            "Thank you, your subscription is confirmed.".into()
+        }
+        Message::InvalidConfirmationLink => {
+            "This confirmation link is invalid or has expired.".into()
+        }
+        Message::PreferencesTitle => "Subscription preferences".into(),
+        Message::PreferencesSaved => "Your preferences have been saved.".into(),
+        Message::SavePreferences => "Save".into(),
+        Message::ManagePreferences => "Manage your subscription preferences".into(),
+        Message::InvalidName { name } => format!("{} is not a valid subscriber name", name),
+        Message::InvalidEmail { email } => format!("{} is not a valid subscriber email", email),
+        Message::EmailRejected(rejection) => rejection.to_string(),
+    }
+}
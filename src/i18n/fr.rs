@@ -0,0 +1,50 @@
+use crate::email_policy::EmailRejection;
+use crate::i18n::Message;
+
+pub(super) fn translate(message: &Message<'_>) -> String {
+    match message {
+        Message::ConfirmationEmailSubject => "Bienvenue !".into(),
+        Message::ConfirmationEmailHtml { confirmation_link } => format!(
+            "Bienvenue dans notre newsletter !<br />Cliquez <a href=\"{}\">ici</a> pour confirmer votre abonnement.",
+            confirmation_link
+        ),
+        Message::ConfirmationEmailText { confirmation_link } => format!(
+            "Bienvenue dans notre newsletter !\nRendez-vous sur {} pour confirmer votre abonnement.",
+            confirmation_link
+        ),
+        Message::ListInvitationEmailSubject => "Rejoindre une autre liste".into(),
+        Message::ListInvitationEmailHtml { invitation_link } => format!(
+            "Quelqu'un a demandé d'ajouter cette adresse à une autre de nos listes.<br />Cliquez <a href=\"{}\">ici</a> pour la rejoindre, ou ignorez cet e-mail.",
+            invitation_link
+        ),
+        Message::ListInvitationEmailText { invitation_link } => format!(
+            "Quelqu'un a demandé d'ajouter cette adresse à une autre de nos listes.\nRendez-vous sur {} pour la rejoindre, ou ignorez cet e-mail.",
+            invitation_link
+        ),
+        Message::SubscriptionConfirmed => "Abonnement confirmé".into(),
+        Message::SubscriptionConfirmedDetails => "Merci, votre abonnement est confirmé.".into(),
+        Message::InvalidConfirmationLink => {
+            "Ce lien de confirmation est invalide ou a expiré.".into()
+        }
+        Message::PreferencesTitle => "Préférences d'abonnement".into(),
+        Message::PreferencesSaved => "Vos préférences ont été enregistrées.".into(),
+        Message::SavePreferences => "Enregistrer".into(),
+        Message::ManagePreferences => "Gérer vos préférences d'abonnement".into(),
+        Message::InvalidName { name } => format!("{} n'est pas un nom valide", name),
+        Message::InvalidEmail { email } => {
+            format!("{} n'est pas une adresse e-mail valide", email)
+        }
+        Message::EmailRejected(EmailRejection::Disposable { domain }) => format!(
+            "{} est un fournisseur d'adresses jetables, veuillez utiliser une adresse permanente",
+            domain
+        ),
+        Message::EmailRejected(EmailRejection::Misspelled { email, suggestion }) => format!(
+            "{} semble mal orthographiée. Vouliez-vous dire {} ?",
+            email, suggestion
+        ),
+        Message::EmailRejected(EmailRejection::Undeliverable { domain }) => format!(
+            "{} n'accepte pas d'e-mails, veuillez vérifier l'adresse",
+            domain
+        ),
+    }
+}
@@ -0,0 +1,158 @@
+mod de;
+mod en;
+mod fr;
+
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::http::request::Parts;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::email_policy::EmailRejection;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Fr,
+}
+
+impl Locale {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+        }
+    }
+
+    // Only the primary language subtag is considered, so `de-AT` is German.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let language = tag.trim().split(['-', '_']).next()?.to_lowercase();
+        match language.as_str() {
+            "en" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            "fr" => Some(Locale::Fr),
+            _ => None,
+        }
+    }
+
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut ranges: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |quality| quality.trim().parse().ok())?;
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ranges.into_iter().find_map(|(tag, _)| Locale::parse(tag))
+    }
+}
+
+// The locale preferred by the browser, if it is one we have a catalogue for.
+pub struct AcceptLanguage(pub Option<Locale>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AcceptLanguage {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(AcceptLanguage(
+            parts
+                .headers
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(Locale::from_accept_language),
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub enum Message<'a> {
+    ConfirmationEmailSubject,
+    ConfirmationEmailHtml { confirmation_link: &'a str },
+    ConfirmationEmailText { confirmation_link: &'a str },
+    ListInvitationEmailSubject,
+    ListInvitationEmailHtml { invitation_link: &'a str },
+    ListInvitationEmailText { invitation_link: &'a str },
+    SubscriptionConfirmed,
+    SubscriptionConfirmedDetails,
+    InvalidConfirmationLink,
+    PreferencesTitle,
+    PreferencesSaved,
+    SavePreferences,
+    ManagePreferences,
+    InvalidName { name: &'a str },
+    InvalidEmail { email: &'a str },
+    EmailRejected(&'a EmailRejection),
+}
+
+// Every catalogue matches on all messages, so a message cannot be added without
+// translating it.
+pub fn translate(locale: Locale, message: &Message<'_>) -> String {
+    match locale {
+        Locale::En => en::translate(message),
+        Locale::De => de::translate(message),
+        Locale::Fr => fr::translate(message),
+    }
+}
+
+#[tracing::instrument(name = "Get the locale of a subscriber", skip(executor))]
+pub async fn get_subscriber_locale(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Locale, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT locale FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row
+        .and_then(|row| Locale::parse(&row.locale))
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_none, assert_some_eq};
+
+    use crate::i18n::*;
+
+    #[test]
+    fn region_subtags_are_ignored() {
+        assert_some_eq!(Locale::parse("de-AT"), Locale::De);
+        assert_some_eq!(Locale::parse("FR_ca"), Locale::Fr);
+        assert_none!(Locale::parse("nl"));
+    }
+
+    #[test]
+    fn the_supported_language_with_the_highest_quality_is_preferred() {
+        assert_some_eq!(
+            Locale::from_accept_language("nl-NL, fr;q=0.5, de-CH;q=0.8, en;q=0.1"),
+            Locale::De
+        );
+        assert_some_eq!(Locale::from_accept_language("*, fr"), Locale::Fr);
+        assert_none!(Locale::from_accept_language("de;q=0, nl"));
+    }
+
+    #[test]
+    fn messages_are_translated() {
+        let message = Message::InvalidName { name: "}{" };
+        assert_eq!(
+            translate(Locale::En, &message),
+            "}{ is not a valid subscriber name"
+        );
+        assert_eq!(translate(Locale::De, &message), "}{ ist kein gültiger Name");
+    }
+}
@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres, QueryBuilder};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+    custom_fields::get_field_types,
+    domain::SubscriberEmail,
+    email_client::{EmailClient, EmailKind},
+    health::Heartbeat,
+    i18n::{translate, Locale, Message},
+    preferences::preferences_link,
+    segments::Segment,
+    startup::HmacSecret,
+    suppression::is_suppressed,
+    tracking::add_tracking,
+};
+
+// How often an issue being delivered is touched, see `scheduler.issue_lease_in_seconds`.
+pub const ISSUE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+struct NewsletterIssue {
+    title: String,
+    html_content: String,
+    tracking_enabled: bool,
+    segment_query: Option<String>,
+}
+
+struct IssueVariant {
+    title: String,
+    html_content: String,
+}
+
+struct QueuedDelivery {
+    subscriber_id: Uuid,
+    email: String,
+    normalised_email: String,
+    locale: String,
+}
+
+// The `heartbeat_at` a worker last wrote to the issue it delivers. Once the lease has run out
+// and another worker has reclaimed the issue, it no longer matches, so the first worker can
+// neither touch nor finish the issue any more.
+#[derive(Debug, Clone, Copy)]
+pub struct IssueLease {
+    pub newsletter_issue_id: Uuid,
+    pub heartbeat_at: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeliveryOutcome {
+    Sent,
+    // The application is shutting down; the issue was requeued after the email in flight.
+    Interrupted,
+}
+
+// `heartbeat` is touched after every delivery, so that a worker sending to a long list is
+// not mistaken for a stalled one. The issue itself is touched every
+// `ISSUE_HEARTBEAT_INTERVAL`, so that no other replica reclaims it.
+#[tracing::instrument(
+    name = "Deliver newsletter issue",
+    skip(pool, email_client, access_url, hmac_secret, heartbeat, shutdown)
+)]
+pub async fn deliver_issue(
+    pool: &Pool<Postgres>,
+    email_client: &EmailClient,
+    access_url: &str,
+    hmac_secret: &HmacSecret,
+    mut lease: IssueLease,
+    heartbeat: Option<&Heartbeat>,
+    shutdown: &CancellationToken,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    let result = send_issue_to_confirmed_subscribers(
+        pool,
+        email_client,
+        access_url,
+        hmac_secret,
+        &mut lease,
+        heartbeat,
+        shutdown,
+    )
+    .await;
+
+    let updated = match result {
+        Ok(DeliveryOutcome::Interrupted) => requeue_issue(pool, &lease)
+            .await
+            .context("Failed to requeue an interrupted newsletter issue")?,
+        Ok(DeliveryOutcome::Sent) => finish_issue(pool, &lease, "sent")
+            .await
+            .context("Failed to record the final status of a newsletter issue")?,
+        Err(_) => finish_issue(pool, &lease, "failed")
+            .await
+            .context("Failed to record the final status of a newsletter issue")?,
+    };
+    if !updated {
+        tracing::warn!(
+            newsletter_issue_id = %lease.newsletter_issue_id,
+            "Leaving the status of a newsletter issue to the worker which reclaimed it",
+        );
+    }
+
+    result
+}
+
+async fn send_issue_to_confirmed_subscribers(
+    pool: &Pool<Postgres>,
+    email_client: &EmailClient,
+    access_url: &str,
+    hmac_secret: &HmacSecret,
+    lease: &mut IssueLease,
+    heartbeat: Option<&Heartbeat>,
+    shutdown: &CancellationToken,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    let newsletter_issue_id = lease.newsletter_issue_id;
+    let issue = get_issue(pool, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve a newsletter issue")?;
+    let variants = get_issue_variants(pool, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the variants of a newsletter issue")?;
+    let segment = match &issue.segment_query {
+        Some(query) => {
+            let field_types = get_field_types(pool)
+                .await
+                .context("Failed to retrieve custom field types")?;
+            Some(Segment::parse(query, &field_types)?)
+        }
+        None => None,
+    };
+    enqueue_confirmed_subscribers(pool, newsletter_issue_id, segment.as_ref())
+        .await
+        .context("Failed to enqueue deliveries for confirmed subscribers")?;
+    let queued_deliveries = get_queued_deliveries(pool, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve queued deliveries")?;
+
+    let mut touched_at = Instant::now();
+    for delivery in queued_deliveries {
+        if shutdown.is_cancelled() {
+            return Ok(DeliveryOutcome::Interrupted);
+        }
+        if let Some(heartbeat) = heartbeat {
+            heartbeat.beat();
+        }
+        if touched_at.elapsed() >= ISSUE_HEARTBEAT_INTERVAL {
+            lease.heartbeat_at = touch_issue(pool, lease)
+                .await
+                .context("Failed to touch a newsletter issue")?
+                .ok_or_else(|| anyhow!("The newsletter issue was reclaimed by another worker"))?;
+            touched_at = Instant::now();
+        }
+
+        let email = match SubscriberEmail::parse(delivery.email) {
+            Ok(email) => email,
+            Err(error) => {
+                tracing::warn!(
+                    error.cause_chain = ?error,
+                    "Skipping a confirmed subscriber. There stored contact details are invalid",
+                );
+                mark_delivery_as_failed(pool, newsletter_issue_id, delivery.subscriber_id, &error)
+                    .await
+                    .context("Failed to record a failed delivery")?;
+                continue;
+            }
+        };
+
+        if is_suppressed(pool, hmac_secret, &delivery.normalised_email)
+            .await
+            .context("Failed to check the suppression list")?
+        {
+            mark_delivery_as_skipped(pool, newsletter_issue_id, delivery.subscriber_id)
+                .await
+                .context("Failed to record a skipped delivery")?;
+            continue;
+        }
+
+        let locale = Locale::parse(&delivery.locale).unwrap_or_default();
+        let (title, issue_html_content) = match variants.get(&locale) {
+            Some(variant) => (&variant.title, &variant.html_content),
+            None => (&issue.title, &issue.html_content),
+        };
+        let html_content = match issue.tracking_enabled {
+            true => add_tracking(
+                issue_html_content,
+                access_url,
+                hmac_secret,
+                newsletter_issue_id,
+                delivery.subscriber_id,
+            ),
+            false => issue_html_content.clone(),
+        };
+        let preferences_link = preferences_link(access_url, hmac_secret, delivery.subscriber_id);
+        let manage_preferences = translate(locale, &Message::ManagePreferences);
+        let html_content = add_footer(
+            html_content,
+            &format!(
+                "<p><a href=\"{}\">{}</a></p>",
+                preferences_link, manage_preferences
+            ),
+        );
+        let text_content = format!(
+            "{}\n\n{}: {}",
+            issue_html_content, manage_preferences, preferences_link
+        );
+
+        match email_client
+            .send_email(
+                EmailKind::Newsletter,
+                &email,
+                title,
+                &html_content,
+                &text_content,
+            )
+            .await
+        {
+            Ok(sent_email) => mark_delivery_as_sent(
+                pool,
+                newsletter_issue_id,
+                delivery.subscriber_id,
+                sent_email.message_id.as_deref(),
+            )
+            .await
+            .context("Failed to record a sent delivery")?,
+            Err(error) => {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    "Failed to send newsletter issue to {}",
+                    email,
+                );
+                mark_delivery_as_failed(
+                    pool,
+                    newsletter_issue_id,
+                    delivery.subscriber_id,
+                    &error.to_string(),
+                )
+                .await
+                .context("Failed to record a failed delivery")?;
+            }
+        }
+    }
+
+    Ok(DeliveryOutcome::Sent)
+}
+
+fn add_footer(mut html: String, footer: &str) -> String {
+    match html.rfind("</body>") {
+        Some(position) => html.insert_str(position, footer),
+        None => html.push_str(footer),
+    }
+    html
+}
+
+#[tracing::instrument(name = "Get newsletter issue", skip(pool))]
+async fn get_issue(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+            SELECT title, html_content, tracking_enabled, segment_query
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get newsletter issue variants", skip(pool))]
+async fn get_issue_variants(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<HashMap<Locale, IssueVariant>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT locale, title, html_content
+            FROM newsletter_issue_variants
+            WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let variant = IssueVariant {
+                title: row.title,
+                html_content: row.html_content,
+            };
+            Some((Locale::parse(&row.locale)?, variant))
+        })
+        .collect())
+}
+
+// Returns the new heartbeat, or `None` when the lease was lost.
+#[tracing::instrument(name = "Touch newsletter issue", skip(pool))]
+async fn touch_issue(
+    pool: &Pool<Postgres>,
+    lease: &IssueLease,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET heartbeat_at = now()
+            WHERE newsletter_issue_id = $1 AND status = 'sending' AND heartbeat_at = $2
+            RETURNING heartbeat_at AS "heartbeat_at!"
+        "#,
+        lease.newsletter_issue_id,
+        lease.heartbeat_at,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.heartbeat_at))
+}
+
+// Returns whether the issue was still held under `lease`.
+#[tracing::instrument(name = "Finish newsletter issue", skip(pool))]
+async fn finish_issue(
+    pool: &Pool<Postgres>,
+    lease: &IssueLease,
+    status: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $3, finished_at = now()
+            WHERE newsletter_issue_id = $1 AND status = 'sending' AND heartbeat_at = $2
+        "#,
+        lease.newsletter_issue_id,
+        lease.heartbeat_at,
+        status,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// The deliveries which were not sent stay queued, so the scheduler picks the issue up again
+// and continues where it stopped.
+#[tracing::instrument(name = "Requeue newsletter issue", skip(pool))]
+async fn requeue_issue(pool: &Pool<Postgres>, lease: &IssueLease) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = 'scheduled', send_at = now()
+            WHERE newsletter_issue_id = $1 AND status = 'sending' AND heartbeat_at = $2
+        "#,
+        lease.newsletter_issue_id,
+        lease.heartbeat_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(
+    name = "Enqueue deliveries for confirmed subscribers",
+    skip(pool, segment)
+)]
+async fn enqueue_confirmed_subscribers(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+    segment: Option<&Segment>,
+) -> Result<(), sqlx::Error> {
+    let mut builder = QueryBuilder::new(
+        r#"
+            INSERT INTO deliveries (newsletter_issue_id, subscriber_id, status, queued_at)
+            SELECT DISTINCT l.newsletter_issue_id, s.id, 'queued', now()
+            FROM subscriptions s
+            JOIN list_memberships m ON m.subscriber_id = s.id
+            JOIN newsletter_issue_lists l ON l.list_id = m.list_id
+            WHERE s.status = 'confirmed'
+                AND m.status = 'subscribed'
+                AND l.newsletter_issue_id =
+        "#,
+    );
+    builder.push_bind(newsletter_issue_id);
+    if let Some(segment) = segment {
+        builder.push(" AND ");
+        segment.push_sql(&mut builder);
+    }
+    builder.push(" ON CONFLICT DO NOTHING");
+
+    builder.build().execute(pool).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get queued deliveries", skip(pool))]
+async fn get_queued_deliveries(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<QueuedDelivery>, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedDelivery,
+        r#"
+            SELECT d.subscriber_id, s.email, s.normalised_email, s.locale
+            FROM deliveries d
+            JOIN subscriptions s ON s.id = d.subscriber_id
+            WHERE d.newsletter_issue_id = $1 AND d.status = 'queued'
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Mark delivery as sent", skip(pool))]
+async fn mark_delivery_as_sent(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    provider_message_id: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE deliveries
+            SET status = 'sent', provider_message_id = $3, sent_at = now()
+            WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+        provider_message_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Mark delivery as failed", skip(pool))]
+async fn mark_delivery_as_failed(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE deliveries
+            SET status = 'failed', error = $3, failed_at = now()
+            WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+        error,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Mark delivery as skipped", skip(pool))]
+async fn mark_delivery_as_skipped(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE deliveries
+            SET status = 'skipped'
+            WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -1,7 +1,31 @@
 pub mod authentication;
+pub mod bot_protection;
 pub mod configuration;
+pub mod confirmation_queue;
+pub mod consent;
+pub mod custom_fields;
+pub mod data_requests;
+pub mod deliverability;
 pub mod domain;
 pub mod email_client;
+pub mod email_normalisation;
+pub mod email_policy;
+pub mod health;
+pub mod i18n;
+pub mod issue_delivery;
+pub mod lists;
+pub mod metrics;
+pub mod migrations;
+pub mod preferences;
+pub mod problem;
+pub mod request_id;
 pub mod routes;
+pub mod scheduler;
+pub mod segments;
+pub mod signature;
 pub mod startup;
+pub mod subscriber_export;
+pub mod subscriber_import;
+pub mod suppression;
 pub mod telemetry;
+pub mod tracking;
@@ -0,0 +1,114 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+pub const DEFAULT_LIST: &str = "newsletter";
+
+#[derive(Serialize)]
+pub struct List {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct ListMembership {
+    pub list_id: Uuid,
+    pub name: String,
+    pub subscribed: bool,
+}
+
+#[tracing::instrument(name = "Get mailing list by slug", skip(executor))]
+pub async fn get_list_id(
+    executor: impl PgExecutor<'_>,
+    slug: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!("SELECT list_id FROM lists WHERE slug = $1", slug)
+        .fetch_optional(executor)
+        .await?;
+
+    Ok(row.map(|r| r.list_id))
+}
+
+#[tracing::instrument(name = "List mailing lists", skip(executor))]
+pub async fn list_lists(executor: impl PgExecutor<'_>) -> Result<Vec<List>, sqlx::Error> {
+    sqlx::query_as!(
+        List,
+        "SELECT list_id, slug, name, created_at FROM lists ORDER BY slug"
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[tracing::instrument(name = "Create mailing list", skip(executor))]
+pub async fn create_list(
+    executor: impl PgExecutor<'_>,
+    slug: &str,
+    name: &str,
+) -> Result<Option<List>, sqlx::Error> {
+    sqlx::query_as!(
+        List,
+        r#"
+            INSERT INTO lists (list_id, slug, name, created_at)
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT (slug) DO NOTHING
+            RETURNING list_id, slug, name, created_at
+        "#,
+        Uuid::new_v4(),
+        slug,
+        name,
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// Returns false when the membership already had the given status.
+#[tracing::instrument(name = "Update mailing list membership", skip(executor))]
+pub async fn set_membership(
+    executor: impl PgExecutor<'_>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    subscribed: bool,
+) -> Result<bool, sqlx::Error> {
+    let status = match subscribed {
+        true => "subscribed",
+        false => "unsubscribed",
+    };
+
+    let result = sqlx::query!(
+        r#"
+            INSERT INTO list_memberships (list_id, subscriber_id, status, updated_at)
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT (list_id, subscriber_id)
+            DO UPDATE SET status = EXCLUDED.status, updated_at = EXCLUDED.updated_at
+            WHERE list_memberships.status <> EXCLUDED.status
+        "#,
+        list_id,
+        subscriber_id,
+        status,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Get mailing list memberships of a subscriber", skip(executor))]
+pub async fn get_memberships(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListMembership>, sqlx::Error> {
+    sqlx::query_as!(
+        ListMembership,
+        r#"
+            SELECT l.list_id, l.name, COALESCE(m.status = 'subscribed', FALSE) AS "subscribed!"
+            FROM lists l
+            LEFT JOIN list_memberships m ON m.list_id = l.list_id AND m.subscriber_id = $1
+            ORDER BY l.slug
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await
+}
@@ -1,18 +1,48 @@
 use newsletter::{
     configuration::get_configuration,
-    startup::{get_app_state, get_listener, run},
-    telemetry::{get_subscriber, initialize_subscriber},
+    migrations::prepare_database,
+    startup::{get_app_state, get_listener, get_metrics_listener, run},
+    telemetry::{get_subscriber, initialize_subscriber, otlp_tracer},
 };
 
 #[tokio::main]
 async fn main() {
-    let subscriber = get_subscriber("newsletter".into(), "info".into(), std::io::stdout);
-    initialize_subscriber(subscriber);
+    let configuration = match get_configuration() {
+        Ok(configuration) => configuration,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
 
-    let configuration = get_configuration().expect("Failed to read configuration");
+    let tracer = match &configuration.telemetry.otlp {
+        Some(otlp) => match otlp_tracer("newsletter", otlp) {
+            Ok(tracer) => Some(tracer),
+            Err(error) => {
+                eprintln!("Failed to set up the OTLP exporter: {}", error);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let subscriber = get_subscriber("newsletter".into(), "info".into(), std::io::stdout, tracer);
+    initialize_subscriber(subscriber);
 
     let listener = get_listener(&configuration).await;
+    let metrics_listener = get_metrics_listener(&configuration).await;
     let app_state = get_app_state(&configuration).await;
 
-    run(listener, app_state).await
+    let run_migrations =
+        configuration.application.run_migrations || std::env::args().any(|arg| arg == "--migrate");
+    if let Err(error) =
+        prepare_database(&app_state.pool, run_migrations, &app_state.hmac_secret).await
+    {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+
+    run(listener, metrics_listener, app_state).await;
+
+    // Flushes the spans which are still batched.
+    opentelemetry::global::shutdown_tracer_provider();
 }
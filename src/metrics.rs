@@ -0,0 +1,181 @@
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use prometheus::{
+    core::Collector, Encoder, Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sqlx::{Pool, Postgres};
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    pub emails: IntCounterVec,
+    db_pool_connections: IntGaugeVec,
+    db_pool_max_connections: IntGauge,
+    subscribers: IntGaugeVec,
+    delivery_queue_depth: IntGauge,
+    delivery_queue_oldest_age: Gauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let metrics = Self {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests handled"),
+                &["method", "path", "status"],
+            )
+            .unwrap(),
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "Time taken to handle HTTP requests",
+                ),
+                &["method", "path", "status"],
+            )
+            .unwrap(),
+            emails: IntCounterVec::new(
+                Opts::new("emails_total", "Emails handed to the email provider"),
+                &["transport", "kind", "outcome"],
+            )
+            .unwrap(),
+            db_pool_connections: IntGaugeVec::new(
+                Opts::new("db_pool_connections", "Open database connections"),
+                &["state"],
+            )
+            .unwrap(),
+            db_pool_max_connections: IntGauge::new(
+                "db_pool_max_connections",
+                "Maximum number of database connections",
+            )
+            .unwrap(),
+            subscribers: IntGaugeVec::new(
+                Opts::new("subscribers", "Subscribers by status"),
+                &["status"],
+            )
+            .unwrap(),
+            delivery_queue_depth: IntGauge::new(
+                "delivery_queue_depth",
+                "Newsletter deliveries waiting to be sent",
+            )
+            .unwrap(),
+            delivery_queue_oldest_age: Gauge::new(
+                "delivery_queue_oldest_age_seconds",
+                "Age of the oldest newsletter delivery waiting to be sent",
+            )
+            .unwrap(),
+            registry,
+        };
+
+        let collectors: Vec<Box<dyn Collector>> = vec![
+            Box::new(metrics.http_requests.clone()),
+            Box::new(metrics.http_request_duration.clone()),
+            Box::new(metrics.emails.clone()),
+            Box::new(metrics.db_pool_connections.clone()),
+            Box::new(metrics.db_pool_max_connections.clone()),
+            Box::new(metrics.subscribers.clone()),
+            Box::new(metrics.delivery_queue_depth.clone()),
+            Box::new(metrics.delivery_queue_oldest_age.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector).unwrap();
+        }
+
+        metrics
+    }
+
+    // The gauges backed by the database are refreshed on every scrape, and keep their last
+    // values when the database can't be reached.
+    pub async fn render(&self, pool: &Pool<Postgres>) -> String {
+        let idle = pool.num_idle() as i64;
+        self.db_pool_connections
+            .with_label_values(&["idle"])
+            .set(idle);
+        self.db_pool_connections
+            .with_label_values(&["in_use"])
+            .set(i64::from(pool.size()) - idle);
+        self.db_pool_max_connections
+            .set(i64::from(pool.options().get_max_connections()));
+
+        if let Err(error) = self.refresh_from_database(pool).await {
+            tracing::warn!(error.cause_chain = ?error, "Failed to refresh the database metrics");
+        }
+
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Failed to encode the metrics");
+
+        String::from_utf8(buffer).expect("Metrics are not valid UTF-8")
+    }
+
+    async fn refresh_from_database(&self, pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+        let subscribers = sqlx::query!(
+            r#"SELECT status, COUNT(*) AS "count!" FROM subscriptions GROUP BY status"#
+        )
+        .fetch_all(pool)
+        .await?;
+        let queue = sqlx::query!(
+            r#"
+                SELECT
+                    COUNT(*) AS "depth!",
+                    EXTRACT(EPOCH FROM now() - MIN(queued_at))::float8 AS oldest_age
+                FROM deliveries
+                WHERE status = 'queued'
+            "#
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.subscribers.reset();
+        for row in subscribers {
+            self.subscribers
+                .with_label_values(&[&row.status])
+                .set(row.count);
+        }
+        self.delivery_queue_depth.set(queue.depth);
+        self.delivery_queue_oldest_age
+            .set(queue.oldest_age.unwrap_or(0.0));
+
+        Ok(())
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Requests are labelled with the route they matched rather than their path, so that ids in
+// paths don't create a series per resource.
+pub async fn track_http_metrics(
+    State(metrics): State<Metrics>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started_at = Instant::now();
+    let method = request.method().to_string();
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".into());
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [method.as_str(), path.as_str(), status.as_str()];
+    metrics.http_requests.with_label_values(&labels).inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(started_at.elapsed().as_secs_f64());
+
+    response
+}
@@ -0,0 +1,124 @@
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::{PgConnection, Pool, Postgres};
+
+use crate::startup::HmacSecret;
+use crate::suppression::key_legacy_email_hashes;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+// Every replica takes the same session lock, so only one of them checks and migrates the
+// schema at a time while the others wait.
+const MIGRATION_LOCK_KEY: i64 = 0x6e65_7773_6c65_7474;
+
+#[derive(thiserror::Error, Debug)]
+pub enum MigrationError {
+    #[error("The database has migrations this binary doesn't know about: {0:?}")]
+    UnknownMigrations(Vec<i64>),
+    #[error(transparent)]
+    Migrate(#[from] MigrateError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+// Refuses to run against a schema newer than the embedded migrations and, when `apply` is
+// set, applies the pending ones together with the data changes which need the HMAC secret.
+#[tracing::instrument(name = "Prepare the database schema", skip(pool, hmac_secret))]
+pub async fn prepare_database(
+    pool: &Pool<Postgres>,
+    apply: bool,
+    hmac_secret: &HmacSecret,
+) -> Result<(), MigrationError> {
+    let mut connection = pool.acquire().await?;
+
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *connection)
+        .await?;
+    let result = check_and_apply(&mut connection, apply, hmac_secret).await;
+    let unlocked = sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *connection)
+        .await;
+
+    if unlocked.is_err() {
+        // Closing the session is the only other way to release the lock.
+        let _ = connection.detach();
+    }
+
+    result?;
+    unlocked?;
+    Ok(())
+}
+
+async fn check_and_apply(
+    connection: &mut PgConnection,
+    apply: bool,
+    hmac_secret: &HmacSecret,
+) -> Result<(), MigrationError> {
+    let applied = applied_versions(connection).await?;
+    let unknown: Vec<i64> = applied
+        .iter()
+        .copied()
+        .filter(|version| {
+            !MIGRATOR
+                .iter()
+                .any(|migration| migration.version == *version)
+        })
+        .collect();
+    if !unknown.is_empty() {
+        return Err(MigrationError::UnknownMigrations(unknown));
+    }
+
+    let pending = MIGRATOR
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .count();
+    if !apply {
+        if pending > 0 {
+            tracing::warn!(
+                "The database is missing {} migrations, start with --migrate to apply them",
+                pending
+            );
+        }
+        return Ok(());
+    }
+
+    if pending > 0 {
+        tracing::info!("Applying {} pending migrations", pending);
+        MIGRATOR.run(&mut *connection).await?;
+    }
+    // Runs on every start, so that it is finished even when a replica stopped right after
+    // migrating.
+    key_legacy_email_hashes(connection, hmac_secret).await?;
+
+    Ok(())
+}
+
+async fn applied_versions(connection: &mut PgConnection) -> Result<Vec<i64>, sqlx::Error> {
+    if !has_migrations_table(&mut *connection).await? {
+        return Ok(vec![]);
+    }
+
+    sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+        .fetch_all(connection)
+        .await
+}
+
+async fn has_migrations_table(connection: &mut PgConnection) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(connection)
+        .await
+}
+
+// The version of the latest migration applied to the database, if any.
+pub async fn schema_version(pool: &Pool<Postgres>) -> Result<Option<i64>, sqlx::Error> {
+    let mut connection = pool.acquire().await?;
+    let applied = applied_versions(&mut connection).await?;
+
+    Ok(applied.last().copied())
+}
+
+// The version of the latest migration embedded in this binary.
+pub fn latest_version() -> Option<i64> {
+    MIGRATOR.iter().map(|migration| migration.version).max()
+}
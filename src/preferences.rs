@@ -0,0 +1,116 @@
+use uuid::Uuid;
+
+use crate::signature::{sign, verify, InvalidSignature};
+use crate::startup::HmacSecret;
+
+pub fn preferences_token(secret: &HmacSecret, subscriber_id: Uuid) -> String {
+    sign(secret, &format!("preferences:{}", subscriber_id))
+}
+
+pub fn verify_preferences_token(
+    secret: &HmacSecret,
+    token: &str,
+) -> Result<Uuid, InvalidSignature> {
+    verify(secret, token)?
+        .strip_prefix("preferences:")
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or(InvalidSignature)
+}
+
+pub fn preferences_link(access_url: &str, secret: &HmacSecret, subscriber_id: Uuid) -> String {
+    format!(
+        "{}/subscriptions/preferences?token={}",
+        access_url,
+        preferences_token(secret, subscriber_id)
+    )
+}
+
+// Lets an existing subscriber join a list somebody asked to add them to. The source of the
+// request is signed as well, so that it ends up in the consent record.
+pub fn list_invitation_token(
+    secret: &HmacSecret,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    source: &str,
+) -> String {
+    sign(
+        secret,
+        &format!("join:{}:{}:{}", subscriber_id, list_id, source),
+    )
+}
+
+// Returns the subscriber, the list and the source of the request.
+pub fn verify_list_invitation_token(
+    secret: &HmacSecret,
+    token: &str,
+) -> Result<(Uuid, Uuid, String), InvalidSignature> {
+    let payload = verify(secret, token)?;
+    let mut parts = payload
+        .strip_prefix("join:")
+        .ok_or(InvalidSignature)?
+        .splitn(3, ':');
+    let mut next_id = || {
+        parts
+            .next()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or(InvalidSignature)
+    };
+    let (subscriber_id, list_id) = (next_id()?, next_id()?);
+    let source = parts.next().ok_or(InvalidSignature)?;
+
+    Ok((subscriber_id, list_id, source.to_string()))
+}
+
+pub fn list_invitation_link(
+    access_url: &str,
+    secret: &HmacSecret,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    source: &str,
+) -> String {
+    format!(
+        "{}/subscriptions/join?token={}",
+        access_url,
+        list_invitation_token(secret, subscriber_id, list_id, source)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+
+    use crate::preferences::*;
+    use crate::signature::sign;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("super-secret".into()))
+    }
+
+    #[test]
+    fn preferences_token_is_verified() {
+        let subscriber_id = Uuid::new_v4();
+        let token = preferences_token(&secret(), subscriber_id);
+        assert_ok_eq!(verify_preferences_token(&secret(), &token), subscriber_id);
+    }
+
+    #[test]
+    fn tokens_signed_for_another_purpose_are_rejected() {
+        let token = sign(
+            &secret(),
+            &format!("o:{}:{}", Uuid::new_v4(), Uuid::new_v4()),
+        );
+        assert_err!(verify_preferences_token(&secret(), &token));
+    }
+
+    #[test]
+    fn list_invitation_token_is_verified() {
+        let (subscriber_id, list_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let token = list_invitation_token(&secret(), subscriber_id, list_id, "blog:footer");
+        assert_ok_eq!(
+            verify_list_invitation_token(&secret(), &token),
+            (subscriber_id, list_id, "blog:footer".to_string())
+        );
+        assert_err!(verify_preferences_token(&secret(), &token));
+    }
+}
@@ -0,0 +1,229 @@
+use axum::http::header::{CONTENT_TYPE, WWW_AUTHENTICATE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Serialize, Serializer};
+
+use crate::request_id::current_request_id;
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+// An RFC 7807 problem details body. `code` is stable and meant for clients to match on,
+// while `title` and `detail` are meant for humans and may change.
+#[derive(Serialize, Debug)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    #[serde(serialize_with = "serialize_status")]
+    status: StatusCode,
+    detail: String,
+    code: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+    request_id: Option<String>,
+    #[serde(skip)]
+    realm: Option<&'static str>,
+    #[serde(skip)]
+    cause_chain: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status,
+            detail: detail.into(),
+            code,
+            errors: vec![],
+            request_id: current_request_id().map(|request_id| request_id.to_string()),
+            realm: None,
+            cause_chain: None,
+        }
+    }
+
+    pub fn unauthorized(realm: &'static str) -> Self {
+        Self {
+            realm: Some(realm),
+            ..Self::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "Authentication failed",
+            )
+        }
+    }
+
+    // For signed links, e.g. in emails, which are invalid, expired or no longer point to anybody.
+    pub fn invalid_link() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_link",
+            "The link is invalid or has expired",
+        )
+    }
+
+    pub fn validation(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "validation_failed", detail)
+    }
+
+    pub fn invalid_fields(errors: Vec<FieldError>) -> Self {
+        let detail = errors
+            .iter()
+            .map(|error| error.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        Self {
+            errors,
+            ..Self::validation(detail)
+        }
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", detail)
+    }
+
+    pub fn conflict(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, "conflict", detail)
+    }
+
+    pub fn too_many_requests(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::TOO_MANY_REQUESTS, "rate_limited", detail)
+    }
+
+    // The cause chain only ends up in the logs, clients get the request id to report instead.
+    pub fn unexpected(error: &dyn std::error::Error) -> Self {
+        Self {
+            cause_chain: Some(CauseChain(error).to_string()),
+            ..Self::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Something went wrong",
+            )
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+impl From<anyhow::Error> for Problem {
+    fn from(error: anyhow::Error) -> Self {
+        Self::unexpected(error.as_ref())
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        if let Some(cause_chain) = &self.cause_chain {
+            tracing::error!(
+                error.cause_chain = %cause_chain,
+                error.code = self.code,
+                "Failed to handle the request"
+            );
+        }
+
+        let mut response = (self.status, Json(&self)).into_response();
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE));
+        if let Some(realm) = self.realm {
+            headers.insert(
+                WWW_AUTHENTICATE,
+                HeaderValue::from_str(&format!(r#"Basic realm="{}""#, realm)).unwrap(),
+            );
+        }
+
+        response
+    }
+}
+
+fn serialize_status<S: Serializer>(status: &StatusCode, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u16(status.as_u16())
+}
+
+struct CauseChain<'a>(&'a dyn std::error::Error);
+
+impl std::fmt::Display for CauseChain<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self.0, f)
+    }
+}
+
+pub fn error_chain_fmt(
+    e: &dyn std::error::Error,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "{}", e)?;
+    let mut current = e.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{}", cause)?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use crate::problem::{FieldError, Problem};
+
+    #[test]
+    fn problems_serialize_as_rfc_7807() {
+        let problem = Problem::conflict("Newsletter issue is already sent");
+
+        let body = serde_json::to_value(&problem).unwrap();
+
+        assert_eq!(body["type"], "about:blank");
+        assert_eq!(body["title"], "Conflict");
+        assert_eq!(body["status"], 409);
+        assert_eq!(body["detail"], "Newsletter issue is already sent");
+        assert_eq!(body["code"], "conflict");
+        assert!(body.get("errors").is_none());
+    }
+
+    #[test]
+    fn field_errors_are_listed_and_summarised_in_the_detail() {
+        let problem = Problem::invalid_fields(vec![
+            FieldError::new("name", "name is not valid"),
+            FieldError::new("email", "email is not valid"),
+        ]);
+
+        let body = serde_json::to_value(&problem).unwrap();
+
+        assert_eq!(problem.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "validation_failed");
+        assert_eq!(body["detail"], "name is not valid; email is not valid");
+        assert_eq!(body["errors"][1]["field"], "email");
+        assert_eq!(body["errors"][1]["message"], "email is not valid");
+    }
+
+    #[test]
+    fn unexpected_errors_do_not_leak_their_cause() {
+        let error = std::io::Error::other("connection refused");
+
+        let problem = Problem::unexpected(&error);
+        let body = serde_json::to_value(&problem).unwrap();
+
+        assert_eq!(body["status"], 500);
+        assert_eq!(body["code"], "internal_error");
+        assert!(!body.to_string().contains("connection refused"));
+    }
+}
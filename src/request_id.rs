@@ -0,0 +1,90 @@
+use axum::extract::Request;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: RequestId;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    // Incoming ids are echoed and logged, so only short ids made of safe characters are kept.
+    pub fn parse(s: &str) -> Option<Self> {
+        let is_valid = !s.is_empty()
+            && s.len() <= 128
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+
+        is_valid.then(|| Self(s.to_string()))
+    }
+
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for RequestId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// The id of the request being handled by the current task, if any.
+pub fn current_request_id() -> Option<RequestId> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+// Keeps a valid incoming `X-Request-Id` or generates one, makes it available to the handler
+// and echoes it in the response.
+pub async fn propagate_request_id(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(RequestId::parse)
+        .unwrap_or_else(RequestId::generate);
+    request.extensions_mut().insert(request_id.clone());
+
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .await;
+    response.headers_mut().insert(
+        REQUEST_ID_HEADER,
+        HeaderValue::from_str(request_id.as_ref()).unwrap(),
+    );
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_none, assert_some};
+
+    use crate::request_id::RequestId;
+
+    #[test]
+    fn request_ids_of_safe_characters_are_accepted() {
+        assert_some!(RequestId::parse("3f2b8c1e-8f4e-4b7a-9d55-0b7f6f0c2a11"));
+        assert_some!(RequestId::parse("lb.req_42:7"));
+    }
+
+    #[test]
+    fn empty_long_or_unsafe_request_ids_are_rejected() {
+        assert_none!(RequestId::parse(""));
+        assert_none!(RequestId::parse(&"a".repeat(129)));
+        assert_none!(RequestId::parse("abc def"));
+        assert_none!(RequestId::parse("abc\"<script>"));
+    }
+}
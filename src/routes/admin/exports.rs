@@ -0,0 +1,83 @@
+use anyhow::Context;
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+
+use crate::custom_fields::get_field_types;
+use crate::lists::get_list_id;
+use crate::routes::admin::{authenticate, AdminError};
+use crate::segments::{get_segment_query, Segment};
+use crate::subscriber_export::{export_subscribers, ExportFilter, ExportFormat};
+
+#[derive(Deserialize, Debug)]
+pub struct ExportParameters {
+    format: Option<ExportFormat>,
+    segment: Option<String>,
+    list: Option<String>,
+    status: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Exporting subscribers",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn export_subscriber_data(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Query(parameters): Query<ExportParameters>,
+) -> Result<Response, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let format = parameters.format.unwrap_or(ExportFormat::Csv);
+    let filter = ExportFilter {
+        status: parameters.status,
+        list_id: match parameters.list {
+            Some(slug) => Some(
+                get_list_id(&pool, &slug)
+                    .await
+                    .context("Failed to retrieve a mailing list")?
+                    .ok_or_else(|| {
+                        AdminError::ValidationError(format!("{} is not a known list", slug))
+                    })?,
+            ),
+            None => None,
+        },
+        segment: match parameters.segment {
+            Some(name) => Some(resolve_segment(&pool, &name).await?),
+            None => None,
+        },
+    };
+
+    let body = Body::from_stream(export_subscribers(pool, format, filter));
+    let headers = [
+        (CONTENT_TYPE, format.content_type().to_string()),
+        (
+            CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"subscribers.{}\"",
+                format.extension()
+            ),
+        ),
+    ];
+
+    Ok((headers, body).into_response())
+}
+
+async fn resolve_segment(pool: &Pool<Postgres>, name: &str) -> Result<Segment, AdminError> {
+    let query = get_segment_query(pool, name)
+        .await
+        .context("Failed to retrieve a segment")?
+        .ok_or_else(|| AdminError::ValidationError(format!("{} is not a known segment", name)))?;
+    let field_types = get_field_types(pool)
+        .await
+        .context("Failed to retrieve custom field types")?;
+
+    Segment::parse(&query, &field_types).map_err(|e| AdminError::ValidationError(e.to_string()))
+}
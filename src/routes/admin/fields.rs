@@ -0,0 +1,66 @@
+use anyhow::Context;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+
+use crate::custom_fields::{
+    create_custom_field, is_valid_field_name, list_custom_fields, CustomField, FieldType,
+};
+use crate::routes::admin::{authenticate, AdminError};
+
+#[derive(Deserialize, Debug)]
+pub struct CustomFieldData {
+    name: String,
+    #[serde(rename = "type")]
+    field_type: FieldType,
+}
+
+#[tracing::instrument(
+    name = "Listing custom fields",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn get_custom_fields(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+) -> Result<Json<Vec<CustomField>>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let fields = list_custom_fields(&pool)
+        .await
+        .context("Failed to list custom fields")?;
+
+    Ok(Json(fields))
+}
+
+#[tracing::instrument(
+    name = "Defining a custom field",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn add_custom_field(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Json(body): Json<CustomFieldData>,
+) -> Result<(StatusCode, Json<CustomField>), AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    if !is_valid_field_name(&body.name) {
+        return Err(AdminError::ValidationError(format!(
+            "{} is not a valid custom field name",
+            body.name
+        )));
+    }
+
+    let field = create_custom_field(&pool, &body.name, body.field_type)
+        .await
+        .context("Failed to define a custom field")?
+        .ok_or_else(|| AdminError::ValidationError(format!("{} already exists", body.name)))?;
+
+    Ok((StatusCode::CREATED, Json(field)))
+}
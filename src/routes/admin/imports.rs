@@ -0,0 +1,115 @@
+use anyhow::Context;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use futures_util::TryStreamExt;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+use crate::email_policy::EmailPolicy;
+use crate::lists::{get_list_id, DEFAULT_LIST};
+use crate::routes::admin::{authenticate, AdminError};
+use crate::startup::HmacSecret;
+use crate::subscriber_import::{
+    create_import, get_import_report, run_import, ImportError, ImportFormat, ImportOptions,
+    ImportReport, InitialStatus,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct ImportData {
+    format: ImportFormat,
+    status: InitialStatus,
+    consent_source: Option<String>,
+    list: Option<String>,
+}
+
+impl From<ImportError> for AdminError {
+    fn from(error: ImportError) -> Self {
+        match error {
+            ImportError::InvalidFile(message) => AdminError::ValidationError(message),
+            ImportError::UnexpectedError(error) => AdminError::UnexpectedError(error),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Creating a subscriber import",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn add_import(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Json(body): Json<ImportData>,
+) -> Result<(StatusCode, Json<ImportReport>), AdminError> {
+    let user_id = authenticate(authorization, &pool).await?;
+
+    let list = body.list.unwrap_or_else(|| DEFAULT_LIST.into());
+    let list_id = get_list_id(&pool, &list)
+        .await
+        .context("Failed to retrieve a mailing list")?
+        .ok_or_else(|| AdminError::ValidationError(format!("{} is not a known list", list)))?;
+    let options = ImportOptions {
+        format: body.format,
+        status: body.status,
+        consent_source: body.consent_source,
+        list_id,
+    };
+
+    let import_id = create_import(&pool, &options, Some(user_id)).await?;
+    let report = get_import_report(&pool, import_id)
+        .await
+        .context("Failed to retrieve the report of a subscriber import")?
+        .ok_or(AdminError::NotFound("Import"))?;
+
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+#[tracing::instrument(
+    name = "Uploading rows of a subscriber import",
+    skip(pool, email_policy, hmac_secret, authorization, body),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn upload_import_rows(
+    State(pool): State<Pool<Postgres>>,
+    State(email_policy): State<EmailPolicy>,
+    State(hmac_secret): State<HmacSecret>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(import_id): Path<Uuid>,
+    body: Body,
+) -> Result<Json<ImportReport>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let reader = StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+    let report = run_import(&pool, &email_policy, &hmac_secret, import_id, reader)
+        .await?
+        .ok_or(AdminError::NotFound("Import"))?;
+
+    Ok(Json(report))
+}
+
+#[tracing::instrument(
+    name = "Getting the report of a subscriber import",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn get_import(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(import_id): Path<Uuid>,
+) -> Result<Json<ImportReport>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let report = get_import_report(&pool, import_id)
+        .await
+        .context("Failed to retrieve the report of a subscriber import")?
+        .ok_or(AdminError::NotFound("Import"))?;
+
+    Ok(Json(report))
+}
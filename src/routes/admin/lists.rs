@@ -0,0 +1,72 @@
+use anyhow::Context;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+
+use crate::lists::{create_list, list_lists, List};
+use crate::routes::admin::{authenticate, AdminError};
+
+#[derive(Deserialize, Debug)]
+pub struct ListData {
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(
+    name = "Listing mailing lists",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn get_lists(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+) -> Result<Json<Vec<List>>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let lists = list_lists(&pool)
+        .await
+        .context("Failed to list mailing lists")?;
+
+    Ok(Json(lists))
+}
+
+#[tracing::instrument(
+    name = "Creating a mailing list",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn add_list(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Json(body): Json<ListData>,
+) -> Result<(StatusCode, Json<List>), AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let is_valid_slug = !body.slug.is_empty()
+        && body.slug.len() <= 64
+        && body
+            .slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !is_valid_slug {
+        return Err(AdminError::ValidationError(format!(
+            "{} is not a valid list slug",
+            body.slug
+        )));
+    }
+    if body.name.trim().is_empty() {
+        return Err(AdminError::ValidationError("name must not be empty".into()));
+    }
+
+    let list = create_list(&pool, &body.slug, body.name.trim())
+        .await
+        .context("Failed to create a mailing list")?
+        .ok_or_else(|| AdminError::ValidationError(format!("{} already exists", body.slug)))?;
+
+    Ok((StatusCode::CREATED, Json(list)))
+}
@@ -0,0 +1,88 @@
+mod exports;
+mod fields;
+mod imports;
+mod lists;
+mod newsletters;
+mod segments;
+mod subscribers;
+mod suppressions;
+
+use std::fmt::Debug;
+
+use axum::response::IntoResponse;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::problem::{error_chain_fmt, FieldError, Problem};
+
+pub use exports::*;
+pub use fields::*;
+pub use imports::*;
+pub use lists::*;
+pub use newsletters::*;
+pub use segments::*;
+pub use subscribers::*;
+pub use suppressions::*;
+
+async fn authenticate(
+    authorization: Authorization<Basic>,
+    pool: &Pool<Postgres>,
+) -> Result<Uuid, AdminError> {
+    let credentials: Credentials = authorization.into();
+
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let user_id = validate_credentials(credentials, pool)
+        .await
+        .map_err(|error| match error {
+            AuthError::InvalidCredentials(_) => AdminError::AuthError(error.into()),
+            AuthError::UnexpectedError(_) => AdminError::UnexpectedError(error.into()),
+        })?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    Ok(user_id)
+}
+
+#[derive(thiserror::Error)]
+pub enum AdminError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Invalid fields")]
+    InvalidFields(Vec<FieldError>),
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<FieldError> for AdminError {
+    fn from(error: FieldError) -> Self {
+        Self::InvalidFields(vec![error])
+    }
+}
+
+impl Debug for AdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> axum::response::Response {
+        match &self {
+            AdminError::AuthError(_) => Problem::unauthorized("admin"),
+            AdminError::ValidationError(message) => Problem::validation(message),
+            AdminError::InvalidFields(errors) => Problem::invalid_fields(errors.clone()),
+            AdminError::NotFound(_) => Problem::not_found(self.to_string()),
+            AdminError::Conflict(message) => Problem::conflict(message),
+            AdminError::UnexpectedError(_) => Problem::unexpected(&self),
+        }
+        .into_response()
+    }
+}
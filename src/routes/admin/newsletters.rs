@@ -0,0 +1,101 @@
+use anyhow::Context;
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::routes::admin::{authenticate, AdminError};
+
+#[derive(Serialize)]
+pub struct EngagementReport {
+    newsletter_issue_id: Uuid,
+    tracking_enabled: bool,
+    delivered: i64,
+    unique_opens: i64,
+    unique_clicks: i64,
+    open_rate: f64,
+    click_rate: f64,
+    top_links: Vec<LinkReport>,
+}
+
+#[derive(Serialize)]
+pub struct LinkReport {
+    url: String,
+    clicks: i64,
+    unique_clicks: i64,
+}
+
+#[tracing::instrument(
+    name = "Getting engagement of a newsletter issue",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn get_newsletter_engagement(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(newsletter_issue_id): Path<Uuid>,
+) -> Result<Json<EngagementReport>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let issue = sqlx::query!(
+        r#"
+            SELECT
+                i.tracking_enabled,
+                (SELECT COUNT(*) FROM deliveries d
+                    WHERE d.newsletter_issue_id = i.newsletter_issue_id
+                    AND d.status IN ('sent', 'bounced')) AS "delivered!",
+                (SELECT COUNT(DISTINCT e.subscriber_id) FROM delivery_events e
+                    WHERE e.newsletter_issue_id = i.newsletter_issue_id
+                    AND e.kind = 'open') AS "unique_opens!",
+                (SELECT COUNT(DISTINCT e.subscriber_id) FROM delivery_events e
+                    WHERE e.newsletter_issue_id = i.newsletter_issue_id
+                    AND e.kind = 'click') AS "unique_clicks!"
+            FROM newsletter_issues i
+            WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(&pool)
+    .await
+    .context("Failed to count engagement of a newsletter issue")?
+    .ok_or(AdminError::NotFound("Newsletter issue"))?;
+
+    let top_links = sqlx::query_as!(
+        LinkReport,
+        r#"
+            SELECT
+                url AS "url!",
+                COUNT(*) AS "clicks!",
+                COUNT(DISTINCT subscriber_id) AS "unique_clicks!"
+            FROM delivery_events
+            WHERE newsletter_issue_id = $1 AND kind = 'click'
+            GROUP BY url
+            ORDER BY 3 DESC, 2 DESC, 1
+            LIMIT 10
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(&pool)
+    .await
+    .context("Failed to retrieve top links of a newsletter issue")?;
+
+    let rate = |count: i64| match issue.delivered {
+        0 => 0.0,
+        delivered => count as f64 / delivered as f64,
+    };
+
+    Ok(Json(EngagementReport {
+        newsletter_issue_id,
+        tracking_enabled: issue.tracking_enabled,
+        delivered: issue.delivered,
+        unique_opens: issue.unique_opens,
+        unique_clicks: issue.unique_clicks,
+        open_rate: rate(issue.unique_opens),
+        click_rate: rate(issue.unique_clicks),
+        top_links,
+    }))
+}
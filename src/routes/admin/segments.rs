@@ -0,0 +1,146 @@
+use anyhow::Context;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+
+use crate::custom_fields::get_field_types;
+use crate::lists::{get_list_id, DEFAULT_LIST};
+use crate::routes::admin::{authenticate, AdminError};
+use crate::segments::{
+    count_recipients, create_segment, delete_segment, get_segment_query, list_segments,
+    NamedSegment, Segment,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct SegmentData {
+    name: String,
+    query: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SegmentPreviewParameters {
+    lists: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SegmentPreview {
+    recipients: i64,
+}
+
+#[tracing::instrument(
+    name = "Listing segments",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn get_segments(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+) -> Result<Json<Vec<NamedSegment>>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let segments = list_segments(&pool)
+        .await
+        .context("Failed to list segments")?;
+
+    Ok(Json(segments))
+}
+
+#[tracing::instrument(
+    name = "Creating a segment",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn add_segment(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Json(body): Json<SegmentData>,
+) -> Result<(StatusCode, Json<NamedSegment>), AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    if body.name.trim().is_empty() {
+        return Err(AdminError::ValidationError("name must not be empty".into()));
+    }
+    parse_segment(&pool, &body.query).await?;
+
+    let segment = create_segment(&pool, body.name.trim(), &body.query)
+        .await
+        .context("Failed to create a segment")?
+        .ok_or_else(|| AdminError::ValidationError(format!("{} already exists", body.name)))?;
+
+    Ok((StatusCode::CREATED, Json(segment)))
+}
+
+#[tracing::instrument(
+    name = "Deleting a segment",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn remove_segment(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let deleted = delete_segment(&pool, &name)
+        .await
+        .context("Failed to delete a segment")?;
+    if !deleted {
+        return Err(AdminError::NotFound("Segment"));
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(
+    name = "Previewing the recipients of a segment",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn preview_segment(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(name): Path<String>,
+    Query(parameters): Query<SegmentPreviewParameters>,
+) -> Result<Json<SegmentPreview>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let query = get_segment_query(&pool, &name)
+        .await
+        .context("Failed to retrieve a segment")?
+        .ok_or(AdminError::NotFound("Segment"))?;
+    let segment = parse_segment(&pool, &query).await?;
+
+    let mut list_ids = Vec::new();
+    for slug in parameters
+        .lists
+        .as_deref()
+        .unwrap_or(DEFAULT_LIST)
+        .split(',')
+    {
+        let list_id = get_list_id(&pool, slug)
+            .await
+            .context("Failed to retrieve a mailing list")?
+            .ok_or_else(|| AdminError::ValidationError(format!("{} is not a known list", slug)))?;
+        list_ids.push(list_id);
+    }
+
+    let recipients = count_recipients(&pool, &list_ids, &segment)
+        .await
+        .context("Failed to count the recipients of a segment")?;
+
+    Ok(Json(SegmentPreview { recipients }))
+}
+
+async fn parse_segment(pool: &Pool<Postgres>, query: &str) -> Result<Segment, AdminError> {
+    let field_types = get_field_types(pool)
+        .await
+        .context("Failed to retrieve custom field types")?;
+
+    Segment::parse(query, &field_types).map_err(|e| AdminError::ValidationError(e.to_string()))
+}
@@ -0,0 +1,638 @@
+use anyhow::Context;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{PgExecutor, Pool, Postgres};
+use uuid::Uuid;
+
+use crate::confirmation_queue::enqueue_confirmation_email;
+use crate::consent::{
+    get_consent_records, record_consent, ClientInfo, ConsentEvent, ConsentEvidence, ConsentRecord,
+};
+use crate::custom_fields::{get_field_types, parse_custom_fields, set_tags, update_custom_fields};
+use crate::data_requests::delete_subscriber_records;
+use crate::deliverability::DeliverabilityCheck;
+use crate::domain::{SubscriberEmail, SubscriberName, SubscriberTag};
+use crate::email_policy::EmailPolicy;
+use crate::problem::FieldError;
+use crate::routes::admin::{authenticate, AdminError};
+use crate::routes::{generate_subscription_token, store_token};
+use crate::startup::HmacSecret;
+use crate::suppression::is_suppressed;
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriberListParameters {
+    status: Option<String>,
+    email_prefix: Option<String>,
+    subscribed_after: Option<DateTime<Utc>>,
+    subscribed_before: Option<DateTime<Utc>>,
+    #[serde(default = "default_limit")]
+    limit: i64,
+    cursor: Option<String>,
+}
+
+fn default_limit() -> i64 {
+    100
+}
+
+#[derive(Serialize)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+    custom_fields: Value,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberPage {
+    subscribers: Vec<Subscriber>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberListMembership {
+    slug: String,
+    status: String,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberStatusChange {
+    old_status: String,
+    new_status: String,
+    changed_by: String,
+    changed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberDetail {
+    #[serde(flatten)]
+    subscriber: Subscriber,
+    lists: Vec<SubscriberListMembership>,
+    status_changes: Vec<SubscriberStatusChange>,
+    consent: Vec<ConsentRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriberUpdate {
+    name: Option<String>,
+    email: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Listing subscribers",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn get_subscribers(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Query(parameters): Query<SubscriberListParameters>,
+) -> Result<Json<SubscriberPage>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    if !(1..=1000).contains(&parameters.limit) {
+        return Err(AdminError::ValidationError(
+            "limit must be between 1 and 1000".into(),
+        ));
+    }
+    let cursor = parameters
+        .cursor
+        .as_deref()
+        .map(decode_cursor)
+        .transpose()?;
+    let email_pattern = parameters.email_prefix.as_deref().map(|prefix| {
+        format!(
+            "{}%",
+            prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        )
+    });
+
+    let mut subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+            SELECT id, email, name, status, subscribed_at, tags, custom_fields
+            FROM subscriptions
+            WHERE ($1::text IS NULL OR status = $1)
+                AND ($2::text IS NULL OR email ILIKE $2)
+                AND ($3::timestamptz IS NULL OR subscribed_at >= $3)
+                AND ($4::timestamptz IS NULL OR subscribed_at < $4)
+                AND ($5::timestamptz IS NULL OR (subscribed_at, id) > ($5, $6::uuid))
+            ORDER BY subscribed_at, id
+            LIMIT $7
+        "#,
+        parameters.status,
+        email_pattern,
+        parameters.subscribed_after,
+        parameters.subscribed_before,
+        cursor.map(|(subscribed_at, _)| subscribed_at),
+        cursor.map(|(_, id)| id),
+        parameters.limit + 1,
+    )
+    .fetch_all(&pool)
+    .await
+    .context("Failed to list subscribers")?;
+
+    let next_cursor = match subscribers.len() as i64 > parameters.limit {
+        true => {
+            subscribers.truncate(parameters.limit as usize);
+            subscribers.last().map(encode_cursor)
+        }
+        false => None,
+    };
+
+    Ok(Json(SubscriberPage {
+        subscribers,
+        next_cursor,
+    }))
+}
+
+#[tracing::instrument(
+    name = "Getting a subscriber",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn get_subscriber(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Json<SubscriberDetail>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    Ok(Json(get_subscriber_detail(&pool, subscriber_id).await?))
+}
+
+// A new email address goes through the same checks as on the subscription form. Nobody has
+// confirmed it yet, so a subscriber who was pending or confirmed is asked to confirm it
+// again; an admin who has their consent otherwise can still confirm them manually.
+#[tracing::instrument(
+    name = "Updating a subscriber",
+    skip(pool, email_policy, deliverability_check, hmac_secret, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn update_subscriber(
+    State(pool): State<Pool<Postgres>>,
+    State(email_policy): State<EmailPolicy>,
+    State(deliverability_check): State<DeliverabilityCheck>,
+    State(hmac_secret): State<HmacSecret>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(subscriber_id): Path<Uuid>,
+    Json(body): Json<SubscriberUpdate>,
+) -> Result<Json<SubscriberDetail>, AdminError> {
+    let user_id = authenticate(authorization, &pool).await?;
+
+    let name = body
+        .name
+        .map(SubscriberName::parse)
+        .transpose()
+        .map_err(|message| FieldError::new("name", message))?;
+    let email = body
+        .email
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .map_err(|message| FieldError::new("email", message))?;
+    if let Some(email) = &email {
+        email_policy
+            .check(email)
+            .map_err(|rejection| FieldError::new("email", rejection.to_string()))?;
+        deliverability_check
+            .check(email)
+            .await
+            .map_err(|rejection| FieldError::new("email", rejection.to_string()))?;
+    }
+    let normalised_email = email.as_ref().map(|email| email_policy.normalise(email));
+    if let Some(normalised_email) = &normalised_email {
+        if is_suppressed(&pool, &hmac_secret, normalised_email)
+            .await
+            .context("Failed to check the suppression list")?
+        {
+            return Err(FieldError::new("email", "Email address is suppressed").into());
+        }
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a PostgreSQL connection from the pool")?;
+    let current = sqlx::query!(
+        "SELECT status, normalised_email FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve a subscriber")?
+    .ok_or(AdminError::NotFound("Subscriber"))?;
+    let reconfirm = normalised_email
+        .as_ref()
+        .is_some_and(|email| *email != current.normalised_email)
+        && (current.status == "confirmed" || current.status == "pending_confirmation");
+
+    let result = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET name = COALESCE($2, name),
+                email = COALESCE($3, email),
+                normalised_email = COALESCE($4, normalised_email),
+                status = CASE WHEN $5 THEN 'pending_confirmation' ELSE status END
+            WHERE id = $1
+        "#,
+        subscriber_id,
+        name.as_ref().map(|name| name.as_ref()),
+        email.as_ref().map(|email| email.as_ref()),
+        normalised_email,
+        reconfirm,
+    )
+    .execute(&mut *transaction)
+    .await;
+
+    match result {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+            return Err(AdminError::Conflict(
+                "Another subscriber already uses this email address".into(),
+            ))
+        }
+        Err(error) => {
+            return Err(anyhow::Error::new(error)
+                .context("Failed to update a subscriber")
+                .into())
+        }
+    }
+
+    if reconfirm {
+        if current.status != "pending_confirmation" {
+            record_status_change(
+                &mut *transaction,
+                subscriber_id,
+                &current.status,
+                "pending_confirmation",
+                user_id,
+            )
+            .await
+            .context("Failed to record a status change of a subscriber")?;
+        }
+        // Links sent to the previous address must not confirm the new one.
+        sqlx::query!(
+            "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+            subscriber_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to revoke the confirmation tokens of a subscriber")?;
+        let subscription_token = generate_subscription_token();
+        store_token(&mut transaction, subscriber_id, &subscription_token)
+            .await
+            .context("Failed to store a confirmation token")?;
+        enqueue_confirmation_email(&mut *transaction, &subscription_token)
+            .await
+            .context("Failed to enqueue a confirmation email")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a subscriber")?;
+
+    Ok(Json(get_subscriber_detail(&pool, subscriber_id).await?))
+}
+
+#[tracing::instrument(
+    name = "Deleting a subscriber",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn delete_subscriber(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<StatusCode, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let deleted = delete_subscriber_data(&pool, subscriber_id)
+        .await
+        .context("Failed to delete a subscriber")?;
+    if !deleted {
+        return Err(AdminError::NotFound("Subscriber"));
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(
+    name = "Manually confirming a subscriber",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn confirm_subscriber_manually(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Json<SubscriberDetail>, AdminError> {
+    let user_id = authenticate(authorization, &pool).await?;
+
+    change_status(&pool, subscriber_id, "confirmed", user_id).await?;
+
+    Ok(Json(get_subscriber_detail(&pool, subscriber_id).await?))
+}
+
+#[tracing::instrument(
+    name = "Manually unsubscribing a subscriber",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn unsubscribe_subscriber_manually(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Json<SubscriberDetail>, AdminError> {
+    let user_id = authenticate(authorization, &pool).await?;
+
+    change_status(&pool, subscriber_id, "unsubscribed", user_id).await?;
+
+    Ok(Json(get_subscriber_detail(&pool, subscriber_id).await?))
+}
+
+fn encode_cursor(subscriber: &Subscriber) -> String {
+    URL_SAFE_NO_PAD.encode(format!(
+        "{}|{}",
+        subscriber
+            .subscribed_at
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        subscriber.id
+    ))
+}
+
+fn decode_cursor(cursor: &str) -> Result<(DateTime<Utc>, Uuid), AdminError> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|cursor| String::from_utf8(cursor).ok())
+        .and_then(|cursor| {
+            let (subscribed_at, id) = cursor.split_once('|')?;
+            Some((
+                DateTime::parse_from_rfc3339(subscribed_at)
+                    .ok()?
+                    .with_timezone(&Utc),
+                Uuid::parse_str(id).ok()?,
+            ))
+        })
+        .ok_or_else(|| AdminError::ValidationError(format!("{} is not a valid cursor", cursor)))
+}
+
+async fn get_subscriber_detail(
+    pool: &Pool<Postgres>,
+    subscriber_id: Uuid,
+) -> Result<SubscriberDetail, AdminError> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+            SELECT id, email, name, status, subscribed_at, tags, custom_fields
+            FROM subscriptions
+            WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber")?
+    .ok_or(AdminError::NotFound("Subscriber"))?;
+
+    let lists = sqlx::query_as!(
+        SubscriberListMembership,
+        r#"
+            SELECT l.slug, m.status
+            FROM list_memberships m
+            JOIN lists l ON l.list_id = m.list_id
+            WHERE m.subscriber_id = $1
+            ORDER BY l.slug
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the mailing lists of a subscriber")?;
+
+    let status_changes = sqlx::query_as!(
+        SubscriberStatusChange,
+        r#"
+            SELECT c.old_status, c.new_status, u.username AS changed_by, c.changed_at
+            FROM subscriber_status_changes c
+            JOIN users u ON u.user_id = c.changed_by
+            WHERE c.subscriber_id = $1
+            ORDER BY c.id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the status changes of a subscriber")?;
+
+    let consent = get_consent_records(pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the consent evidence of a subscriber")?;
+
+    Ok(SubscriberDetail {
+        subscriber,
+        lists,
+        status_changes,
+        consent,
+    })
+}
+
+#[tracing::instrument(name = "Change subscriber status", skip(pool))]
+async fn change_status(
+    pool: &Pool<Postgres>,
+    subscriber_id: Uuid,
+    new_status: &str,
+    user_id: Uuid,
+) -> Result<(), AdminError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a PostgreSQL connection from the pool")?;
+
+    let old_status = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the status of a subscriber")?
+    .ok_or(AdminError::NotFound("Subscriber"))?
+    .status;
+
+    if old_status == new_status {
+        return Ok(());
+    }
+    if old_status == "suppressed" && new_status == "confirmed" {
+        return Err(AdminError::Conflict(
+            "Suppressed subscribers cannot be confirmed".into(),
+        ));
+    }
+    // Confirming them would record a consent they withdrew, so they have to subscribe again.
+    if old_status == "unsubscribed" && new_status == "confirmed" {
+        return Err(AdminError::Conflict(
+            "Unsubscribed subscribers cannot be confirmed".into(),
+        ));
+    }
+
+    sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET status = $2,
+                confirmed_at = CASE WHEN $2 = 'confirmed' THEN COALESCE(confirmed_at, now()) ELSE confirmed_at END
+            WHERE id = $1
+        "#,
+        subscriber_id,
+        new_status,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the status of a subscriber")?;
+    record_status_change(
+        &mut *transaction,
+        subscriber_id,
+        &old_status,
+        new_status,
+        user_id,
+    )
+    .await
+    .context("Failed to record a status change of a subscriber")?;
+    if new_status == "confirmed" {
+        let evidence = ConsentEvidence {
+            event: ConsentEvent::Confirmed,
+            source: "admin",
+            client: &ClientInfo::default(),
+            consent_text: None,
+        };
+        record_consent(&mut *transaction, subscriber_id, &evidence)
+            .await
+            .context("Failed to record the consent of a subscriber")?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the status of a subscriber")?;
+
+    Ok(())
+}
+
+async fn record_status_change(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    old_status: &str,
+    new_status: &str,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriber_status_changes
+                (subscriber_id, old_status, new_status, changed_by, changed_at)
+            VALUES ($1, $2, $3, $4, now())
+        "#,
+        subscriber_id,
+        old_status,
+        new_status,
+        user_id,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Delete subscriber data", skip(pool))]
+async fn delete_subscriber_data(
+    pool: &Pool<Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let deleted = delete_subscriber_records(&mut transaction, subscriber_id).await?;
+    transaction.commit().await?;
+
+    Ok(deleted)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TagsData {
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct TagsResponse {
+    tags: Vec<String>,
+}
+
+#[tracing::instrument(
+    name = "Replacing the tags of a subscriber",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn replace_subscriber_tags(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(subscriber_id): Path<Uuid>,
+    Json(body): Json<TagsData>,
+) -> Result<Json<TagsResponse>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let mut tags = body
+        .tags
+        .into_iter()
+        .map(|tag| SubscriberTag::parse(tag).map(|tag| tag.as_ref().to_string()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AdminError::ValidationError)?;
+    tags.sort();
+    tags.dedup();
+
+    let updated = set_tags(&pool, subscriber_id, &tags)
+        .await
+        .context("Failed to replace the tags of a subscriber")?;
+    if !updated {
+        return Err(AdminError::NotFound("Subscriber"));
+    }
+
+    Ok(Json(TagsResponse { tags }))
+}
+
+#[tracing::instrument(
+    name = "Updating the custom fields of a subscriber",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn update_subscriber_fields(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(subscriber_id): Path<Uuid>,
+    Json(body): Json<Map<String, Value>>,
+) -> Result<Json<Value>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let field_types = get_field_types(&pool)
+        .await
+        .context("Failed to retrieve custom field types")?;
+    let values = parse_custom_fields(
+        body.iter().map(|(name, value)| (name.as_str(), value)),
+        &field_types,
+    )
+    .map_err(AdminError::ValidationError)?;
+
+    let custom_fields = update_custom_fields(&pool, subscriber_id, values)
+        .await
+        .context("Failed to update the custom fields of a subscriber")?
+        .ok_or(AdminError::NotFound("Subscriber"))?;
+
+    Ok(Json(custom_fields))
+}
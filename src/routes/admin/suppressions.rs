@@ -0,0 +1,110 @@
+use anyhow::Context;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+
+use crate::domain::SubscriberEmail;
+use crate::email_policy::EmailPolicy;
+use crate::routes::admin::{authenticate, AdminError};
+use crate::startup::HmacSecret;
+use crate::suppression::{list_suppressions, suppress, unsuppress, Suppression};
+
+#[derive(Deserialize, Debug)]
+pub struct SuppressionListParameters {
+    #[serde(default = "default_limit")]
+    limit: i64,
+    #[serde(default)]
+    offset: i64,
+}
+
+fn default_limit() -> i64 {
+    100
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SuppressionData {
+    email: String,
+    reason: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Listing suppressed email addresses",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn get_suppressions(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Query(parameters): Query<SuppressionListParameters>,
+) -> Result<Json<Vec<Suppression>>, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    if !(1..=1000).contains(&parameters.limit) || parameters.offset < 0 {
+        return Err(AdminError::ValidationError(
+            "limit must be between 1 and 1000 and offset must not be negative".into(),
+        ));
+    }
+
+    let suppressions = list_suppressions(&pool, parameters.limit, parameters.offset)
+        .await
+        .context("Failed to list suppressed email addresses")?;
+
+    Ok(Json(suppressions))
+}
+
+#[tracing::instrument(
+    name = "Adding an email address to the suppression list",
+    skip(pool, email_policy, hmac_secret, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn add_suppression(
+    State(pool): State<Pool<Postgres>>,
+    State(email_policy): State<EmailPolicy>,
+    State(hmac_secret): State<HmacSecret>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Json(body): Json<SuppressionData>,
+) -> Result<StatusCode, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    let email = SubscriberEmail::parse(body.email).map_err(AdminError::ValidationError)?;
+    let reason = body.reason.unwrap_or_else(|| "manual".into());
+
+    let normalised_email = email_policy.normalise(&email);
+    suppress(&pool, &hmac_secret, &normalised_email, &reason, "admin")
+        .await
+        .context("Failed to add an email address to the suppression list")?;
+
+    Ok(StatusCode::CREATED)
+}
+
+#[tracing::instrument(
+    name = "Removing an email address from the suppression list",
+    skip(pool, email_policy, hmac_secret, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn remove_suppression(
+    State(pool): State<Pool<Postgres>>,
+    State(email_policy): State<EmailPolicy>,
+    State(hmac_secret): State<HmacSecret>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(email): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    authenticate(authorization, &pool).await?;
+
+    // Parsed like an added address, so that any spelling of it finds the suppression.
+    let email = SubscriberEmail::parse(email).map_err(AdminError::ValidationError)?;
+    let normalised_email = email_policy.normalise(&email);
+    let removed = unsuppress(&pool, &hmac_secret, &normalised_email)
+        .await
+        .context("Failed to remove an email address from the suppression list")?;
+    if !removed {
+        return Err(AdminError::NotFound("Suppression"));
+    }
+
+    Ok(StatusCode::OK)
+}
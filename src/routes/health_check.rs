@@ -1,6 +1,25 @@
+use axum::extract::State;
 use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+
+use crate::health::{ComponentStatus, HealthCheck};
 
 pub async fn check_health() -> impl IntoResponse {
     StatusCode::OK
 }
+
+// The process is up and serving requests, regardless of its dependencies.
+pub async fn check_liveness() -> impl IntoResponse {
+    StatusCode::OK
+}
+
+pub async fn check_readiness(State(health_check): State<HealthCheck>) -> Response {
+    let report = health_check.report().await;
+    let status = match report.status {
+        ComponentStatus::Up => StatusCode::OK,
+        ComponentStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (status, Json(report)).into_response()
+}
@@ -9,6 +9,7 @@ use serde::Deserialize;
 use sqlx::{Pool, Postgres};
 
 use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::problem::{error_chain_fmt, Problem};
 
 #[derive(Deserialize)]
 pub struct FormData {
@@ -25,7 +26,7 @@ pub async fn login(
         username: form.username,
         password: form.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
 
     let user_id = validate_credentials(credentials, &pool)
         .await
@@ -33,7 +34,7 @@ pub async fn login(
             AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
             AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
         })?;
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
 
     let mut headers = HeaderMap::new();
     headers.append(LOCATION, HeaderValue::from_static("/"));
@@ -55,34 +56,16 @@ impl std::fmt::Debug for LoginError {
     }
 }
 
-fn error_chain_fmt(
-    e: &impl std::error::Error,
-    f: &mut std::fmt::Formatter<'_>,
-) -> std::fmt::Result {
-    writeln!(f, "{}", e)?;
-    let mut current = e.source();
-    while let Some(cause) = current {
-        writeln!(f, "Caused by:\n\t{}", cause)?;
-        current = cause.source();
-    }
-    Ok(())
-}
-
+// Credentials are posted by a form rather than sent with every request, so there is no
+// `WWW-Authenticate` challenge to answer with.
 impl IntoResponse for LoginError {
     fn into_response(self) -> axum::response::Response {
-        let encoded_error = urlencoding::Encoded::new(self.to_string());
-
-        let mut headers = HeaderMap::new();
-        headers.append(
-            LOCATION,
-            HeaderValue::from_str(&format!("/login?error={}", encoded_error)).unwrap(),
-        );
-
-        let status_code = match self {
-            LoginError::AuthError(_) => StatusCode::UNAUTHORIZED,
-            LoginError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        };
-
-        (status_code, headers).into_response()
+        match &self {
+            LoginError::AuthError(_) => {
+                Problem::new(StatusCode::UNAUTHORIZED, "unauthorized", self.to_string())
+            }
+            LoginError::UnexpectedError(_) => Problem::unexpected(&self),
+        }
+        .into_response()
     }
 }
@@ -0,0 +1,17 @@
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use prometheus::TEXT_FORMAT;
+use sqlx::{Pool, Postgres};
+
+use crate::metrics::Metrics;
+
+pub async fn get_metrics(
+    State(metrics): State<Metrics>,
+    State(pool): State<Pool<Postgres>>,
+) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, TEXT_FORMAT)],
+        metrics.render(&pool).await,
+    )
+}
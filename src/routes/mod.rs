@@ -1,13 +1,25 @@
+mod admin;
 mod health_check;
 mod home;
 mod login;
+mod metrics;
 mod newsletters;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_preferences;
+mod tracking;
+mod webhooks;
 
+pub use admin::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use metrics::*;
 pub use newsletters::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
+pub use subscriptions_preferences::*;
+pub use tracking::*;
+pub use webhooks::*;
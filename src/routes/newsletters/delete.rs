@@ -0,0 +1,60 @@
+use anyhow::Context;
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::routes::newsletters::{
+    authenticate, scheduled_issue_not_updated, NewsletterIssueResponse, PublishError,
+};
+
+#[tracing::instrument(
+    name = "Cancelling a newsletter issue",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn cancel_newsletter(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(newsletter_issue_id): Path<Uuid>,
+) -> Result<Json<NewsletterIssueResponse>, PublishError> {
+    authenticate(authorization, &pool).await?;
+
+    let send_at = match cancel_issue(&pool, newsletter_issue_id)
+        .await
+        .context("Failed to cancel a newsletter issue")?
+    {
+        Some(send_at) => Some(send_at),
+        None => return Err(scheduled_issue_not_updated(&pool, newsletter_issue_id).await),
+    };
+
+    Ok(Json(NewsletterIssueResponse {
+        newsletter_issue_id,
+        status: "cancelled".into(),
+        send_at,
+    }))
+}
+
+#[tracing::instrument(name = "Mark scheduled newsletter issue as cancelled", skip(pool))]
+async fn cancel_issue(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = 'cancelled', finished_at = now()
+            WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+            RETURNING send_at AS "send_at!"
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.send_at))
+}
@@ -0,0 +1,177 @@
+use anyhow::Context;
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::routes::newsletters::{authenticate, PublishError};
+
+#[derive(Deserialize, Debug)]
+pub struct Pagination {
+    #[serde(default = "default_page")]
+    page: i64,
+    #[serde(default = "default_per_page")]
+    per_page: i64,
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    50
+}
+
+#[derive(Serialize)]
+pub struct NewsletterIssueReport {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    send_at: Option<DateTime<Utc>>,
+    published_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+    progress: DeliveryProgress,
+    deliveries: Vec<DeliveryReport>,
+    page: i64,
+    per_page: i64,
+}
+
+#[derive(Serialize)]
+pub struct DeliveryProgress {
+    total: i64,
+    queued: i64,
+    sent: i64,
+    failed: i64,
+    bounced: i64,
+    skipped: i64,
+}
+
+#[derive(Serialize)]
+pub struct DeliveryReport {
+    subscriber_id: Uuid,
+    email: String,
+    status: String,
+    provider_message_id: Option<String>,
+    error: Option<String>,
+    queued_at: DateTime<Utc>,
+    sent_at: Option<DateTime<Utc>>,
+    failed_at: Option<DateTime<Utc>>,
+    bounced_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(
+    name = "Getting delivery progress of a newsletter issue",
+    skip(pool, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn get_newsletter(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<NewsletterIssueReport>, PublishError> {
+    authenticate(authorization, &pool).await?;
+
+    if pagination.page < 1 || !(1..=500).contains(&pagination.per_page) {
+        return Err(PublishError::ValidationError(
+            "page must be positive and per_page must be between 1 and 500".into(),
+        ));
+    }
+
+    let issue = sqlx::query!(
+        r#"
+            SELECT title, status, send_at, published_at, started_at, finished_at
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(&pool)
+    .await
+    .context("Failed to retrieve a newsletter issue")?
+    .ok_or(PublishError::NotFound)?;
+
+    let progress = get_delivery_progress(&pool, newsletter_issue_id)
+        .await
+        .context("Failed to count deliveries of a newsletter issue")?;
+    let deliveries = get_deliveries(&pool, newsletter_issue_id, &pagination)
+        .await
+        .context("Failed to retrieve deliveries of a newsletter issue")?;
+
+    Ok(Json(NewsletterIssueReport {
+        newsletter_issue_id,
+        title: issue.title,
+        status: issue.status,
+        send_at: issue.send_at,
+        published_at: issue.published_at,
+        started_at: issue.started_at,
+        finished_at: issue.finished_at,
+        progress,
+        deliveries,
+        page: pagination.page,
+        per_page: pagination.per_page,
+    }))
+}
+
+#[tracing::instrument(name = "Count deliveries by status", skip(pool))]
+async fn get_delivery_progress(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<DeliveryProgress, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryProgress,
+        r#"
+            SELECT
+                COUNT(*) AS "total!",
+                COUNT(*) FILTER (WHERE status = 'queued') AS "queued!",
+                COUNT(*) FILTER (WHERE status = 'sent') AS "sent!",
+                COUNT(*) FILTER (WHERE status = 'failed') AS "failed!",
+                COUNT(*) FILTER (WHERE status = 'bounced') AS "bounced!",
+                COUNT(*) FILTER (WHERE status = 'skipped') AS "skipped!"
+            FROM deliveries
+            WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get a page of deliveries", skip(pool))]
+async fn get_deliveries(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+    pagination: &Pagination,
+) -> Result<Vec<DeliveryReport>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryReport,
+        r#"
+            SELECT
+                d.subscriber_id,
+                s.email,
+                d.status,
+                d.provider_message_id,
+                d.error,
+                d.queued_at,
+                d.sent_at,
+                d.failed_at,
+                d.bounced_at
+            FROM deliveries d
+            JOIN subscriptions s ON s.id = d.subscriber_id
+            WHERE d.newsletter_issue_id = $1
+            ORDER BY s.email, d.subscriber_id
+            LIMIT $2 OFFSET $3
+        "#,
+        newsletter_issue_id,
+        pagination.per_page,
+        (pagination.page - 1) * pagination.per_page,
+    )
+    .fetch_all(pool)
+    .await
+}
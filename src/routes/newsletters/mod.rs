@@ -0,0 +1,123 @@
+mod delete;
+mod get;
+mod patch;
+mod post;
+
+use std::fmt::Debug;
+
+use axum::response::IntoResponse;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::problem::{error_chain_fmt, Problem};
+
+pub use delete::*;
+pub use get::*;
+pub use patch::*;
+pub use post::*;
+
+impl From<Authorization<Basic>> for Credentials {
+    fn from(auth: Authorization<Basic>) -> Self {
+        let username = auth.username();
+        let password = auth.password();
+
+        Self {
+            username: username.into(),
+            password: Secret::new(password.into()),
+        }
+    }
+}
+
+async fn authenticate(
+    authorization: Authorization<Basic>,
+    pool: &Pool<Postgres>,
+) -> Result<Uuid, PublishError> {
+    let credentials: Credentials = authorization.into();
+
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let user_id = validate_credentials(credentials, pool)
+        .await
+        .map_err(|error| match error {
+            AuthError::InvalidCredentials(_) => PublishError::AuthError(error.into()),
+            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(error.into()),
+        })?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    Ok(user_id)
+}
+
+#[derive(Serialize)]
+pub struct NewsletterIssueResponse {
+    newsletter_issue_id: Uuid,
+    status: String,
+    send_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get newsletter issue status", skip(pool))]
+async fn get_issue_status(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.status))
+}
+
+// Cancelling and rescheduling only touch issues which are still `scheduled`, so when
+// nothing was updated this tells a missing issue apart from one that already started.
+async fn scheduled_issue_not_updated(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+) -> PublishError {
+    match get_issue_status(pool, newsletter_issue_id).await {
+        Ok(Some(status)) => {
+            PublishError::Conflict(format!("Newsletter issue is already {}", status))
+        }
+        Ok(None) => PublishError::NotFound,
+        Err(error) => PublishError::UnexpectedError(error.into()),
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Newsletter issue not found")]
+    NotFound,
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for PublishError {
+    fn into_response(self) -> axum::response::Response {
+        match &self {
+            PublishError::AuthError(_) => Problem::unauthorized("publish"),
+            PublishError::ValidationError(message) => Problem::validation(message),
+            PublishError::NotFound => Problem::not_found(self.to_string()),
+            PublishError::Conflict(message) => Problem::conflict(message),
+            PublishError::UnexpectedError(_) => Problem::unexpected(&self),
+        }
+        .into_response()
+    }
+}
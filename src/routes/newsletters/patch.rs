@@ -0,0 +1,70 @@
+use anyhow::Context;
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::routes::newsletters::post::parse_send_at;
+use crate::routes::newsletters::{
+    authenticate, scheduled_issue_not_updated, NewsletterIssueResponse, PublishError,
+};
+
+#[derive(Deserialize)]
+pub struct RescheduleData {
+    send_at: DateTime<FixedOffset>,
+}
+
+#[tracing::instrument(
+    name = "Rescheduling a newsletter issue",
+    skip(pool, body, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn reschedule_newsletter(
+    State(pool): State<Pool<Postgres>>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    Json(body): Json<RescheduleData>,
+) -> Result<Json<NewsletterIssueResponse>, PublishError> {
+    authenticate(authorization, &pool).await?;
+
+    let send_at = parse_send_at(body.send_at)?;
+
+    let rescheduled = reschedule_issue(&pool, newsletter_issue_id, send_at)
+        .await
+        .context("Failed to reschedule a newsletter issue")?;
+    if !rescheduled {
+        return Err(scheduled_issue_not_updated(&pool, newsletter_issue_id).await);
+    }
+
+    Ok(Json(NewsletterIssueResponse {
+        newsletter_issue_id,
+        status: "scheduled".into(),
+        send_at: Some(send_at),
+    }))
+}
+
+#[tracing::instrument(name = "Update send time of scheduled newsletter issue", skip(pool))]
+async fn reschedule_issue(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+    send_at: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET send_at = $2
+            WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        newsletter_issue_id,
+        send_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::email_client::EmailClient;
+use crate::i18n::Locale;
+use crate::issue_delivery::{deliver_issue, DeliveryOutcome, IssueLease};
+use crate::lists::{get_list_id, DEFAULT_LIST};
+use crate::routes::newsletters::{authenticate, NewsletterIssueResponse, PublishError};
+use crate::segments::get_segment_query;
+use crate::startup::{AccessUrl, HmacSecret};
+
+#[derive(Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    send_at: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    tracking: bool,
+    lists: Option<Vec<String>>,
+    segment: Option<String>,
+    #[serde(default)]
+    variants: HashMap<String, Variant>,
+}
+
+#[derive(Deserialize)]
+pub struct Content {
+    html: String,
+}
+
+// Subscribers whose locale has no variant receive the issue's own title and content.
+#[derive(Deserialize)]
+pub struct Variant {
+    title: String,
+    content: Content,
+}
+
+#[tracing::instrument(
+    name = "Sending newsletter to the subscribers",
+    skip(pool, email_client, access_url, hmac_secret, shutdown, body, authorization),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn publish_newsletter(
+    State(pool): State<Pool<Postgres>>,
+    State(email_client): State<EmailClient>,
+    State(AccessUrl(access_url)): State<AccessUrl>,
+    State(hmac_secret): State<HmacSecret>,
+    State(shutdown): State<CancellationToken>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Json(body): Json<BodyData>,
+) -> Result<(StatusCode, Json<NewsletterIssueResponse>), PublishError> {
+    authenticate(authorization, &pool).await?;
+
+    let newsletter_issue_id = Uuid::new_v4();
+    if let Some(tag) = body
+        .variants
+        .keys()
+        .find(|tag| Locale::parse(tag).is_none())
+    {
+        return Err(PublishError::ValidationError(format!(
+            "{} is not a supported locale",
+            tag
+        )));
+    }
+    let list_ids = resolve_lists(&pool, body.lists.as_deref()).await?;
+    let segment_query = match &body.segment {
+        Some(segment) => Some(
+            get_segment_query(&pool, segment)
+                .await
+                .context("Failed to retrieve a segment")?
+                .ok_or_else(|| {
+                    PublishError::ValidationError(format!("{} is not a known segment", segment))
+                })?,
+        ),
+        None => None,
+    };
+
+    match body.send_at {
+        Some(send_at) => {
+            let send_at = parse_send_at(send_at)?;
+            insert_newsletter_issue(
+                &pool,
+                newsletter_issue_id,
+                &body,
+                &list_ids,
+                segment_query.as_deref(),
+                "scheduled",
+                Some(send_at),
+            )
+            .await
+            .context("Failed to store a scheduled newsletter issue")?;
+
+            Ok((
+                StatusCode::ACCEPTED,
+                Json(NewsletterIssueResponse {
+                    newsletter_issue_id,
+                    status: "scheduled".into(),
+                    send_at: Some(send_at),
+                }),
+            ))
+        }
+        None => {
+            let heartbeat_at = insert_newsletter_issue(
+                &pool,
+                newsletter_issue_id,
+                &body,
+                &list_ids,
+                segment_query.as_deref(),
+                "sending",
+                None,
+            )
+            .await
+            .context("Failed to store a newsletter issue")?
+            .context("A newsletter issue being sent has no heartbeat")?;
+            let lease = IssueLease {
+                newsletter_issue_id,
+                heartbeat_at,
+            };
+            let outcome = deliver_issue(
+                &pool,
+                &email_client,
+                &access_url,
+                &hmac_secret,
+                lease,
+                None,
+                &shutdown,
+            )
+            .await?;
+
+            // An issue interrupted by a shutdown is finished by the scheduler after the restart.
+            let (status_code, status, send_at) = match outcome {
+                DeliveryOutcome::Sent => (StatusCode::OK, "sent", None),
+                DeliveryOutcome::Interrupted => {
+                    (StatusCode::ACCEPTED, "scheduled", Some(Utc::now()))
+                }
+            };
+
+            Ok((
+                status_code,
+                Json(NewsletterIssueResponse {
+                    newsletter_issue_id,
+                    status: status.into(),
+                    send_at,
+                }),
+            ))
+        }
+    }
+}
+
+pub(super) fn parse_send_at(send_at: DateTime<FixedOffset>) -> Result<DateTime<Utc>, PublishError> {
+    let send_at = send_at.with_timezone(&Utc);
+
+    if send_at <= Utc::now() {
+        return Err(PublishError::ValidationError(format!(
+            "{} is not in the future",
+            send_at.to_rfc3339()
+        )));
+    }
+
+    Ok(send_at)
+}
+
+async fn resolve_lists(
+    pool: &Pool<Postgres>,
+    slugs: Option<&[String]>,
+) -> Result<Vec<Uuid>, PublishError> {
+    let slugs = match slugs {
+        Some([]) => {
+            return Err(PublishError::ValidationError(
+                "lists must not be empty".into(),
+            ))
+        }
+        Some(slugs) => slugs.to_vec(),
+        None => vec![DEFAULT_LIST.to_string()],
+    };
+
+    let mut list_ids = Vec::with_capacity(slugs.len());
+    for slug in slugs {
+        let list_id = get_list_id(pool, &slug)
+            .await
+            .context("Failed to retrieve a mailing list")?
+            .ok_or_else(|| {
+                PublishError::ValidationError(format!("{} is not a known list", slug))
+            })?;
+        list_ids.push(list_id);
+    }
+
+    Ok(list_ids)
+}
+
+// Returns the heartbeat of an issue stored as being sent, which its lease starts from.
+#[tracing::instrument(name = "Store newsletter issue", skip(pool, body))]
+async fn insert_newsletter_issue(
+    pool: &Pool<Postgres>,
+    newsletter_issue_id: Uuid,
+    body: &BodyData,
+    list_ids: &[Uuid],
+    segment_query: Option<&str>,
+    status: &str,
+    send_at: Option<DateTime<Utc>>,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let heartbeat_at = sqlx::query!(
+        r#"
+            INSERT INTO newsletter_issues (
+                newsletter_issue_id,
+                title,
+                html_content,
+                status,
+                send_at,
+                published_at,
+                started_at,
+                heartbeat_at,
+                tracking_enabled,
+                segment_query
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, now(),
+                CASE WHEN $4 = 'sending' THEN now() END,
+                CASE WHEN $4 = 'sending' THEN now() END,
+                $6, $7
+            )
+            RETURNING heartbeat_at
+        "#,
+        newsletter_issue_id,
+        body.title,
+        body.content.html,
+        status,
+        send_at,
+        body.tracking,
+        segment_query,
+    )
+    .fetch_one(&mut *transaction)
+    .await?
+    .heartbeat_at;
+
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+            SELECT $1, list_id FROM UNNEST($2::uuid[]) AS list_id
+            ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        list_ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let (locales, (titles, html_contents)): (Vec<&str>, (Vec<&str>, Vec<&str>)) = body
+        .variants
+        .iter()
+        .filter_map(|(tag, variant)| {
+            Some((
+                Locale::parse(tag)?.as_str(),
+                (variant.title.as_str(), variant.content.html.as_str()),
+            ))
+        })
+        .unzip();
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_issue_variants (newsletter_issue_id, locale, title, html_content)
+            SELECT $1, * FROM UNNEST($2::text[], $3::text[], $4::text[])
+            ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        &locales as &[&str],
+        &titles as &[&str],
+        &html_contents as &[&str],
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(heartbeat_at)
+}
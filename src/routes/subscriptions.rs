@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 
 use anyhow::Context;
@@ -8,72 +9,257 @@ use axum::{response::IntoResponse, Form};
 use chrono::Utc;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use sqlx::{Executor, Pool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
-use crate::startup::AccessUrl;
+use crate::bot_protection::{form_token, BotProtection};
+use crate::consent::{record_consent, ClientInfo, ConsentEvent, ConsentEvidence};
+use crate::custom_fields::{get_field_types, parse_custom_fields, set_tags, update_custom_fields};
+use crate::deliverability::DeliverabilityCheck;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTag};
+use crate::email_client::{EmailClient, EmailKind};
+use crate::email_policy::EmailPolicy;
+use crate::i18n::{get_subscriber_locale, translate, AcceptLanguage, Locale, Message};
+use crate::lists::{get_list_id, set_membership, DEFAULT_LIST};
+use crate::preferences::list_invitation_link;
+use crate::problem::{error_chain_fmt, FieldError, Problem};
+use crate::startup::{AccessUrl, ConsentStatement};
+use crate::suppression::is_suppressed;
 
 #[derive(Debug, Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    list: Option<String>,
+    tags: Option<String>,
+    source: Option<String>,
+    locale: Option<String>,
+    form_token: Option<String>,
+    #[serde(flatten)]
+    fields: HashMap<String, String>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = SubscribeError;
+impl FormData {
+    // Both fields are checked before giving up, so that every mistake is reported at once.
+    fn parse_subscriber(&self, locale: Locale) -> Result<NewSubscriber, SubscribeError> {
+        let name = SubscriberName::parse(self.name.clone())
+            .map_err(|_| invalid_field(locale, "name", Message::InvalidName { name: &self.name }));
+        let email = SubscriberEmail::parse(self.email.clone()).map_err(|_| {
+            invalid_field(
+                locale,
+                "email",
+                Message::InvalidEmail { email: &self.email },
+            )
+        });
 
-    fn try_from(data: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(data.name).map_err(SubscribeError::ValidationError)?;
-        let email = SubscriberEmail::parse(data.email).map_err(SubscribeError::ValidationError)?;
-
-        Ok(Self {
-            id: Uuid::new_v4(),
-            name,
-            email,
-        })
+        match (name, email) {
+            (Ok(name), Ok(email)) => Ok(NewSubscriber {
+                id: Uuid::new_v4(),
+                name,
+                email,
+            }),
+            (name, email) => Err(SubscribeError::ValidationError(
+                [name.err(), email.err()].into_iter().flatten().collect(),
+            )),
+        }
     }
 }
 
+fn invalid_field(locale: Locale, field: &str, message: Message<'_>) -> FieldError {
+    FieldError::new(field, translate(locale, &message))
+}
+
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, access_url),
+    skip(
+        form,
+        pool,
+        email_client,
+        access_url,
+        consent_statement,
+        bot_protection,
+        email_policy,
+        deliverability_check,
+        client,
+        accept_language
+    ),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
     ),
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
     State(AccessUrl(access_url)): State<AccessUrl>,
     State(pool): State<Pool<Postgres>>,
     State(email_client): State<EmailClient>,
+    State(ConsentStatement(consent_statement)): State<ConsentStatement>,
+    State(bot_protection): State<BotProtection>,
+    State(email_policy): State<EmailPolicy>,
+    State(deliverability_check): State<DeliverabilityCheck>,
+    client: ClientInfo,
+    AcceptLanguage(accept_language): AcceptLanguage,
     Form(form): Form<FormData>,
 ) -> Result<StatusCode, SubscribeError> {
-    let new_subscriber = form.try_into()?;
-    let subscription_token = generate_subscription_token();
+    // Suspected bots get the same response as everybody else, so that they cannot tell
+    // which check they failed.
+    if let Some(rejection) = bot_protection
+        .check_form(form.form_token.as_deref(), &form.fields, &client)
+        .await
+        .context("Failed to verify a challenge response")?
+    {
+        tracing::warn!(%rejection, "Rejected a suspected bot submission");
+        return Ok(StatusCode::OK);
+    }
+
+    let list = form.list.clone().unwrap_or_else(|| DEFAULT_LIST.into());
+    let source = parse_source(form.source.as_deref())?;
+    let tags = parse_tags(form.tags.as_deref())?;
+    let custom_fields = parse_form_fields(&pool, &form.fields).await?;
+    // An explicit choice on the form wins over the browser's language.
+    let locale = form
+        .locale
+        .as_deref()
+        .and_then(Locale::parse)
+        .or(accept_language)
+        .unwrap_or_default();
+    let new_subscriber = form.parse_subscriber(locale)?;
+    email_policy
+        .check(&new_subscriber.email)
+        .map_err(|rejection| invalid_field(locale, "email", Message::EmailRejected(&rejection)))?;
+    let normalised_email = email_policy.normalise(&new_subscriber.email);
+
+    // Telling a mail-bombing script which limit it hit would only help it to pace itself.
+    if let Some(rejection) = bot_protection
+        .check_rate_limits(&pool, &client, &normalised_email)
+        .await
+        .context("Failed to check the subscription rate limits")?
+    {
+        tracing::warn!(%rejection, "Rate limited a subscription");
+        return Ok(StatusCode::OK);
+    }
+    deliverability_check
+        .check(&new_subscriber.email)
+        .await
+        .map_err(|rejection| invalid_field(locale, "email", Message::EmailRejected(&rejection)))?;
+    let list_id = get_list_id(&pool, &list)
+        .await
+        .context("Failed to retrieve the mailing list")?
+        .ok_or_else(|| FieldError::new("list", format!("{} is not a known list", list)))?;
+
+    if let Some(rejection) = bot_protection
+        .spend_form_token(&pool, form.form_token.as_deref())
+        .await
+        .context("Failed to spend a form token")?
+    {
+        tracing::warn!(%rejection, "Rejected a suspected bot submission");
+        return Ok(StatusCode::OK);
+    }
 
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a PostgreSQL connection from the pool")?;
-    insert_subscriber(&mut transaction, &new_subscriber)
+
+    let existing = get_subscriber_by_email(&mut transaction, &normalised_email)
         .await
-        .context("Failed to insert new subscriber in the database")?;
-    store_token(&mut transaction, &new_subscriber, &subscription_token)
+        .context("Failed to look up an existing subscriber")?;
+    let (subscriber_id, subscription_token) = match existing {
+        None => {
+            insert_subscriber(&mut transaction, &new_subscriber, &normalised_email, locale)
+                .await
+                .context("Failed to insert new subscriber in the database")?;
+            set_tags(&mut *transaction, new_subscriber.id, &tags)
+                .await
+                .context("Failed to store the tags of a new subscriber")?;
+            update_custom_fields(&mut *transaction, new_subscriber.id, custom_fields)
+                .await
+                .context("Failed to store the custom fields of a new subscriber")?;
+            (new_subscriber.id, generate_subscription_token())
+        }
+        Some((subscriber_id, status)) if status == "pending_confirmation" => {
+            (subscriber_id, generate_subscription_token())
+        }
+        // Anybody can submit the form with the address of a subscriber, so they only join
+        // the list by following a signed link sent to them. Subscribers who left are not
+        // contacted at all.
+        Some((subscriber_id, status)) => {
+            drop(transaction);
+            if status != "confirmed" {
+                return Ok(StatusCode::OK);
+            }
+            if let Some(rejection) = bot_protection
+                .check_recipient_limit(&pool, &normalised_email)
+                .await
+                .context("Failed to check the recipient rate limit")?
+            {
+                tracing::warn!(%rejection, "Rate limited a list invitation");
+                return Ok(StatusCode::OK);
+            }
+            if is_suppressed(&pool, &bot_protection.hmac_secret, &normalised_email)
+                .await
+                .context("Failed to check the suppression list")?
+            {
+                tracing::warn!("Skipping a list invitation to a suppressed email address");
+                return Ok(StatusCode::OK);
+            }
+            let locale = get_subscriber_locale(&pool, subscriber_id)
+                .await
+                .context("Failed to retrieve the locale of the subscriber")?;
+            let invitation_link = list_invitation_link(
+                &access_url,
+                &bot_protection.hmac_secret,
+                subscriber_id,
+                list_id,
+                &source,
+            );
+            send_list_invitation(
+                &email_client,
+                &new_subscriber.email,
+                &invitation_link,
+                locale,
+            )
+            .await
+            .context("Failed to send a list invitation")?;
+            return Ok(StatusCode::OK);
+        }
+    };
+    store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
         .context("Failed to store the confirmation token for a new subscriber")?;
+    set_membership(&mut *transaction, list_id, subscriber_id, true)
+        .await
+        .context("Failed to add the subscriber to the mailing list")?;
+    let evidence = ConsentEvidence {
+        event: ConsentEvent::Subscribed,
+        source: &source,
+        client: &client,
+        consent_text: Some(&consent_statement),
+    };
+    record_consent(&mut *transaction, subscriber_id, &evidence)
+        .await
+        .context("Failed to record the consent of a subscriber")?;
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber")?;
 
+    if is_suppressed(&pool, &bot_protection.hmac_secret, &normalised_email)
+        .await
+        .context("Failed to check the suppression list")?
+    {
+        tracing::warn!("Skipping a confirmation email to a suppressed email address");
+        return Ok(StatusCode::OK);
+    }
+
     send_confirmation_email(
         &email_client,
         &access_url,
-        &new_subscriber,
+        &new_subscriber.email,
         &subscription_token,
+        locale,
     )
     .await
     .context("Failed to send a confirmation email")?;
@@ -81,7 +267,70 @@ pub async fn subscribe(
     Ok(StatusCode::OK)
 }
 
-fn generate_subscription_token() -> String {
+#[derive(Serialize)]
+pub struct FormToken {
+    form_token: String,
+}
+
+// Subscription forms embed this token when they are rendered; `subscribe` uses it to tell
+// how long the form was shown before it was submitted, and to accept each form only once.
+#[tracing::instrument(name = "Issue a subscription form token", skip(bot_protection))]
+pub async fn get_form_token(State(bot_protection): State<BotProtection>) -> Json<FormToken> {
+    Json(FormToken {
+        form_token: form_token(&bot_protection.hmac_secret, Utc::now()),
+    })
+}
+
+fn parse_source(source: Option<&str>) -> Result<String, SubscribeError> {
+    match source.map(str::trim) {
+        None | Some("") => Ok("subscription_form".into()),
+        Some(source) if source.chars().count() <= 64 => Ok(source.into()),
+        Some(_) => {
+            Err(FieldError::new("source", "source must be at most 64 characters long").into())
+        }
+    }
+}
+
+fn parse_tags(tags: Option<&str>) -> Result<Vec<String>, SubscribeError> {
+    tags.unwrap_or_default()
+        .split(',')
+        .filter(|tag| !tag.trim().is_empty())
+        .map(|tag| {
+            SubscriberTag::parse(tag.into())
+                .map(|tag| tag.as_ref().to_string())
+                .map_err(|message| FieldError::new("tags", message).into())
+        })
+        .collect()
+}
+
+// Custom fields are submitted as `fields[name]=value` so that they cannot clash with the
+// other form fields.
+async fn parse_form_fields(
+    pool: &Pool<Postgres>,
+    fields: &HashMap<String, String>,
+) -> Result<Map<String, Value>, SubscribeError> {
+    let values: Vec<(&str, Value)> = fields
+        .iter()
+        .filter_map(|(key, value)| {
+            let name = key.strip_prefix("fields[")?.strip_suffix(']')?;
+            Some((name, Value::String(value.clone())))
+        })
+        .collect();
+    if values.is_empty() {
+        return Ok(Map::new());
+    }
+
+    let field_types = get_field_types(pool)
+        .await
+        .context("Failed to retrieve custom field types")?;
+    parse_custom_fields(
+        values.iter().map(|(name, value)| (*name, value)),
+        &field_types,
+    )
+    .map_err(|message| FieldError::new("fields", message).into())
+}
+
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -91,27 +340,81 @@ fn generate_subscription_token() -> String {
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(email_client, recipient)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
     access_url: &str,
-    new_subscriber: &NewSubscriber,
+    recipient: &SubscriberEmail,
     subscription_token: &str,
+    locale: Locale,
 ) -> Result<(), reqwest::Error> {
-    let confirmation_link = format!(
+    let confirmation_link = &format!(
         "{}/subscriptions/confirm?subscription_token={}",
         access_url, subscription_token,
     );
 
     email_client
         .send_email(
-            &new_subscriber.email,
-            "Welcome!",
-            &format!("Welcome to our newsletter!<br />Click <a href=\"{}\">here</a> to confirm your subscription.", confirmation_link),
-            &format!("Welcome to our newsletter!\nVisit {} to confirm your subscription.", confirmation_link),
+            EmailKind::Confirmation,
+            recipient,
+            &translate(locale, &Message::ConfirmationEmailSubject),
+            &translate(
+                locale,
+                &Message::ConfirmationEmailHtml { confirmation_link },
+            ),
+            &translate(
+                locale,
+                &Message::ConfirmationEmailText { confirmation_link },
+            ),
         )
-        .await
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send a list invitation to an existing subscriber",
+    skip(email_client, recipient, invitation_link)
+)]
+async fn send_list_invitation(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+    invitation_link: &str,
+    locale: Locale,
+) -> Result<(), reqwest::Error> {
+    email_client
+        .send_email(
+            EmailKind::Confirmation,
+            recipient,
+            &translate(locale, &Message::ListInvitationEmailSubject),
+            &translate(
+                locale,
+                &Message::ListInvitationEmailHtml { invitation_link },
+            ),
+            &translate(
+                locale,
+                &Message::ListInvitationEmailText { invitation_link },
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get subscriber by email", skip(transaction))]
+async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    normalised_email: &str,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT id, status FROM subscriptions WHERE normalised_email = $1",
+        normalised_email,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    Ok(row.map(|r| (r.id, r.status)))
 }
 
 #[tracing::instrument(
@@ -121,16 +424,22 @@ pub async fn send_confirmation_email(
 async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
+    normalised_email: &str,
+    locale: Locale,
 ) -> Result<(), sqlx::Error> {
     let query = sqlx::query!(
         r#"
-            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-            VALUES ($1, $2, $3, $4, 'pending_confirmation')
+            INSERT INTO subscriptions (
+                id, email, normalised_email, name, subscribed_at, status, locale
+            )
+            VALUES ($1, $2, $3, $4, $5, 'pending_confirmation', $6)
         "#,
         new_subscriber.id,
         new_subscriber.email.as_ref(),
+        normalised_email,
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        locale.as_str(),
     );
 
     transaction.execute(query).await?;
@@ -140,11 +449,11 @@ async fn insert_subscriber(
 
 #[tracing::instrument(
     name = "Storing new subscriber token in the database",
-    skip(transaction, new_subscriber)
+    skip(transaction, subscription_token)
 )]
-async fn store_token(
+pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
-    new_subscriber: &NewSubscriber,
+    subscriber_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), sqlx::Error> {
     let query = sqlx::query!(
@@ -153,7 +462,7 @@ async fn store_token(
             VALUES ($1, $2)
         "#,
         subscription_token,
-        new_subscriber.id,
+        subscriber_id,
     );
 
     transaction.execute(query).await?;
@@ -163,12 +472,18 @@ async fn store_token(
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
+    #[error("Invalid subscription details")]
+    ValidationError(Vec<FieldError>),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
 
+impl From<FieldError> for SubscribeError {
+    fn from(error: FieldError) -> Self {
+        Self::ValidationError(vec![error])
+    }
+}
+
 impl Debug for SubscribeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -178,25 +493,9 @@ impl Debug for SubscribeError {
 impl IntoResponse for SubscribeError {
     fn into_response(self) -> axum::response::Response {
         match self {
-            SubscribeError::ValidationError(message) => (StatusCode::BAD_REQUEST, Json(message)),
-            SubscribeError::UnexpectedError(_) => {
-                tracing::error!("{:?}", self);
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string()))
-            }
+            SubscribeError::ValidationError(errors) => Problem::invalid_fields(errors),
+            SubscribeError::UnexpectedError(_) => Problem::unexpected(&self),
         }
         .into_response()
     }
 }
-
-fn error_chain_fmt(
-    e: &impl std::error::Error,
-    f: &mut std::fmt::Formatter<'_>,
-) -> std::fmt::Result {
-    writeln!(f, "{}", e)?;
-    let mut current = e.source();
-    while let Some(cause) = current {
-        writeln!(f, "Caused by:\n\t{}", cause)?;
-        current = cause.source();
-    }
-    Ok(())
-}
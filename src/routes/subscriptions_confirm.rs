@@ -1,49 +1,116 @@
+use anyhow::Context;
 use axum::extract::State;
-use axum::response::IntoResponse;
+use axum::response::{Html, IntoResponse, Response};
 use axum::{extract::Query, http::StatusCode};
 use serde::Deserialize;
 use sqlx::{Pool, Postgres};
 use uuid::Uuid;
 
+use crate::consent::{record_consent, ClientInfo, ConsentEvent, ConsentEvidence};
+use crate::i18n::{get_subscriber_locale, translate, AcceptLanguage, Locale, Message};
+use crate::problem::Problem;
+use crate::routes::subscriptions_preferences::escape_html;
+
 #[derive(Deserialize, Debug)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(pool))]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(pool, client, accept_language)
+)]
 pub async fn confirm(
     State(pool): State<Pool<Postgres>>,
+    client: ClientInfo,
+    AcceptLanguage(accept_language): AcceptLanguage,
     Query(parameters): Query<Parameters>,
-) -> impl IntoResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+) -> Result<Response, Problem> {
+    let id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+        .await
+        .context("Failed to look up the subscription token")?;
+
+    // The invalid link page is meant for people following the link from their inbox.
+    let subscriber_id = match id {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let locale = accept_language.unwrap_or_default();
+            let page = render_page(
+                locale,
+                &translate(locale, &Message::InvalidConfirmationLink),
+                "",
+            );
+            return Ok((StatusCode::UNAUTHORIZED, page).into_response());
+        }
     };
 
-    match id {
-        None => StatusCode::UNAUTHORIZED,
-        Some(subscriber_id) => match confirm_subscriber(&pool, subscriber_id).await {
-            Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            Ok(_) => StatusCode::OK,
-        },
-    }
+    confirm_subscriber(&pool, subscriber_id, &client)
+        .await
+        .context("Failed to confirm the subscriber")?;
+    let locale = get_subscriber_locale(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the locale of the subscriber")?;
+
+    Ok(render_page(
+        locale,
+        &translate(locale, &Message::SubscriptionConfirmed),
+        &translate(locale, &Message::SubscriptionConfirmedDetails),
+    )
+    .into_response())
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool, subscription_id))]
+fn render_page(locale: Locale, title: &str, details: &str) -> Html<String> {
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    <h1>{}</h1>
+    <p>{}</p>
+</body>
+</html>"#,
+        locale.as_str(),
+        escape_html(title),
+        escape_html(title),
+        escape_html(details),
+    ))
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(pool, subscription_id, client)
+)]
 pub async fn confirm_subscriber(
     pool: &Pool<Postgres>,
     subscription_id: Uuid,
+    client: &ClientInfo,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+    let mut transaction = pool.begin().await?;
+    let result = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET status = 'confirmed', confirmed_at = COALESCE(confirmed_at, now())
+            WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
         subscription_id
     )
-    .execute(pool)
-    .await
-    .map_err(|error| {
-        tracing::error!("Failed to execute query: {:?}", error);
-        error
-    })?;
+    .execute(&mut *transaction)
+    .await?;
+    // Following the link again does not confirm anything, so it is not evidence of consent.
+    if result.rows_affected() == 0 {
+        return Ok(());
+    }
+    let evidence = ConsentEvidence {
+        event: ConsentEvent::Confirmed,
+        source: "confirmation_link",
+        client,
+        consent_text: None,
+    };
+    record_consent(&mut *transaction, subscription_id, &evidence).await?;
+    transaction.commit().await?;
 
     Ok(())
 }
@@ -58,11 +125,7 @@ pub async fn get_subscriber_id_from_token(
         subscription_token,
     )
     .fetch_optional(pool)
-    .await
-    .map_err(|error| {
-        tracing::error!("Failed to execute query: {:?}", error);
-        error
-    })?;
+    .await?;
 
     Ok(result.map(|r| r.subscriber_id))
 }
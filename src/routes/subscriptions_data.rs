@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use axum::extract::{Query, State};
+use axum::http::header::CONTENT_DISPOSITION;
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use axum::{Form, Json};
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::bot_protection::BotProtection;
+use crate::consent::ClientInfo;
+use crate::data_requests::{
+    collect_subscriber_data, data_request_link, erase_subscriber_data, verify_data_request_token,
+    DataRequestKind, DATA_REQUEST_LINK_LIFETIME_HOURS,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, EmailKind};
+use crate::email_policy::EmailPolicy;
+use crate::problem::{FieldError, Problem};
+use crate::routes::subscriptions_preferences::escape_html;
+use crate::startup::{AccessUrl, HmacSecret};
+use crate::suppression::is_suppressed;
+
+#[derive(Deserialize, Debug)]
+pub struct DataRequestForm {
+    email: String,
+    kind: DataRequestKind,
+    form_token: Option<String>,
+    #[serde(flatten)]
+    fields: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DataRequestParameters {
+    token: String,
+}
+
+// Always answers 200 so that the endpoint cannot be used to find out who is subscribed. The
+// form is protected like the subscription form, and a single address receives at most
+// `max_emails_per_recipient` emails per window.
+#[tracing::instrument(
+    name = "Requesting subscriber data access or erasure",
+    skip(form, pool, email_client, access_url, hmac_secret, bot_protection, email_policy, client),
+    fields(kind = ?form.kind)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn request_subscriber_data(
+    State(AccessUrl(access_url)): State<AccessUrl>,
+    State(pool): State<Pool<Postgres>>,
+    State(email_client): State<EmailClient>,
+    State(hmac_secret): State<HmacSecret>,
+    State(bot_protection): State<BotProtection>,
+    State(email_policy): State<EmailPolicy>,
+    client: ClientInfo,
+    Form(form): Form<DataRequestForm>,
+) -> Result<StatusCode, Problem> {
+    if let Some(rejection) = bot_protection
+        .check_form(form.form_token.as_deref(), &form.fields, &client)
+        .await
+        .context("Failed to verify a challenge response")?
+    {
+        tracing::warn!(%rejection, "Rejected a suspected bot data request");
+        return Ok(StatusCode::OK);
+    }
+
+    let email = SubscriberEmail::parse(form.email)
+        .map_err(|message| Problem::invalid_fields(vec![FieldError::new("email", message)]))?;
+    let normalised_email = email_policy.normalise(&email);
+    if let Some(rejection) = bot_protection
+        .spend_form_token(&pool, form.form_token.as_deref())
+        .await
+        .context("Failed to spend a form token")?
+    {
+        tracing::warn!(%rejection, "Rejected a suspected bot data request");
+        return Ok(StatusCode::OK);
+    }
+
+    let rejection = match bot_protection
+        .check_rate_limits(&pool, &client, &normalised_email)
+        .await
+        .context("Failed to check the data request rate limits")?
+    {
+        Some(rejection) => Some(rejection),
+        None => bot_protection
+            .check_recipient_limit(&pool, &normalised_email)
+            .await
+            .context("Failed to check the data request rate limits")?,
+    };
+    if let Some(rejection) = rejection {
+        tracing::warn!(%rejection, "Rate limited a data request");
+        return Err(Problem::too_many_requests(
+            "Too many data requests, try again later",
+        ));
+    }
+
+    let subscriber = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE normalised_email = $1",
+        normalised_email
+    )
+    .fetch_optional(&pool)
+    .await
+    .context("Failed to look up a subscriber")?;
+    let subscriber_id = match subscriber {
+        Some(subscriber) => subscriber.id,
+        None => return Ok(StatusCode::OK),
+    };
+    if is_suppressed(&pool, &hmac_secret, &normalised_email)
+        .await
+        .context("Failed to check the suppression list")?
+    {
+        tracing::warn!("Skipping a data request email to a suppressed email address");
+        return Ok(StatusCode::OK);
+    }
+
+    let link = data_request_link(&access_url, &hmac_secret, form.kind, subscriber_id);
+    let (subject, action) = match form.kind {
+        DataRequestKind::Access => (
+            "Your data request",
+            "download a copy of the data we hold about you",
+        ),
+        DataRequestKind::Erasure => ("Your erasure request", "permanently erase your data"),
+    };
+    email_client
+        .send_email(
+            EmailKind::DataRequest,
+            &email,
+            subject,
+            &format!(
+                "Click <a href=\"{}\">here</a> to {}. The link expires in {} hours.<br />If you did not make this request, you can ignore this email.",
+                link, action, DATA_REQUEST_LINK_LIFETIME_HOURS
+            ),
+            &format!(
+                "Visit {} to {}. The link expires in {} hours.\nIf you did not make this request, you can ignore this email.",
+                link, action, DATA_REQUEST_LINK_LIFETIME_HOURS
+            ),
+        )
+        .await
+        .context("Failed to send a data request email")?;
+
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(name = "Export the data held on a subscriber", skip_all)]
+pub async fn get_subscriber_data(
+    State(pool): State<Pool<Postgres>>,
+    State(hmac_secret): State<HmacSecret>,
+    State(email_policy): State<EmailPolicy>,
+    Query(parameters): Query<DataRequestParameters>,
+) -> Result<Response, Problem> {
+    let subscriber_id = authorize(&hmac_secret, DataRequestKind::Access, &parameters.token)
+        .ok_or_else(Problem::invalid_link)?;
+
+    let data = collect_subscriber_data(&pool, &email_policy, &hmac_secret, subscriber_id)
+        .await
+        .context("Failed to collect the data held on a subscriber")?
+        .ok_or_else(Problem::invalid_link)?;
+
+    Ok((
+        [(
+            CONTENT_DISPOSITION,
+            "attachment; filename=\"subscriber-data.json\"",
+        )],
+        Json(data),
+    )
+        .into_response())
+}
+
+// Erasure happens on POST only, so that link scanners following the emailed link cannot
+// trigger it.
+#[tracing::instrument(name = "Show the erasure confirmation", skip_all)]
+pub async fn get_erasure(
+    State(hmac_secret): State<HmacSecret>,
+    Query(parameters): Query<DataRequestParameters>,
+) -> Result<Html<String>, Problem> {
+    authorize(&hmac_secret, DataRequestKind::Erasure, &parameters.token)
+        .ok_or_else(Problem::invalid_link)?;
+
+    Ok(Html(render_page(&format!(
+        r#"<p>Erasing your data removes your subscriptions and their history. This cannot be undone.</p>
+    <form action="/subscriptions/erasure" method="post">
+        <input type="hidden" name="token" value="{}" />
+        <button type="submit">Erase my data</button>
+    </form>"#,
+        escape_html(&parameters.token)
+    ))))
+}
+
+#[tracing::instrument(name = "Erase the data held on a subscriber", skip_all)]
+pub async fn erase_subscriber(
+    State(pool): State<Pool<Postgres>>,
+    State(hmac_secret): State<HmacSecret>,
+    State(email_policy): State<EmailPolicy>,
+    Form(form): Form<DataRequestParameters>,
+) -> Result<Html<String>, Problem> {
+    let subscriber_id = authorize(&hmac_secret, DataRequestKind::Erasure, &form.token)
+        .ok_or_else(Problem::invalid_link)?;
+
+    if !erase_subscriber_data(&pool, &email_policy, &hmac_secret, subscriber_id)
+        .await
+        .context("Failed to erase the data held on a subscriber")?
+    {
+        return Err(Problem::invalid_link());
+    }
+
+    Ok(Html(render_page("<p>Your data has been erased.</p>")))
+}
+
+fn authorize(hmac_secret: &HmacSecret, kind: DataRequestKind, token: &str) -> Option<Uuid> {
+    verify_data_request_token(hmac_secret, kind, token, Utc::now()).ok()
+}
+
+fn render_page(content: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your data</title>
+</head>
+<body>
+    {}
+</body>
+</html>"#,
+        content
+    )
+}
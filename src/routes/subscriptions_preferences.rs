@@ -0,0 +1,229 @@
+use anyhow::Context;
+use axum::extract::{Query, State};
+use axum::response::Html;
+use axum_extra::extract::Form;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::consent::{record_consent, ClientInfo, ConsentEvent, ConsentEvidence};
+use crate::i18n::{translate, Locale, Message};
+use crate::lists::{get_memberships, set_membership, ListMembership};
+use crate::preferences::{
+    preferences_token, verify_list_invitation_token, verify_preferences_token,
+};
+use crate::problem::Problem;
+use crate::startup::{ConsentStatement, HmacSecret};
+
+#[derive(Deserialize, Debug)]
+pub struct PreferencesParameters {
+    token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PreferencesForm {
+    token: String,
+    #[serde(default)]
+    lists: Vec<Uuid>,
+}
+
+#[tracing::instrument(name = "Show subscription preferences", skip_all)]
+pub async fn get_preferences(
+    State(pool): State<Pool<Postgres>>,
+    State(hmac_secret): State<HmacSecret>,
+    Query(parameters): Query<PreferencesParameters>,
+) -> Result<Html<String>, Problem> {
+    let (subscriber_id, locale) = authorize(&pool, &hmac_secret, &parameters.token).await?;
+    let memberships = get_memberships(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the list memberships of a subscriber")?;
+
+    Ok(Html(render_preferences(
+        locale,
+        &parameters.token,
+        &memberships,
+        false,
+    )))
+}
+
+#[tracing::instrument(name = "Update subscription preferences", skip_all)]
+pub async fn update_preferences(
+    State(pool): State<Pool<Postgres>>,
+    State(hmac_secret): State<HmacSecret>,
+    Form(form): Form<PreferencesForm>,
+) -> Result<Html<String>, Problem> {
+    let (subscriber_id, locale) = authorize(&pool, &hmac_secret, &form.token).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a PostgreSQL connection from the pool")?;
+    for membership in get_memberships(&mut *transaction, subscriber_id)
+        .await
+        .context("Failed to retrieve the list memberships of a subscriber")?
+    {
+        set_membership(
+            &mut *transaction,
+            membership.list_id,
+            subscriber_id,
+            form.lists.contains(&membership.list_id),
+        )
+        .await
+        .context("Failed to update a list membership")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update list memberships")?;
+
+    let memberships = get_memberships(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the list memberships of a subscriber")?;
+
+    Ok(Html(render_preferences(
+        locale,
+        &form.token,
+        &memberships,
+        true,
+    )))
+}
+
+// Existing subscribers are sent this link when somebody asks to add them to another list,
+// see `subscribe`. Following it is what adds them and records their consent.
+#[tracing::instrument(name = "Join a mailing list", skip_all)]
+pub async fn join_list(
+    State(pool): State<Pool<Postgres>>,
+    State(hmac_secret): State<HmacSecret>,
+    State(ConsentStatement(consent_statement)): State<ConsentStatement>,
+    client: ClientInfo,
+    Query(parameters): Query<PreferencesParameters>,
+) -> Result<Html<String>, Problem> {
+    let (subscriber_id, list_id, source) =
+        verify_list_invitation_token(&hmac_secret, &parameters.token)
+            .map_err(|_| Problem::invalid_link())?;
+    let (subscriber_id, locale) = find_subscriber(&pool, subscriber_id).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a PostgreSQL connection from the pool")?;
+    let joined = set_membership(&mut *transaction, list_id, subscriber_id, true)
+        .await
+        .context("Failed to add the subscriber to the mailing list")?;
+    // Following the link again does not change anything, so it is not evidence of consent.
+    if joined {
+        let evidence = ConsentEvidence {
+            event: ConsentEvent::Subscribed,
+            source: &source,
+            client: &client,
+            consent_text: Some(&consent_statement),
+        };
+        record_consent(&mut *transaction, subscriber_id, &evidence)
+            .await
+            .context("Failed to record the consent of a subscriber")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to join a mailing list")?;
+
+    let memberships = get_memberships(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the list memberships of a subscriber")?;
+
+    Ok(Html(render_preferences(
+        locale,
+        &preferences_token(&hmac_secret, subscriber_id),
+        &memberships,
+        true,
+    )))
+}
+
+async fn authorize(
+    pool: &Pool<Postgres>,
+    hmac_secret: &HmacSecret,
+    token: &str,
+) -> Result<(Uuid, Locale), Problem> {
+    let subscriber_id =
+        verify_preferences_token(hmac_secret, token).map_err(|_| Problem::invalid_link())?;
+
+    find_subscriber(pool, subscriber_id).await
+}
+
+async fn find_subscriber(
+    pool: &Pool<Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(Uuid, Locale), Problem> {
+    let row = sqlx::query!(
+        "SELECT id, locale FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up a subscriber")?
+    .ok_or_else(Problem::invalid_link)?;
+
+    Ok((row.id, Locale::parse(&row.locale).unwrap_or_default()))
+}
+
+fn render_preferences(
+    locale: Locale,
+    token: &str,
+    memberships: &[ListMembership],
+    saved: bool,
+) -> String {
+    let lists: String = memberships
+        .iter()
+        .map(|membership| {
+            format!(
+                r#"<p><label><input type="checkbox" name="lists" value="{}"{} /> {}</label></p>"#,
+                membership.list_id,
+                if membership.subscribed {
+                    " checked"
+                } else {
+                    ""
+                },
+                escape_html(&membership.name),
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    {}
+    <form action="/subscriptions/preferences" method="post">
+        <input type="hidden" name="token" value="{}" />
+        {}
+        <button type="submit">{}</button>
+    </form>
+</body>
+</html>"#,
+        locale.as_str(),
+        escape_html(&translate(locale, &Message::PreferencesTitle)),
+        if saved {
+            format!(
+                "<p><i>{}</i></p>",
+                escape_html(&translate(locale, &Message::PreferencesSaved))
+            )
+        } else {
+            String::new()
+        },
+        escape_html(token),
+        lists,
+        escape_html(&translate(locale, &Message::SavePreferences)),
+    )
+}
+
+pub(crate) fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,99 @@
+use axum::extract::{Path, State};
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE, LOCATION};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::IntoResponse;
+use sqlx::{Pool, Postgres};
+
+use crate::startup::HmacSecret;
+use crate::tracking::{TrackingEvent, TrackingToken};
+
+// A transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[tracing::instrument(
+    name = "Track an opened newsletter issue",
+    skip(pool, hmac_secret, token)
+)]
+pub async fn track_open(
+    State(pool): State<Pool<Postgres>>,
+    State(hmac_secret): State<HmacSecret>,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    let token = match TrackingToken::verify(&token, &hmac_secret) {
+        Ok(
+            token @ TrackingToken {
+                event: TrackingEvent::Open,
+                ..
+            },
+        ) => token,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    if let Err(error) = record_event(&pool, &token).await {
+        tracing::error!("Failed to record an opened newsletter issue: {:?}", error);
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.append(CONTENT_TYPE, HeaderValue::from_static("image/gif"));
+    headers.append(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+
+    (StatusCode::OK, headers, PIXEL).into_response()
+}
+
+#[tracing::instrument(name = "Track a clicked link", skip(pool, hmac_secret, token))]
+pub async fn track_click(
+    State(pool): State<Pool<Postgres>>,
+    State(hmac_secret): State<HmacSecret>,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    let token = match TrackingToken::verify(&token, &hmac_secret) {
+        Ok(token) => token,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let location = match &token.event {
+        TrackingEvent::Click { url } => match HeaderValue::from_str(url) {
+            Ok(location) => location,
+            Err(_) => return StatusCode::NOT_FOUND.into_response(),
+        },
+        TrackingEvent::Open => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    if let Err(error) = record_event(&pool, &token).await {
+        tracing::error!("Failed to record a clicked link: {:?}", error);
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.append(LOCATION, location);
+
+    (StatusCode::FOUND, headers).into_response()
+}
+
+#[tracing::instrument(name = "Record engagement with a delivery", skip(pool))]
+async fn record_event(pool: &Pool<Postgres>, token: &TrackingToken) -> Result<(), sqlx::Error> {
+    let (kind, url) = match &token.event {
+        TrackingEvent::Open => ("open", None),
+        TrackingEvent::Click { url } => ("click", Some(url.as_str())),
+    };
+
+    sqlx::query!(
+        r#"
+            INSERT INTO delivery_events
+                (newsletter_issue_id, subscriber_id, kind, url, occurred_at)
+            SELECT newsletter_issue_id, subscriber_id, $3, $4, now()
+            FROM deliveries
+            WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        token.newsletter_issue_id,
+        token.subscriber_id,
+        kind,
+        url,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,343 @@
+use std::fmt::Debug;
+
+use anyhow::Context;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sqlx::{Executor, Pool, Postgres, Transaction};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::configuration::WebhookSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_policy::EmailPolicy;
+use crate::problem::{error_chain_fmt, Problem};
+use crate::startup::HmacSecret;
+use crate::suppression::suppress;
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    Bounce(BounceEvent),
+    SpamComplaint(SpamComplaintEvent),
+    Delivery(DeliveryEvent),
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct BounceEvent {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+    #[serde(rename = "Type")]
+    bounce_type: String,
+    email: String,
+    bounced_at: Option<DateTime<Utc>>,
+}
+
+impl BounceEvent {
+    fn is_hard(&self) -> bool {
+        matches!(self.bounce_type.as_str(), "HardBounce" | "BadEmailAddress")
+    }
+
+    fn is_soft(&self) -> bool {
+        self.bounce_type == "SoftBounce"
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct SpamComplaintEvent {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+    email: String,
+    bounced_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeliveryEvent {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+// The address Postmark reports, and its normalised form when it is a valid address.
+#[derive(Debug)]
+struct ReportedAddress {
+    email: String,
+    normalised_email: Option<String>,
+}
+
+impl ReportedAddress {
+    fn new(email_policy: &EmailPolicy, email: &str) -> Self {
+        match SubscriberEmail::parse(email.to_string()) {
+            Ok(parsed) => Self {
+                normalised_email: Some(email_policy.normalise(&parsed)),
+                email: parsed.as_ref().to_string(),
+            },
+            Err(_) => Self {
+                email: email.to_string(),
+                normalised_email: None,
+            },
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Handling a Postmark webhook",
+    skip(pool, settings, email_policy, hmac_secret, authorization)
+)]
+pub async fn handle_postmark_webhook(
+    State(pool): State<Pool<Postgres>>,
+    State(settings): State<WebhookSettings>,
+    State(email_policy): State<EmailPolicy>,
+    State(hmac_secret): State<HmacSecret>,
+    TypedHeader(authorization): TypedHeader<Authorization<Basic>>,
+    Json(event): Json<PostmarkEvent>,
+) -> Result<StatusCode, WebhookError> {
+    // Both credentials are always compared, in constant time, so that response times do not
+    // tell how much of them was right.
+    let username_matches = authorization
+        .username()
+        .as_bytes()
+        .ct_eq(settings.username.as_bytes());
+    let password_matches = authorization
+        .password()
+        .as_bytes()
+        .ct_eq(settings.password.expose_secret().as_bytes());
+    if !bool::from(username_matches & password_matches) {
+        return Err(WebhookError::AuthError);
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a PostgreSQL connection from the pool")?;
+
+    match event {
+        PostmarkEvent::Bounce(bounce) => {
+            let subscriber_id = record_bounce(&mut transaction, &bounce)
+                .await
+                .context("Failed to record a bounced delivery")?;
+            let address = ReportedAddress::new(&email_policy, &bounce.email);
+
+            let reason = if bounce.is_hard() {
+                Some("hard_bounce")
+            } else if bounce.is_soft()
+                && count_soft_bounces(
+                    &mut transaction,
+                    subscriber_id,
+                    &address,
+                    Utc::now() - settings.soft_bounce_window(),
+                )
+                .await
+                .context("Failed to count soft bounces of a subscriber")?
+                    >= settings.soft_bounce_threshold
+            {
+                Some("soft_bounce")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                suppress_subscriber(
+                    &mut transaction,
+                    &hmac_secret,
+                    subscriber_id,
+                    &address,
+                    reason,
+                )
+                .await
+                .context("Failed to suppress a bounced subscriber")?;
+            }
+        }
+        PostmarkEvent::SpamComplaint(complaint) => {
+            let subscriber_id = record_complaint(&mut transaction, &complaint)
+                .await
+                .context("Failed to record a spam complaint")?;
+            suppress_subscriber(
+                &mut transaction,
+                &hmac_secret,
+                subscriber_id,
+                &ReportedAddress::new(&email_policy, &complaint.email),
+                "spam_complaint",
+            )
+            .await
+            .context("Failed to suppress a complaining subscriber")?;
+        }
+        PostmarkEvent::Delivery(delivery) => {
+            record_delivery(&mut transaction, &delivery)
+                .await
+                .context("Failed to record a delivered email")?;
+        }
+        PostmarkEvent::Unsupported => {
+            tracing::info!("Ignoring unsupported Postmark webhook record type");
+        }
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to record a Postmark webhook")?;
+
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(name = "Mark delivery as bounced", skip(transaction))]
+async fn record_bounce(
+    transaction: &mut Transaction<'_, Postgres>,
+    bounce: &BounceEvent,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            UPDATE deliveries
+            SET status = 'bounced', bounce_type = $2, bounced_at = COALESCE($3, now())
+            WHERE provider_message_id = $1
+            RETURNING subscriber_id
+        "#,
+        bounce.message_id,
+        bounce.bounce_type,
+        bounce.bounced_at,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    Ok(row.map(|r| r.subscriber_id))
+}
+
+#[tracing::instrument(name = "Count soft bounces of subscriber", skip(transaction))]
+async fn count_soft_bounces(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Option<Uuid>,
+    address: &ReportedAddress,
+    since: DateTime<Utc>,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT COUNT(*) AS "count!"
+            FROM deliveries d
+            JOIN subscriptions s ON s.id = d.subscriber_id
+            WHERE (s.id = $1 OR s.normalised_email = $2)
+                AND d.bounce_type = 'SoftBounce'
+                AND d.bounced_at >= $3
+        "#,
+        subscriber_id,
+        address.normalised_email,
+        since,
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    Ok(row.count)
+}
+
+#[tracing::instrument(name = "Mark delivery as complained", skip(transaction))]
+async fn record_complaint(
+    transaction: &mut Transaction<'_, Postgres>,
+    complaint: &SpamComplaintEvent,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            UPDATE deliveries
+            SET complained_at = COALESCE($2, now())
+            WHERE provider_message_id = $1
+            RETURNING subscriber_id
+        "#,
+        complaint.message_id,
+        complaint.bounced_at,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    Ok(row.map(|r| r.subscriber_id))
+}
+
+#[tracing::instrument(name = "Mark delivery as delivered", skip(transaction))]
+async fn record_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    delivery: &DeliveryEvent,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+            UPDATE deliveries
+            SET delivered_at = COALESCE($2, now())
+            WHERE provider_message_id = $1
+        "#,
+        delivery.message_id,
+        delivery.delivered_at,
+    );
+
+    transaction.execute(query).await?;
+
+    Ok(())
+}
+
+// Events for emails that are not newsletter deliveries, e.g. confirmation emails, carry no
+// known message ID, so the subscriber is looked up by the address Postmark reports instead.
+#[tracing::instrument(name = "Suppress subscriber", skip(transaction, hmac_secret))]
+async fn suppress_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Option<Uuid>,
+    address: &ReportedAddress,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET status = 'suppressed'
+            WHERE id = $1 OR normalised_email = $2
+        "#,
+        subscriber_id,
+        address.normalised_email,
+    );
+
+    transaction.execute(query).await?;
+    // Addresses which cannot be parsed are suppressed as reported, as there is nothing
+    // better to go by.
+    suppress(
+        &mut **transaction,
+        hmac_secret,
+        address
+            .normalised_email
+            .as_deref()
+            .unwrap_or(&address.email),
+        reason,
+        "postmark_webhook",
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> axum::response::Response {
+        match &self {
+            WebhookError::AuthError => Problem::unauthorized("webhooks"),
+            WebhookError::UnexpectedError(_) => Problem::unexpected(&self),
+        }
+        .into_response()
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use sqlx::{Pool, Postgres};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    confirmation_queue::try_send_confirmation_email,
+    email_client::EmailClient,
+    health::Heartbeat,
+    issue_delivery::{deliver_issue, IssueLease},
+    startup::{AccessUrl, HmacSecret},
+};
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn run_scheduler_until_stopped(
+    pool: Pool<Postgres>,
+    email_client: EmailClient,
+    access_url: AccessUrl,
+    hmac_secret: HmacSecret,
+    poll_interval: Duration,
+    issue_lease: Duration,
+    heartbeat: Heartbeat,
+    shutdown: CancellationToken,
+) {
+    while !shutdown.is_cancelled() {
+        heartbeat.beat();
+        // Confirmation emails are short and waited on by people, so they go before issues.
+        let outcome =
+            match try_send_confirmation_email(&pool, &email_client, &access_url.0, &hmac_secret)
+                .await
+            {
+                Ok(ExecutionOutcome::TaskCompleted) => Ok(ExecutionOutcome::TaskCompleted),
+                Ok(ExecutionOutcome::EmptyQueue) | Err(_) => {
+                    try_execute_task(
+                        &pool,
+                        &email_client,
+                        &access_url,
+                        &hmac_secret,
+                        issue_lease,
+                        &heartbeat,
+                        &shutdown,
+                    )
+                    .await
+                }
+            };
+        match outcome {
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Ok(ExecutionOutcome::EmptyQueue) | Err(_) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(poll_interval) => {}
+                    _ = shutdown.cancelled() => {}
+                }
+            }
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Execute a due newsletter issue",
+    skip_all,
+    fields(newsletter_issue_id = tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &Pool<Postgres>,
+    email_client: &EmailClient,
+    access_url: &AccessUrl,
+    hmac_secret: &HmacSecret,
+    issue_lease: Duration,
+    heartbeat: &Heartbeat,
+    shutdown: &CancellationToken,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let lease = match claim_due_issue(pool, issue_lease).await? {
+        Some(lease) => lease,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    tracing::Span::current().record(
+        "newsletter_issue_id",
+        tracing::field::display(&lease.newsletter_issue_id),
+    );
+
+    deliver_issue(
+        pool,
+        email_client,
+        &access_url.0,
+        hmac_secret,
+        lease,
+        Some(heartbeat),
+        shutdown,
+    )
+    .await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+// The row lock taken by `FOR UPDATE SKIP LOCKED` makes replicas polling at the same time
+// pass over an issue another replica is claiming, and the status check on the outer
+// update keeps an issue that was cancelled in the meantime from being sent. An issue whose
+// worker has not touched it for `issue_lease`, e.g. because it crashed, is claimed again
+// and continues with the deliveries which are still queued, keeping the time it was first
+// started at.
+#[tracing::instrument(name = "Claim a due newsletter issue", skip(pool))]
+async fn claim_due_issue(
+    pool: &Pool<Postgres>,
+    issue_lease: Duration,
+) -> Result<Option<IssueLease>, sqlx::Error> {
+    let issue_lease = issue_lease.as_secs_f64();
+    sqlx::query_as!(
+        IssueLease,
+        r#"
+            UPDATE newsletter_issues
+            SET status = 'sending', started_at = COALESCE(started_at, now()), heartbeat_at = now()
+            WHERE newsletter_issue_id = (
+                SELECT newsletter_issue_id
+                FROM newsletter_issues
+                WHERE (status = 'scheduled' AND send_at <= now())
+                    OR (status = 'sending' AND heartbeat_at < now() - make_interval(secs => $1))
+                ORDER BY send_at
+                FOR UPDATE
+                SKIP LOCKED
+                LIMIT 1
+            )
+            AND (
+                (status = 'scheduled' AND send_at <= now())
+                OR (status = 'sending' AND heartbeat_at < now() - make_interval(secs => $1))
+            )
+            RETURNING newsletter_issue_id, heartbeat_at AS "heartbeat_at!"
+        "#,
+        issue_lease,
+    )
+    .fetch_optional(pool)
+    .await
+}
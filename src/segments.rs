@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{PgExecutor, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::custom_fields::FieldType;
+
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid segment: {0}")]
+pub struct SegmentError(String);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Operator {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Operator::Eq => " = ",
+            Operator::Ne => " <> ",
+            Operator::Lt => " < ",
+            Operator::Le => " <= ",
+            Operator::Gt => " > ",
+            Operator::Ge => " >= ",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Attribute {
+    Tag,
+    Email,
+    SubscribedAt,
+    Field { name: String, field_type: FieldType },
+}
+
+impl Attribute {
+    fn field_type(&self) -> FieldType {
+        match self {
+            Attribute::Tag | Attribute::Email => FieldType::Text,
+            Attribute::SubscribedAt => FieldType::Date,
+            Attribute::Field { field_type, .. } => *field_type,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Literal {
+    Text(String),
+    Number(f64),
+    Boolean(bool),
+    Date(NaiveDate),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+    Not(Box<Segment>),
+    Condition {
+        attribute: Attribute,
+        operator: Operator,
+        value: Literal,
+    },
+}
+
+impl Segment {
+    // Grammar, loosest binding first:
+    //   segment   := and ("or" and)*
+    //   and       := unary ("and" unary)*
+    //   unary     := "not" unary | "(" segment ")" | condition
+    //   condition := attribute ("=" | "!=" | "<" | "<=" | ">" | ">=") value
+    pub fn parse(
+        query: &str,
+        field_types: &HashMap<String, FieldType>,
+    ) -> Result<Self, SegmentError> {
+        let mut parser = Parser {
+            tokens: tokenize(query)?,
+            position: 0,
+            field_types,
+        };
+
+        let segment = parser.parse_or()?;
+        match parser.next() {
+            None => Ok(segment),
+            Some(token) => Err(SegmentError(format!("unexpected {:?}", token))),
+        }
+    }
+
+    // Conditions on a missing custom field evaluate to false rather than NULL so that
+    // `not` keeps its usual meaning.
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Segment::And(left, right) | Segment::Or(left, right) => {
+                builder.push("(");
+                left.push_sql(builder);
+                builder.push(match self {
+                    Segment::And(_, _) => " AND ",
+                    _ => " OR ",
+                });
+                right.push_sql(builder);
+                builder.push(")");
+            }
+            Segment::Not(segment) => {
+                builder.push("NOT ");
+                segment.push_sql(builder);
+            }
+            Segment::Condition {
+                attribute: Attribute::Tag,
+                operator,
+                value,
+            } => {
+                builder.push(match operator {
+                    Operator::Ne => "NOT (",
+                    _ => "(",
+                });
+                push_literal(builder, value);
+                builder.push(" = ANY(s.tags))");
+            }
+            Segment::Condition {
+                attribute,
+                operator,
+                value,
+            } => {
+                builder.push("COALESCE(");
+                match attribute {
+                    Attribute::Email => {
+                        builder.push("lower(s.email)");
+                        builder.push(operator.as_sql());
+                        builder.push("lower(");
+                        push_literal(builder, value);
+                        builder.push(")");
+                    }
+                    Attribute::SubscribedAt => {
+                        builder.push("s.subscribed_at::date");
+                        builder.push(operator.as_sql());
+                        push_literal(builder, value);
+                    }
+                    Attribute::Field { name, field_type } => {
+                        builder.push("(s.custom_fields ->> ");
+                        builder.push_bind(name.clone());
+                        builder.push(match field_type {
+                            FieldType::Text => ")",
+                            FieldType::Number => ")::float8",
+                            FieldType::Boolean => ")::boolean",
+                            FieldType::Date => ")::date",
+                        });
+                        builder.push(operator.as_sql());
+                        push_literal(builder, value);
+                    }
+                    Attribute::Tag => unreachable!(),
+                }
+                builder.push(", FALSE)");
+            }
+        }
+    }
+}
+
+fn push_literal(builder: &mut QueryBuilder<'_, Postgres>, value: &Literal) {
+    match value {
+        Literal::Text(text) => builder.push_bind(text.clone()),
+        Literal::Number(number) => builder.push_bind(*number),
+        Literal::Boolean(boolean) => builder.push_bind(*boolean),
+        Literal::Date(date) => builder.push_bind(*date),
+    };
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, SegmentError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' => Token::Operator(Operator::Eq),
+            '!' => match chars.next_if_eq(&'=') {
+                Some(_) => Token::Operator(Operator::Ne),
+                None => return Err(SegmentError("expected = after !".into())),
+            },
+            '<' | '>' => {
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                Token::Operator(match (c, or_equal) {
+                    ('<', false) => Operator::Lt,
+                    ('<', true) => Operator::Le,
+                    (_, false) => Operator::Gt,
+                    (_, true) => Operator::Ge,
+                })
+            }
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => value.push(escaped),
+                            None => return Err(SegmentError("unterminated string".into())),
+                        },
+                        Some(c) => value.push(c),
+                        None => return Err(SegmentError("unterminated string".into())),
+                    }
+                }
+                Token::Quoted(value)
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"()=!<>\"".contains(*c))
+                {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    field_types: &'a HashMap<String, FieldType>,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn next_if_keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Segment, SegmentError> {
+        let mut segment = self.parse_and()?;
+        while self.next_if_keyword("or") {
+            segment = Segment::Or(Box::new(segment), Box::new(self.parse_and()?));
+        }
+        Ok(segment)
+    }
+
+    fn parse_and(&mut self) -> Result<Segment, SegmentError> {
+        let mut segment = self.parse_unary()?;
+        while self.next_if_keyword("and") {
+            segment = Segment::And(Box::new(segment), Box::new(self.parse_unary()?));
+        }
+        Ok(segment)
+    }
+
+    fn parse_unary(&mut self) -> Result<Segment, SegmentError> {
+        if self.next_if_keyword("not") {
+            return Ok(Segment::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.tokens.get(self.position) == Some(&Token::Open) {
+            self.position += 1;
+            let segment = self.parse_or()?;
+            return match self.next() {
+                Some(Token::Close) => Ok(segment),
+                _ => Err(SegmentError("expected )".into())),
+            };
+        }
+        self.parse_condition()
+    }
+
+    fn parse_condition(&mut self) -> Result<Segment, SegmentError> {
+        let attribute = match self.next() {
+            Some(Token::Word(name)) => name.clone(),
+            token => {
+                return Err(SegmentError(format!(
+                    "expected an attribute, got {:?}",
+                    token
+                )))
+            }
+        };
+        let attribute = match attribute.as_str() {
+            "tag" => Attribute::Tag,
+            "email" => Attribute::Email,
+            "subscribed_at" => Attribute::SubscribedAt,
+            name => match self.field_types.get(name) {
+                Some(field_type) => Attribute::Field {
+                    name: name.into(),
+                    field_type: *field_type,
+                },
+                None => return Err(SegmentError(format!("{} is not a known attribute", name))),
+            },
+        };
+
+        let operator = match self.next() {
+            Some(Token::Operator(operator)) => *operator,
+            token => {
+                return Err(SegmentError(format!(
+                    "expected an operator, got {:?}",
+                    token
+                )))
+            }
+        };
+        let field_type = attribute.field_type();
+        if matches!(field_type, FieldType::Text | FieldType::Boolean)
+            && !matches!(operator, Operator::Eq | Operator::Ne)
+        {
+            return Err(SegmentError(format!(
+                "{} values can only be compared with = and !=",
+                field_type.as_str()
+            )));
+        }
+
+        let value = match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value.clone(),
+            token => return Err(SegmentError(format!("expected a value, got {:?}", token))),
+        };
+        let value = match field_type.parse_str(&value).map_err(SegmentError)? {
+            serde_json::Value::Number(number) => {
+                Literal::Number(number.as_f64().unwrap_or_default())
+            }
+            serde_json::Value::Bool(boolean) => Literal::Boolean(boolean),
+            _ if field_type == FieldType::Date => Literal::Date(
+                NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                    .map_err(|e| SegmentError(e.to_string()))?,
+            ),
+            _ => Literal::Text(value),
+        };
+
+        Ok(Segment::Condition {
+            attribute,
+            operator,
+            value,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct NamedSegment {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub query: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List segments", skip(executor))]
+pub async fn list_segments(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<NamedSegment>, sqlx::Error> {
+    sqlx::query_as!(
+        NamedSegment,
+        "SELECT segment_id, name, query, created_at FROM segments ORDER BY name"
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[tracing::instrument(name = "Get segment query by name", skip(executor))]
+pub async fn get_segment_query(
+    executor: impl PgExecutor<'_>,
+    name: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!("SELECT query FROM segments WHERE name = $1", name)
+        .fetch_optional(executor)
+        .await?;
+
+    Ok(row.map(|r| r.query))
+}
+
+#[tracing::instrument(name = "Create segment", skip(executor))]
+pub async fn create_segment(
+    executor: impl PgExecutor<'_>,
+    name: &str,
+    query: &str,
+) -> Result<Option<NamedSegment>, sqlx::Error> {
+    sqlx::query_as!(
+        NamedSegment,
+        r#"
+            INSERT INTO segments (segment_id, name, query, created_at)
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT (name) DO NOTHING
+            RETURNING segment_id, name, query, created_at
+        "#,
+        Uuid::new_v4(),
+        name,
+        query,
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+#[tracing::instrument(name = "Delete segment", skip(executor))]
+pub async fn delete_segment(
+    executor: impl PgExecutor<'_>,
+    name: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM segments WHERE name = $1", name)
+        .execute(executor)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Count recipients of a segment", skip(executor, segment))]
+pub async fn count_recipients(
+    executor: impl PgExecutor<'_>,
+    list_ids: &[Uuid],
+    segment: &Segment,
+) -> Result<i64, sqlx::Error> {
+    let mut builder = QueryBuilder::new(
+        r#"
+            SELECT COUNT(DISTINCT s.id)
+            FROM subscriptions s
+            JOIN list_memberships m ON m.subscriber_id = s.id
+            WHERE s.status = 'confirmed' AND m.status = 'subscribed' AND m.list_id = ANY(
+        "#,
+    );
+    builder.push_bind(list_ids.to_vec());
+    builder.push(") AND ");
+    segment.push_sql(&mut builder);
+
+    builder.build_query_scalar().fetch_one(executor).await
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_err;
+
+    use crate::segments::*;
+
+    fn field_types() -> HashMap<String, FieldType> {
+        HashMap::from([
+            ("country".to_string(), FieldType::Text),
+            ("age".to_string(), FieldType::Number),
+        ])
+    }
+
+    fn sql(query: &str) -> String {
+        let mut builder = QueryBuilder::new("");
+        Segment::parse(query, &field_types())
+            .unwrap()
+            .push_sql(&mut builder);
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn conditions_on_custom_fields_are_compiled_with_bind_parameters() {
+        assert_eq!(
+            sql("country = DE"),
+            "COALESCE((s.custom_fields ->> $1) = $2, FALSE)"
+        );
+        assert_eq!(
+            sql("age >= 18"),
+            "COALESCE((s.custom_fields ->> $1)::float8 >= $2, FALSE)"
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            Segment::parse("tag = a or tag = b and tag = c", &field_types()).unwrap(),
+            Segment::parse("tag = a or (tag = b and tag = c)", &field_types()).unwrap(),
+        );
+        assert_eq!(
+            sql("NOT tag = beta or tag != alpha"),
+            "(NOT ($1 = ANY(s.tags)) OR NOT ($2 = ANY(s.tags)))"
+        );
+    }
+
+    #[test]
+    fn quoted_values_may_contain_spaces_and_operators() {
+        assert_eq!(
+            Segment::parse(r#"country = "Côte d'Ivoire <\"CI\">""#, &field_types()).unwrap(),
+            Segment::Condition {
+                attribute: Attribute::Field {
+                    name: "country".into(),
+                    field_type: FieldType::Text
+                },
+                operator: Operator::Eq,
+                value: Literal::Text(r#"Côte d'Ivoire <"CI">"#.into()),
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        for query in [
+            "",
+            "city = Berlin",
+            "age = many",
+            "country > DE",
+            "tag beta",
+            "(tag = beta",
+            "tag = beta)",
+            "tag = beta and",
+            r#"tag = "beta"#,
+            "subscribed_at < yesterday",
+        ] {
+            assert_err!(Segment::parse(query, &field_types()), "{}", query);
+        }
+    }
+}
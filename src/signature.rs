@@ -0,0 +1,87 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+
+use crate::startup::HmacSecret;
+
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid signed token")]
+pub struct InvalidSignature;
+
+pub fn sign(secret: &HmacSecret, payload: &str) -> String {
+    let signature = mac(secret, payload).finalize().into_bytes();
+
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(signature)
+    )
+}
+
+pub fn verify(secret: &HmacSecret, token: &str) -> Result<String, InvalidSignature> {
+    let (payload, signature) = token.split_once('.').ok_or(InvalidSignature)?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|payload| String::from_utf8(payload).ok())
+        .ok_or(InvalidSignature)?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| InvalidSignature)?;
+
+    mac(secret, &payload)
+        .verify_slice(&signature)
+        .map_err(|_| InvalidSignature)?;
+
+    Ok(payload)
+}
+
+// A hex encoded MAC of `payload`, for values which are looked up rather than verified.
+pub fn keyed_hash(secret: &HmacSecret, payload: &str) -> String {
+    format!("{:x}", mac(secret, payload).finalize().into_bytes())
+}
+
+fn mac(secret: &HmacSecret, payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.0.expose_secret().as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+
+    use crate::signature::*;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("super-secret".into()))
+    }
+
+    #[test]
+    fn signed_payload_is_verified() {
+        let token = sign(&secret(), "payload");
+        assert_ok_eq!(verify(&secret(), &token), "payload".to_string());
+    }
+
+    #[test]
+    fn token_signed_with_another_secret_is_rejected() {
+        let token = sign(&HmacSecret(Secret::new("another-secret".into())), "payload");
+        assert_err!(verify(&secret(), &token));
+    }
+
+    #[test]
+    fn tampered_payload_is_rejected() {
+        let token = sign(&secret(), "payload");
+        let (_, signature) = token.split_once('.').unwrap();
+        let tampered = format!("{}.{}", URL_SAFE_NO_PAD.encode("tampered"), signature);
+        assert_err!(verify(&secret(), &tampered));
+    }
+
+    #[test]
+    fn token_without_signature_is_rejected() {
+        assert_err!(verify(&secret(), &URL_SAFE_NO_PAD.encode("payload")));
+    }
+}
@@ -1,32 +1,74 @@
+use std::future::IntoFuture;
+use std::net::SocketAddr;
 use std::time::Duration;
 
 use axum::{
     extract::{FromRef, MatchedPath},
     http::Request,
-    routing::{get, post},
+    middleware,
+    routing::{delete, get, patch, post, put},
     Router,
 };
-use secrecy::ExposeSecret;
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use secrecy::Secret;
+use sqlx::{Pool, Postgres};
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 use tower_http::trace::TraceLayer;
-use uuid::Uuid;
 
 use crate::{
-    configuration::Settings,
+    bot_protection::BotProtection,
+    configuration::{Settings, WebhookSettings},
+    consent::TrustedProxies,
+    deliverability::DeliverabilityCheck,
     email_client::EmailClient,
+    email_policy::EmailPolicy,
+    health::HealthCheck,
+    metrics::{track_http_metrics, Metrics},
+    request_id::{propagate_request_id, RequestId},
     routes::login,
-    routes::{check_health, confirm, home, publish_newsletter, subscribe},
+    routes::{
+        add_custom_field, add_import, add_list, add_segment, add_suppression, cancel_newsletter,
+        check_health, check_liveness, check_readiness, confirm, confirm_subscriber_manually,
+        delete_subscriber, erase_subscriber, export_subscriber_data, get_custom_fields,
+        get_erasure, get_form_token, get_import, get_lists, get_metrics, get_newsletter,
+        get_newsletter_engagement, get_preferences, get_segments, get_subscriber,
+        get_subscriber_data, get_subscribers, get_suppressions, handle_postmark_webhook, home,
+        join_list, preview_segment, publish_newsletter, remove_segment, remove_suppression,
+        replace_subscriber_tags, request_subscriber_data, reschedule_newsletter, subscribe,
+        track_click, track_open, unsubscribe_subscriber_manually, update_preferences,
+        update_subscriber, update_subscriber_fields, upload_import_rows,
+    },
+    scheduler::run_scheduler_until_stopped,
+    telemetry::set_remote_parent,
 };
 
 #[derive(Clone)]
 pub struct AccessUrl(pub String);
 
+#[derive(Clone)]
+pub struct HmacSecret(pub Secret<String>);
+
+#[derive(Clone)]
+pub struct ConsentStatement(pub String);
+
 #[derive(Clone)]
 pub struct AppState {
     pub pool: Pool<Postgres>,
     pub email_client: EmailClient,
     pub access_url: AccessUrl,
+    pub hmac_secret: HmacSecret,
+    pub consent_statement: ConsentStatement,
+    pub trusted_proxies: TrustedProxies,
+    pub scheduler_poll_interval: Duration,
+    pub scheduler_issue_lease: Duration,
+    pub webhooks: WebhookSettings,
+    pub bot_protection: BotProtection,
+    pub email_policy: EmailPolicy,
+    pub deliverability_check: DeliverabilityCheck,
+    pub health_check: HealthCheck,
+    pub shutdown: CancellationToken,
+    pub shutdown_timeout: Duration,
+    pub metrics: Metrics,
 }
 
 impl FromRef<AppState> for Pool<Postgres> {
@@ -47,15 +89,185 @@ impl FromRef<AppState> for AccessUrl {
     }
 }
 
-pub async fn run(listener: TcpListener, app_state: AppState) {
-    let app = Router::new()
+impl FromRef<AppState> for HmacSecret {
+    fn from_ref(state: &AppState) -> Self {
+        state.hmac_secret.clone()
+    }
+}
+
+impl FromRef<AppState> for ConsentStatement {
+    fn from_ref(state: &AppState) -> Self {
+        state.consent_statement.clone()
+    }
+}
+
+impl FromRef<AppState> for TrustedProxies {
+    fn from_ref(state: &AppState) -> Self {
+        state.trusted_proxies.clone()
+    }
+}
+
+impl FromRef<AppState> for BotProtection {
+    fn from_ref(state: &AppState) -> Self {
+        state.bot_protection.clone()
+    }
+}
+
+impl FromRef<AppState> for EmailPolicy {
+    fn from_ref(state: &AppState) -> Self {
+        state.email_policy.clone()
+    }
+}
+
+impl FromRef<AppState> for DeliverabilityCheck {
+    fn from_ref(state: &AppState) -> Self {
+        state.deliverability_check.clone()
+    }
+}
+
+impl FromRef<AppState> for HealthCheck {
+    fn from_ref(state: &AppState) -> Self {
+        state.health_check.clone()
+    }
+}
+
+impl FromRef<AppState> for CancellationToken {
+    fn from_ref(state: &AppState) -> Self {
+        state.shutdown.clone()
+    }
+}
+
+impl FromRef<AppState> for Metrics {
+    fn from_ref(state: &AppState) -> Self {
+        state.metrics.clone()
+    }
+}
+
+impl FromRef<AppState> for WebhookSettings {
+    fn from_ref(state: &AppState) -> Self {
+        state.webhooks.clone()
+    }
+}
+
+// Runs until `app_state.shutdown` is cancelled, by SIGTERM or SIGINT, and then gives
+// in-flight requests and the scheduler `app_state.shutdown_timeout` to finish before closing
+// the connection pool. `/metrics` is served by `metrics_listener` when one is given.
+pub async fn run(
+    listener: TcpListener,
+    metrics_listener: Option<TcpListener>,
+    app_state: AppState,
+) {
+    let pool = app_state.pool.clone();
+    let shutdown = app_state.shutdown.clone();
+    let shutdown_timeout = app_state.shutdown_timeout;
+
+    tokio::spawn(cancel_on_signal(shutdown.clone()));
+    let scheduler = tokio::spawn(run_scheduler_until_stopped(
+        app_state.pool.clone(),
+        app_state.email_client.clone(),
+        app_state.access_url.clone(),
+        app_state.hmac_secret.clone(),
+        app_state.scheduler_poll_interval,
+        app_state.scheduler_issue_lease,
+        app_state.health_check.heartbeat.clone(),
+        shutdown.clone(),
+    ));
+
+    let metrics_router = Router::new().route("/metrics", get(get_metrics));
+    let mut app = Router::new()
         .route("/login", post(login))
         .route("/home", get(home))
         .route("/newsletters", post(publish_newsletter))
+        .route(
+            "/newsletters/:newsletter_issue_id",
+            get(get_newsletter)
+                .patch(reschedule_newsletter)
+                .delete(cancel_newsletter),
+        )
         .route("/subscriptions/confirm", get(confirm))
+        .route("/subscriptions/data", get(get_subscriber_data))
+        .route(
+            "/subscriptions/data-requests",
+            post(request_subscriber_data),
+        )
+        .route(
+            "/subscriptions/erasure",
+            get(get_erasure).post(erase_subscriber),
+        )
+        .route(
+            "/subscriptions/preferences",
+            get(get_preferences).post(update_preferences),
+        )
+        .route("/subscriptions/join", get(join_list))
+        .route("/subscriptions/form-token", get(get_form_token))
         .route("/subscriptions", post(subscribe))
-        .with_state(app_state)
+        .route("/webhooks/postmark", post(handle_postmark_webhook))
+        .route("/t/o/:token", get(track_open))
+        .route("/t/c/:token", get(track_click))
+        .route(
+            "/admin/newsletters/:newsletter_issue_id/engagement",
+            get(get_newsletter_engagement),
+        )
+        .route(
+            "/admin/fields",
+            get(get_custom_fields).post(add_custom_field),
+        )
+        .route("/admin/exports/subscribers", get(export_subscriber_data))
+        .route("/admin/imports", post(add_import))
+        .route("/admin/imports/:import_id", get(get_import))
+        .route("/admin/imports/:import_id/rows", post(upload_import_rows))
+        .route("/admin/lists", get(get_lists).post(add_list))
+        .route("/admin/segments", get(get_segments).post(add_segment))
+        .route("/admin/segments/:name", delete(remove_segment))
+        .route("/admin/segments/:name/preview", get(preview_segment))
+        .route("/admin/subscribers", get(get_subscribers))
+        .route(
+            "/admin/subscribers/:subscriber_id",
+            get(get_subscriber)
+                .patch(update_subscriber)
+                .delete(delete_subscriber),
+        )
+        .route(
+            "/admin/subscribers/:subscriber_id/confirm",
+            post(confirm_subscriber_manually),
+        )
+        .route(
+            "/admin/subscribers/:subscriber_id/unsubscribe",
+            post(unsubscribe_subscriber_manually),
+        )
+        .route(
+            "/admin/subscribers/:subscriber_id/fields",
+            patch(update_subscriber_fields),
+        )
+        .route(
+            "/admin/subscribers/:subscriber_id/tags",
+            put(replace_subscriber_tags),
+        )
+        .route(
+            "/admin/suppressions",
+            get(get_suppressions).post(add_suppression),
+        )
+        .route("/admin/suppressions/:email", delete(remove_suppression))
         .route("/health_check", get(check_health))
+        .route("/health/live", get(check_liveness))
+        .route("/health/ready", get(check_readiness));
+    match metrics_listener {
+        Some(metrics_listener) => {
+            let metrics_server = axum::serve(
+                metrics_listener,
+                metrics_router.with_state(app_state.clone()),
+            )
+            .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+            tokio::spawn(metrics_server.into_future());
+        }
+        None => app = app.merge(metrics_router),
+    }
+    let app = app
+        .route_layer(middleware::from_fn_with_state(
+            app_state.metrics.clone(),
+            track_http_metrics,
+        ))
+        .with_state(app_state)
         .layer(
             // Refer to https://github.com/tokio-rs/axum/blob/main/examples/tracing-aka-logging/Cargo.toml
             TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
@@ -63,19 +275,67 @@ pub async fn run(listener: TcpListener, app_state: AppState) {
                     .extensions()
                     .get::<MatchedPath>()
                     .map(MatchedPath::as_str);
+                let request_id = request
+                    .extensions()
+                    .get::<RequestId>()
+                    .map(RequestId::to_string);
 
-                tracing::info_span!(
+                let span = tracing::info_span!(
                     "Processing HTTP request",
                     method = ?request.method(),
                     path,
-                    request_id = %Uuid::new_v4(),
-                )
+                    request_id,
+                );
+                set_remote_parent(&span, request.headers());
+                span
             }),
-        );
+        )
+        .layer(middleware::from_fn(propagate_request_id));
 
-    axum::serve(listener, app)
-        .await
-        .expect("Failed to start up the application");
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+    .into_future();
+    let scheduler_handle = scheduler.abort_handle();
+    let drain = async {
+        server.await.expect("Failed to start up the application");
+        if let Err(error) = scheduler.await {
+            tracing::error!(error.cause_chain = ?error, "The scheduler failed while stopping");
+        }
+    };
+    let deadline = async {
+        shutdown.cancelled().await;
+        tokio::time::sleep(shutdown_timeout).await;
+    };
+
+    tokio::select! {
+        _ = drain => tracing::info!("Finished in-flight work"),
+        _ = deadline => {
+            tracing::warn!(
+                "In-flight work did not finish within {} seconds, dropping it",
+                shutdown_timeout.as_secs()
+            );
+            scheduler_handle.abort();
+        }
+    }
+
+    pool.close().await;
+}
+
+async fn cancel_on_signal(shutdown: CancellationToken) {
+    let interrupt = tokio::signal::ctrl_c();
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("Failed to listen for SIGTERM");
+
+    tokio::select! {
+        _ = interrupt => tracing::info!("Received SIGINT, shutting down"),
+        _ = terminate.recv() => tracing::info!("Received SIGTERM, shutting down"),
+        _ = shutdown.cancelled() => return,
+    }
+
+    shutdown.cancel();
 }
 
 pub async fn get_listener(configuration: &Settings) -> TcpListener {
@@ -87,20 +347,57 @@ pub async fn get_listener(configuration: &Settings) -> TcpListener {
     .expect("Failed to bind a port for application")
 }
 
+pub async fn get_metrics_listener(configuration: &Settings) -> Option<TcpListener> {
+    let port = configuration.metrics.port?;
+
+    Some(
+        TcpListener::bind(format!("{}:{}", configuration.application.host, port))
+            .await
+            .expect("Failed to bind a port for metrics"),
+    )
+}
+
 pub async fn get_app_state(configuration: &Settings) -> AppState {
+    let pool = db_connection_pool(configuration).await;
+    let metrics = Metrics::new();
+    let email_client = email_client(configuration).await.with_metrics(&metrics);
+
     AppState {
-        pool: db_connection_pool(configuration).await,
-        email_client: email_client(configuration).await,
+        metrics,
+        shutdown: CancellationToken::new(),
+        shutdown_timeout: configuration.application.shutdown_timeout(),
+        health_check: HealthCheck::from_settings(
+            &configuration.health,
+            pool.clone(),
+            email_client.clone(),
+        ),
+        pool,
+        email_client,
         access_url: AccessUrl(configuration.application.access_url.clone()),
+        hmac_secret: HmacSecret(configuration.application.hmac_secret.clone()),
+        consent_statement: ConsentStatement(configuration.application.consent_statement.clone()),
+        trusted_proxies: TrustedProxies(configuration.application.trusted_proxies.clone()),
+        scheduler_poll_interval: configuration.scheduler.poll_interval(),
+        scheduler_issue_lease: configuration.scheduler.issue_lease(),
+        webhooks: configuration.webhooks.clone(),
+        bot_protection: BotProtection::from_settings(
+            &configuration.bot_protection,
+            HmacSecret(configuration.application.hmac_secret.clone()),
+        ),
+        email_policy: EmailPolicy::from_settings(&configuration.email_validation)
+            .expect("Failed to load the disposable email domains"),
+        deliverability_check: DeliverabilityCheck::from_settings(
+            &configuration.deliverability_check,
+        )
+        .expect("Failed to read the DNS resolver configuration"),
     }
 }
 
 async fn db_connection_pool(configuration: &Settings) -> Pool<Postgres> {
-    PgPoolOptions::new()
-        .min_connections(5)
-        .max_connections(5)
-        .acquire_timeout(Duration::from_secs(5))
-        .connect(configuration.database.connection_string().expose_secret())
+    configuration
+        .database
+        .pool_options()
+        .connect_with(configuration.database.with_db())
         .await
         .expect("Failed to create database connection pool")
 }
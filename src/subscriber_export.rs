@@ -0,0 +1,199 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::{Stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{Pool, Postgres, QueryBuilder};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::consent::ConsentRecord;
+use crate::segments::Segment;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ExportFilter {
+    pub status: Option<String>,
+    pub list_id: Option<Uuid>,
+    pub segment: Option<Segment>,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct ExportedSubscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub lists: Vec<String>,
+    pub consent: Json<Vec<ConsentRecord>>,
+}
+
+const CSV_HEADER: &str = "id,email,name,status,subscribed_at,confirmed_at,lists,consent\n";
+
+// Rows are read with a cursor on a spawned task and handed over through a bounded channel,
+// so a slow consumer holds back the query instead of buffering the whole table.
+pub fn export_subscribers(
+    pool: Pool<Postgres>,
+    format: ExportFormat,
+    filter: ExportFilter,
+) -> impl Stream<Item = Result<String, anyhow::Error>> {
+    let (sender, receiver) = mpsc::channel(64);
+
+    tokio::spawn(async move {
+        if format == ExportFormat::Csv && sender.send(Ok(CSV_HEADER.to_string())).await.is_err() {
+            return;
+        }
+
+        let mut builder = QueryBuilder::new(
+            r#"
+                SELECT
+                    s.id,
+                    s.email,
+                    s.name,
+                    s.status,
+                    s.subscribed_at,
+                    s.confirmed_at,
+                    ARRAY(
+                        SELECT l.slug
+                        FROM list_memberships m
+                        JOIN lists l ON l.list_id = m.list_id
+                        WHERE m.subscriber_id = s.id AND m.status = 'subscribed'
+                        ORDER BY l.slug
+                    ) AS lists,
+                    (
+                        SELECT COALESCE(jsonb_agg(to_jsonb(c) - 'id' - 'subscriber_id' ORDER BY c.recorded_at, c.id), '[]')
+                        FROM consent_records c
+                        WHERE c.subscriber_id = s.id
+                    ) AS consent
+                FROM subscriptions s
+                WHERE TRUE
+            "#,
+        );
+        if let Some(status) = filter.status {
+            builder.push(" AND s.status = ");
+            builder.push_bind(status);
+        }
+        if let Some(list_id) = filter.list_id {
+            builder.push(
+                " AND EXISTS (SELECT 1 FROM list_memberships m WHERE m.subscriber_id = s.id AND m.status = 'subscribed' AND m.list_id = ",
+            );
+            builder.push_bind(list_id);
+            builder.push(")");
+        }
+        if let Some(segment) = &filter.segment {
+            builder.push(" AND ");
+            segment.push_sql(&mut builder);
+        }
+        builder.push(" ORDER BY s.subscribed_at, s.id");
+
+        let mut rows = builder.build_query_as::<ExportedSubscriber>().fetch(&pool);
+        loop {
+            let line = match rows.try_next().await {
+                Ok(Some(subscriber)) => format_line(format, &subscriber),
+                Ok(None) => break,
+                Err(error) => Err(anyhow::Error::new(error).context("Failed to read subscribers")),
+            };
+            let failed = line.is_err();
+            if sender.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|line| (line, receiver))
+    })
+}
+
+fn format_line(
+    format: ExportFormat,
+    subscriber: &ExportedSubscriber,
+) -> Result<String, anyhow::Error> {
+    match format {
+        ExportFormat::Ndjson => Ok(serde_json::to_string(subscriber)? + "\n"),
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+            writer.write_record([
+                subscriber.id.to_string(),
+                subscriber.email.clone(),
+                subscriber.name.clone(),
+                subscriber.status.clone(),
+                subscriber
+                    .subscribed_at
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                subscriber
+                    .confirmed_at
+                    .map(|confirmed_at| confirmed_at.to_rfc3339_opts(SecondsFormat::Secs, true))
+                    .unwrap_or_default(),
+                subscriber.lists.join(";"),
+                serde_json::to_string(&subscriber.consent)?,
+            ])?;
+
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use crate::subscriber_export::*;
+
+    fn subscriber() -> ExportedSubscriber {
+        ExportedSubscriber {
+            id: Uuid::nil(),
+            email: "arine@gmail.com".into(),
+            name: "You, Arine".into(),
+            status: "confirmed".into(),
+            subscribed_at: Utc.with_ymd_and_hms(2026, 10, 1, 9, 0, 0).unwrap(),
+            confirmed_at: None,
+            lists: vec!["newsletter".into(), "security".into()],
+            consent: Json(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn csv_lines_quote_fields_containing_separators() {
+        assert_eq!(
+            format_line(ExportFormat::Csv, &subscriber()).unwrap(),
+            "00000000-0000-0000-0000-000000000000,arine@gmail.com,\"You, Arine\",confirmed,2026-10-01T09:00:00Z,,newsletter;security,[]\n"
+        );
+    }
+
+    #[test]
+    fn ndjson_lines_are_terminated_by_a_newline() {
+        let line = format_line(ExportFormat::Ndjson, &subscriber()).unwrap();
+        assert!(line.ends_with("}\n"));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            value["lists"],
+            serde_json::json!(["newsletter", "security"])
+        );
+    }
+}
@@ -0,0 +1,597 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use csv_core::ReadRecordResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Transaction};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use uuid::Uuid;
+
+use crate::confirmation_queue::enqueue_confirmation_email;
+use crate::consent::{record_consent, ClientInfo, ConsentEvent, ConsentEvidence};
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_policy::EmailPolicy;
+use crate::routes::generate_subscription_token;
+use crate::startup::HmacSecret;
+use crate::suppression::is_suppressed;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl ImportFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ImportFormat::Csv => "csv",
+            ImportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InitialStatus {
+    Confirmed,
+    Pending,
+}
+
+impl InitialStatus {
+    fn as_subscription_status(&self) -> &'static str {
+        match self {
+            InitialStatus::Confirmed => "confirmed",
+            InitialStatus::Pending => "pending_confirmation",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImportOptions {
+    pub format: ImportFormat,
+    pub status: InitialStatus,
+    pub consent_source: Option<String>,
+    pub list_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct ImportRowError {
+    pub row: i64,
+    pub email: Option<String>,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct ImportReport {
+    pub import_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub rows: i64,
+    pub imported: i64,
+    pub duplicates: i64,
+    pub failed: i64,
+    pub errors: Vec<ImportRowError>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("{0}")]
+    InvalidFile(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[derive(Deserialize)]
+struct JsonRow {
+    email: Option<String>,
+    name: Option<String>,
+}
+
+enum RowOutcome {
+    Imported,
+    Duplicate,
+    Failed(String),
+}
+
+#[tracing::instrument(name = "Create subscriber import", skip(pool))]
+pub async fn create_import(
+    pool: &Pool<Postgres>,
+    options: &ImportOptions,
+    created_by: Option<Uuid>,
+) -> Result<Uuid, ImportError> {
+    let consent_source = options
+        .consent_source
+        .as_deref()
+        .map(str::trim)
+        .filter(|source| !source.is_empty());
+    if options.status == InitialStatus::Confirmed && consent_source.is_none() {
+        return Err(ImportError::InvalidFile(
+            "consent_source is required to import confirmed subscribers".into(),
+        ));
+    }
+
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriber_imports (
+                import_id, format, initial_status, consent_source, list_id, created_by, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        import_id,
+        options.format.as_str(),
+        options.status.as_subscription_status(),
+        consent_source,
+        options.list_id,
+        created_by,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create a subscriber import")?;
+
+    Ok(import_id)
+}
+
+// Every row is committed together with the import's progress, so uploading the same file
+// again after an interruption skips the rows which were already processed. Rows are CSV
+// records, which may span several lines, or JSON lines. Confirmation emails for pending
+// subscribers are queued with their row and sent by the worker.
+#[tracing::instrument(
+    name = "Run subscriber import",
+    skip(pool, email_policy, hmac_secret, reader)
+)]
+pub async fn run_import(
+    pool: &Pool<Postgres>,
+    email_policy: &EmailPolicy,
+    hmac_secret: &HmacSecret,
+    import_id: Uuid,
+    mut reader: impl AsyncBufRead + Unpin,
+) -> Result<Option<ImportReport>, ImportError> {
+    let import = match sqlx::query!(
+        r#"
+            SELECT format, initial_status, consent_source, list_id
+            FROM subscriber_imports
+            WHERE import_id = $1
+        "#,
+        import_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber import")?
+    {
+        Some(import) => import,
+        None => return Ok(None),
+    };
+
+    let mut csv_reader = csv_core::Reader::new();
+    let mut line = String::new();
+    let mut row: i64 = 0;
+    let mut columns = None;
+
+    loop {
+        let parsed = match import.format.as_str() {
+            "csv" => {
+                let record = match read_csv_record(&mut reader, &mut csv_reader)
+                    .await
+                    .context("Failed to read the import file")?
+                {
+                    Some(record) => record,
+                    None => break,
+                };
+                row += 1;
+                match &columns {
+                    None => {
+                        columns = Some(parse_csv_header(record)?);
+                        continue;
+                    }
+                    Some(columns) => parse_csv_row(record, columns),
+                }
+            }
+            _ => {
+                line.clear();
+                if reader
+                    .read_line(&mut line)
+                    .await
+                    .context("Failed to read the import file")?
+                    == 0
+                {
+                    break;
+                }
+                row += 1;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                parse_json_row(&line)
+            }
+        };
+
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a PostgreSQL connection from the pool")?;
+        let last_row = sqlx::query!(
+            "SELECT last_row FROM subscriber_imports WHERE import_id = $1 FOR UPDATE",
+            import_id,
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to lock a subscriber import")?
+        .last_row;
+        if row <= last_row {
+            continue;
+        }
+
+        let (email, outcome) = match parsed {
+            Ok(new_subscriber) => (
+                Some(new_subscriber.email.as_ref().to_owned()),
+                match email_policy.check(&new_subscriber.email) {
+                    Err(rejection) => RowOutcome::Failed(rejection.to_string()),
+                    Ok(()) => import_subscriber(
+                        &mut transaction,
+                        hmac_secret,
+                        &email_policy.normalise(&new_subscriber.email),
+                        new_subscriber,
+                        &import.initial_status,
+                        import.consent_source.as_deref(),
+                        import.list_id,
+                    )
+                    .await
+                    .context("Failed to import a subscriber")?,
+                },
+            ),
+            Err((email, error)) => (email, RowOutcome::Failed(error)),
+        };
+
+        let (imported, duplicates, failed) = match &outcome {
+            RowOutcome::Imported => (1, 0, 0),
+            RowOutcome::Duplicate => (0, 1, 0),
+            RowOutcome::Failed(_) => (0, 0, 1),
+        };
+        let error = match &outcome {
+            RowOutcome::Imported => None,
+            RowOutcome::Duplicate => Some("Email address is already subscribed".to_string()),
+            RowOutcome::Failed(error) => Some(error.clone()),
+        };
+        if let Some(error) = error {
+            record_row_error(&mut transaction, import_id, row, email.as_deref(), &error)
+                .await
+                .context("Failed to record an import error")?;
+        }
+        sqlx::query!(
+            r#"
+                UPDATE subscriber_imports
+                SET last_row = $2,
+                    imported = imported + $3,
+                    duplicates = duplicates + $4,
+                    failed = failed + $5
+                WHERE import_id = $1
+            "#,
+            import_id,
+            row,
+            imported,
+            duplicates,
+            failed,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to record the progress of a subscriber import")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to import a subscriber")?;
+    }
+
+    if import.format == "csv" && columns.is_none() {
+        return Err(ImportError::InvalidFile(
+            "The CSV file has no header".into(),
+        ));
+    }
+
+    sqlx::query!(
+        "UPDATE subscriber_imports SET finished_at = now() WHERE import_id = $1",
+        import_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to finish a subscriber import")?;
+
+    Ok(get_import_report(pool, import_id)
+        .await
+        .context("Failed to retrieve the report of a subscriber import")?)
+}
+
+#[tracing::instrument(name = "Get subscriber import report", skip(pool))]
+pub async fn get_import_report(
+    pool: &Pool<Postgres>,
+    import_id: Uuid,
+) -> Result<Option<ImportReport>, sqlx::Error> {
+    let import = match sqlx::query!(
+        r#"
+            SELECT created_at, finished_at, last_row, imported, duplicates, failed
+            FROM subscriber_imports
+            WHERE import_id = $1
+        "#,
+        import_id,
+    )
+    .fetch_optional(pool)
+    .await?
+    {
+        Some(import) => import,
+        None => return Ok(None),
+    };
+
+    let errors = sqlx::query_as!(
+        ImportRowError,
+        r#"
+            SELECT row_number AS row, email, error
+            FROM subscriber_import_errors
+            WHERE import_id = $1
+            ORDER BY row_number
+        "#,
+        import_id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(ImportReport {
+        import_id,
+        created_at: import.created_at,
+        finished_at: import.finished_at,
+        rows: import.last_row,
+        imported: import.imported,
+        duplicates: import.duplicates,
+        failed: import.failed,
+        errors,
+    }))
+}
+
+fn parse_csv_header(
+    record: Result<csv::StringRecord, String>,
+) -> Result<(usize, usize), ImportError> {
+    let header =
+        record.map_err(|e| ImportError::InvalidFile(format!("Invalid CSV header: {}", e)))?;
+    let position = |column: &str| {
+        header
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(column))
+            .ok_or_else(|| {
+                ImportError::InvalidFile(format!("The CSV header has no {} column", column))
+            })
+    };
+
+    Ok((position("email")?, position("name")?))
+}
+
+fn parse_csv_row(
+    record: Result<csv::StringRecord, String>,
+    (email, name): &(usize, usize),
+) -> Result<NewSubscriber, (Option<String>, String)> {
+    let record = record.map_err(|e| (None, e))?;
+
+    parse_subscriber(
+        record.get(*email).map(|email| email.trim().to_owned()),
+        record.get(*name).map(|name| name.trim().to_owned()),
+    )
+}
+
+// Reads the next record, which ends at the first line break outside quotes, or `None` at the
+// end of the file. A record which is not valid UTF-8 is an error for that record alone.
+async fn read_csv_record(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    csv_reader: &mut csv_core::Reader,
+) -> Result<Option<Result<csv::StringRecord, String>>, std::io::Error> {
+    let mut output = vec![0; 1024];
+    let mut ends = vec![0; 16];
+    let (mut output_len, mut ends_len) = (0, 0);
+
+    loop {
+        let input = reader.fill_buf().await?;
+        let (result, read, written, ended) =
+            csv_reader.read_record(input, &mut output[output_len..], &mut ends[ends_len..]);
+        reader.consume(read);
+        output_len += written;
+        ends_len += ended;
+
+        match result {
+            ReadRecordResult::InputEmpty => {}
+            ReadRecordResult::OutputFull => output.resize(output.len() * 2, 0),
+            ReadRecordResult::OutputEndsFull => ends.resize(ends.len() * 2, 0),
+            ReadRecordResult::Record => break,
+            ReadRecordResult::End => return Ok(None),
+        }
+    }
+
+    let mut record = csv::ByteRecord::new();
+    let mut start = 0;
+    for &end in &ends[..ends_len] {
+        record.push_field(&output[start..end]);
+        start = end;
+    }
+
+    Ok(Some(
+        csv::StringRecord::from_byte_record(record).map_err(|e| e.to_string()),
+    ))
+}
+
+fn parse_json_row(line: &str) -> Result<NewSubscriber, (Option<String>, String)> {
+    let row: JsonRow = serde_json::from_str(line).map_err(|e| (None, e.to_string()))?;
+
+    parse_subscriber(row.email, row.name)
+}
+
+fn parse_subscriber(
+    email: Option<String>,
+    name: Option<String>,
+) -> Result<NewSubscriber, (Option<String>, String)> {
+    let email = email.ok_or((None, "Missing email".to_string()))?;
+    let name = name.ok_or((Some(email.clone()), "Missing name".to_string()))?;
+
+    Ok(NewSubscriber {
+        id: Uuid::new_v4(),
+        email: SubscriberEmail::parse(email.clone()).map_err(|e| (Some(email.clone()), e))?,
+        name: SubscriberName::parse(name).map_err(|e| (Some(email), e))?,
+    })
+}
+
+async fn import_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    hmac_secret: &HmacSecret,
+    normalised_email: &str,
+    new_subscriber: NewSubscriber,
+    status: &str,
+    consent_source: Option<&str>,
+    list_id: Uuid,
+) -> Result<RowOutcome, sqlx::Error> {
+    if is_suppressed(&mut **transaction, hmac_secret, normalised_email).await? {
+        return Ok(RowOutcome::Failed("Email address is suppressed".into()));
+    }
+
+    let inserted = sqlx::query!(
+        r#"
+            INSERT INTO subscriptions (
+                id, email, normalised_email, name, subscribed_at, status, consent_source,
+                confirmed_at
+            )
+            SELECT $1, $2, $3, $4, now(), $5, $6, CASE WHEN $5 = 'confirmed' THEN now() END
+            WHERE NOT EXISTS (SELECT 1 FROM subscriptions WHERE normalised_email = $3)
+            ON CONFLICT DO NOTHING
+        "#,
+        new_subscriber.id,
+        new_subscriber.email.as_ref(),
+        normalised_email,
+        new_subscriber.name.as_ref(),
+        status,
+        consent_source,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Ok(RowOutcome::Duplicate);
+    }
+    let evidence = ConsentEvidence {
+        event: ConsentEvent::Imported,
+        source: consent_source.unwrap_or("import"),
+        client: &ClientInfo::default(),
+        consent_text: None,
+    };
+    record_consent(&mut **transaction, new_subscriber.id, &evidence).await?;
+
+    sqlx::query!(
+        r#"
+            INSERT INTO list_memberships (list_id, subscriber_id, status, updated_at)
+            VALUES ($1, $2, 'subscribed', now())
+        "#,
+        list_id,
+        new_subscriber.id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    if status == "confirmed" {
+        return Ok(RowOutcome::Imported);
+    }
+
+    let subscription_token = generate_subscription_token();
+    sqlx::query!(
+        r#"
+            INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+            VALUES ($1, $2)
+        "#,
+        subscription_token,
+        new_subscriber.id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    enqueue_confirmation_email(&mut **transaction, &subscription_token).await?;
+
+    Ok(RowOutcome::Imported)
+}
+
+async fn record_row_error(
+    transaction: &mut Transaction<'_, Postgres>,
+    import_id: Uuid,
+    row: i64,
+    email: Option<&str>,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriber_import_errors (import_id, row_number, email, error)
+            VALUES ($1, $2, $3, $4)
+        "#,
+        import_id,
+        row,
+        email,
+        error,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_err;
+
+    use crate::subscriber_import::*;
+
+    async fn read_csv_records(file: &str) -> Vec<Result<csv::StringRecord, String>> {
+        let mut reader = file.as_bytes();
+        let mut csv_reader = csv_core::Reader::new();
+        let mut records = Vec::new();
+        while let Some(record) = read_csv_record(&mut reader, &mut csv_reader).await.unwrap() {
+            records.push(record);
+        }
+        records
+    }
+
+    async fn read_csv_row(line: &str) -> Result<NewSubscriber, (Option<String>, String)> {
+        let record = read_csv_records(line).await.pop().unwrap();
+        parse_csv_row(record, &(0, 1))
+    }
+
+    #[tokio::test]
+    async fn csv_header_columns_are_found_in_any_order() {
+        let header = read_csv_records("Name, Email ,country")
+            .await
+            .pop()
+            .unwrap();
+        assert_eq!(parse_csv_header(header).unwrap(), (1, 0));
+        let header = read_csv_records("email,country").await.pop().unwrap();
+        assert_err!(parse_csv_header(header));
+    }
+
+    #[tokio::test]
+    async fn quoted_csv_fields_are_parsed() {
+        let subscriber = read_csv_row(r#""arine@gmail.com","You, Arine""#)
+            .await
+            .unwrap();
+        assert_eq!(subscriber.email.as_ref(), "arine@gmail.com");
+        assert_eq!(subscriber.name.as_ref(), "You, Arine");
+    }
+
+    #[tokio::test]
+    async fn csv_records_may_span_several_lines() {
+        let records = read_csv_records(
+            "email,name\r\n\nfirst@example.com,\"First\nReader\"\nsecond@example.com,Second",
+        )
+        .await;
+        assert_eq!(records.len(), 3);
+        let subscriber = parse_csv_row(records[1].clone(), &(0, 1)).unwrap();
+        assert_eq!(subscriber.name.as_ref(), "First\nReader");
+        let subscriber = parse_csv_row(records[2].clone(), &(0, 1)).unwrap();
+        assert_eq!(subscriber.email.as_ref(), "second@example.com");
+    }
+
+    #[tokio::test]
+    async fn invalid_rows_are_reported_with_their_email() {
+        let (email, _) = read_csv_row("arine@gmail.com,").await.err().unwrap();
+        assert_eq!(email.as_deref(), Some("arine@gmail.com"));
+        let (email, _) = parse_json_row(r#"{"email": "not-an-email", "name": "arine"}"#)
+            .err()
+            .unwrap();
+        assert_eq!(email.as_deref(), Some("not-an-email"));
+        assert!(parse_json_row(r#"{"email": "arine@gmail.com", "name": "arine"}"#).is_ok());
+    }
+}
@@ -0,0 +1,220 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, PgConnection, PgExecutor};
+
+use crate::signature::keyed_hash;
+use crate::startup::HmacSecret;
+
+// Erased subscribers leave only `email_hash` behind, so `email` is empty for them.
+#[derive(Serialize)]
+pub struct Suppression {
+    pub email: Option<String>,
+    pub email_hash: String,
+    pub reason: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// The hash is keyed, as the handful of addresses somebody might have are quickly tried
+// against a plain digest. The address is digested before it is keyed, so that the plain
+// digests of earlier versions can be keyed without knowing the address, see
+// `key_legacy_email_hashes`.
+pub fn email_hash(secret: &HmacSecret, normalised_email: &str) -> String {
+    keyed_hash(
+        secret,
+        &format!("{:x}", Sha256::digest(normalised_email.as_bytes())),
+    )
+}
+
+#[tracing::instrument(
+    name = "Check whether an email address is suppressed",
+    skip(executor, hmac_secret)
+)]
+pub async fn is_suppressed(
+    executor: impl PgExecutor<'_>,
+    hmac_secret: &HmacSecret,
+    normalised_email: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM suppressions WHERE email_hash = $1) AS "suppressed!""#,
+        email_hash(hmac_secret, normalised_email),
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(row.suppressed)
+}
+
+#[tracing::instrument(
+    name = "Add an email address to the suppression list",
+    skip(executor, hmac_secret)
+)]
+pub async fn suppress(
+    executor: impl PgExecutor<'_>,
+    hmac_secret: &HmacSecret,
+    normalised_email: &str,
+    reason: &str,
+    source: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO suppressions (email, email_hash, reason, source, created_at)
+            VALUES ($1, $2, $3, $4, now())
+            ON CONFLICT DO NOTHING
+        "#,
+        normalised_email,
+        email_hash(hmac_secret, normalised_email),
+        reason,
+        source,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// Suppresses the address without keeping it in clear text, e.g. after an erasure request.
+#[tracing::instrument(
+    name = "Add a tombstone to the suppression list",
+    skip(executor, hmac_secret, normalised_email)
+)]
+pub async fn add_tombstone(
+    executor: impl PgExecutor<'_>,
+    hmac_secret: &HmacSecret,
+    normalised_email: &str,
+    reason: &str,
+    source: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO suppressions (email, email_hash, reason, source, created_at)
+            VALUES (NULL, $1, $2, $3, now())
+            ON CONFLICT (email_hash) DO UPDATE SET email = NULL
+        "#,
+        email_hash(hmac_secret, normalised_email),
+        reason,
+        source,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Remove an email address from the suppression list",
+    skip(executor, hmac_secret)
+)]
+pub async fn unsuppress(
+    executor: impl PgExecutor<'_>,
+    hmac_secret: &HmacSecret,
+    normalised_email: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM suppressions WHERE email_hash = $1",
+        email_hash(hmac_secret, normalised_email)
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "List suppressed email addresses", skip(executor))]
+pub async fn list_suppressions(
+    executor: impl PgExecutor<'_>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+            SELECT email, email_hash, reason, source, created_at
+            FROM suppressions
+            ORDER BY created_at DESC, email_hash
+            LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+// Keys the plain SHA-256 digests written before the hashes were keyed. The database does not
+// know the secret, so this runs right after the migrations rather than in one of them.
+#[tracing::instrument(name = "Key legacy suppression hashes", skip_all)]
+pub async fn key_legacy_email_hashes(
+    connection: &mut PgConnection,
+    hmac_secret: &HmacSecret,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = connection.begin().await?;
+    let legacy_hashes: Vec<String> =
+        sqlx::query!("SELECT email_hash FROM suppressions WHERE legacy_email_hash FOR UPDATE")
+            .fetch_all(&mut *transaction)
+            .await?
+            .into_iter()
+            .map(|r| r.email_hash)
+            .collect();
+    if legacy_hashes.is_empty() {
+        return Ok(());
+    }
+    let keyed_hashes: Vec<String> = legacy_hashes
+        .iter()
+        .map(|legacy_hash| keyed_hash(hmac_secret, legacy_hash))
+        .collect();
+
+    sqlx::query!(
+        r#"
+            UPDATE suppressions s
+            SET email_hash = k.keyed_hash, legacy_email_hash = FALSE
+            FROM UNNEST($1::text[], $2::text[]) AS k(legacy_hash, keyed_hash)
+            WHERE s.email_hash = k.legacy_hash
+        "#,
+        &legacy_hashes,
+        &keyed_hashes,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    tracing::info!("Keyed {} suppression hashes", legacy_hashes.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use sha2::{Digest, Sha256};
+
+    use crate::signature::keyed_hash;
+    use crate::startup::HmacSecret;
+    use crate::suppression::email_hash;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("super-secret".into()))
+    }
+
+    #[test]
+    fn email_hash_depends_on_the_secret() {
+        let another_secret = HmacSecret(Secret::new("another-secret".into()));
+        assert_ne!(
+            email_hash(&secret(), "someone@example.com"),
+            email_hash(&another_secret, "someone@example.com")
+        );
+        assert_ne!(
+            email_hash(&secret(), "someone@example.com"),
+            "72497f475e4f76d0b28f57c73a084ece576d170874eba3ee2609d9afe4b71aab"
+        );
+    }
+
+    #[test]
+    fn keyed_legacy_hashes_match_new_hashes() {
+        let legacy_hash = format!("{:x}", Sha256::digest("someone@example.com".as_bytes()));
+        assert_eq!(
+            keyed_hash(&secret(), &legacy_hash),
+            email_hash(&secret(), "someone@example.com")
+        );
+    }
+}
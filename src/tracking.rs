@@ -0,0 +1,186 @@
+use uuid::Uuid;
+
+use crate::signature::{sign, verify, InvalidSignature};
+use crate::startup::HmacSecret;
+
+#[derive(Debug, PartialEq)]
+pub enum TrackingEvent {
+    Open,
+    Click { url: String },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TrackingToken {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Uuid,
+    pub event: TrackingEvent,
+}
+
+impl TrackingToken {
+    pub fn sign(&self, secret: &HmacSecret) -> String {
+        let payload = match &self.event {
+            TrackingEvent::Open => {
+                format!("o:{}:{}", self.newsletter_issue_id, self.subscriber_id)
+            }
+            TrackingEvent::Click { url } => {
+                format!(
+                    "c:{}:{}:{}",
+                    self.newsletter_issue_id, self.subscriber_id, url
+                )
+            }
+        };
+
+        sign(secret, &payload)
+    }
+
+    pub fn verify(token: &str, secret: &HmacSecret) -> Result<Self, InvalidSignature> {
+        let payload = verify(secret, token)?;
+        let mut parts = payload.splitn(4, ':');
+
+        let kind = parts.next();
+        let newsletter_issue_id = parts
+            .next()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or(InvalidSignature)?;
+        let subscriber_id = parts
+            .next()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or(InvalidSignature)?;
+        let event = match (kind, parts.next()) {
+            (Some("o"), None) => TrackingEvent::Open,
+            (Some("c"), Some(url)) => TrackingEvent::Click { url: url.into() },
+            _ => return Err(InvalidSignature),
+        };
+
+        Ok(Self {
+            newsletter_issue_id,
+            subscriber_id,
+            event,
+        })
+    }
+}
+
+pub fn add_tracking(
+    html: &str,
+    access_url: &str,
+    secret: &HmacSecret,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> String {
+    let mut tracked = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find("href=\"") {
+        let (before, after) = rest.split_at(start + "href=\"".len());
+        tracked.push_str(before);
+
+        let end = match after.find('"') {
+            Some(end) => end,
+            None => {
+                rest = after;
+                break;
+            }
+        };
+        let link = &after[..end];
+        if link.starts_with("http://") || link.starts_with("https://") {
+            let token = TrackingToken {
+                newsletter_issue_id,
+                subscriber_id,
+                event: TrackingEvent::Click {
+                    url: link.replace("&amp;", "&"),
+                },
+            };
+            tracked.push_str(&format!("{}/t/c/{}", access_url, token.sign(secret)));
+        } else {
+            tracked.push_str(link);
+        }
+        rest = &after[end..];
+    }
+    tracked.push_str(rest);
+
+    let token = TrackingToken {
+        newsletter_issue_id,
+        subscriber_id,
+        event: TrackingEvent::Open,
+    };
+    let pixel = format!(
+        r#"<img src="{}/t/o/{}" width="1" height="1" alt="" />"#,
+        access_url,
+        token.sign(secret)
+    );
+    match tracked.rfind("</body>") {
+        Some(position) => tracked.insert_str(position, &pixel),
+        None => tracked.push_str(&pixel),
+    }
+
+    tracked
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+
+    use crate::tracking::*;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("super-secret".into()))
+    }
+
+    fn click_token(url: &str) -> TrackingToken {
+        TrackingToken {
+            newsletter_issue_id: Uuid::new_v4(),
+            subscriber_id: Uuid::new_v4(),
+            event: TrackingEvent::Click { url: url.into() },
+        }
+    }
+
+    #[test]
+    fn signed_click_token_is_verified() {
+        let token = click_token("https://example.com/a:b?c=d");
+        let signed = token.sign(&secret());
+        assert_ok_eq!(TrackingToken::verify(&signed, &secret()), token);
+    }
+
+    #[test]
+    fn click_token_for_another_url_is_rejected() {
+        let signed = click_token("https://example.com").sign(&secret());
+        let forged = signed.replacen(
+            signed.split_once('.').unwrap().0,
+            &base64::Engine::encode(
+                &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+                format!("c:{}:{}:https://evil.com", Uuid::new_v4(), Uuid::new_v4()),
+            ),
+            1,
+        );
+        assert_err!(TrackingToken::verify(&forged, &secret()));
+    }
+
+    #[test]
+    fn absolute_links_are_rewritten_and_pixel_is_added() {
+        let html = r##"<html><body><a href="https://example.com/?a=1&amp;b=2">link</a> <a href="#top">top</a></body></html>"##;
+        let tracked = add_tracking(
+            html,
+            "http://localhost",
+            &secret(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+
+        assert!(!tracked.contains("https://example.com"));
+        assert!(tracked.contains(r##"href="#top""##));
+        assert!(tracked.contains(r#"<a href="http://localhost/t/c/"#));
+        assert!(tracked.contains(r#"<img src="http://localhost/t/o/"#));
+        assert!(tracked.ends_with(r#" /></body></html>"#));
+
+        let start = tracked.find("/t/c/").unwrap() + "/t/c/".len();
+        let end = start + tracked[start..].find('"').unwrap();
+        let token = TrackingToken::verify(&tracked[start..end], &secret()).unwrap();
+        assert_eq!(
+            token.event,
+            TrackingEvent::Click {
+                url: "https://example.com/?a=1&b=2".into()
+            }
+        );
+    }
+}
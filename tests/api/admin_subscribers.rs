@@ -0,0 +1,412 @@
+use reqwest::{Method, StatusCode};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn listing_subscribers_requires_valid_credentials() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::GET, "/admin/subscribers")
+        .basic_auth(Uuid::new_v4(), Some(Uuid::new_v4()))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn subscribers_are_filtered_by_status_and_email_prefix() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("alice@example.com").await;
+    app.insert_confirmed_subscriber("al_ice@example.com").await;
+    app.insert_confirmed_subscriber("Bob@Example.com").await;
+    set_status(&app, "Bob@Example.com", "pending_confirmation").await;
+
+    for (query, expected) in [
+        (
+            "status=confirmed",
+            vec!["al_ice@example.com", "alice@example.com"],
+        ),
+        ("status=pending_confirmation", vec!["Bob@Example.com"]),
+        (
+            "email_prefix=AL",
+            vec!["al_ice@example.com", "alice@example.com"],
+        ),
+        ("email_prefix=bob", vec!["Bob@Example.com"]),
+        ("email_prefix=al_", vec!["al_ice@example.com"]),
+        ("subscribed_after=2100-01-01T00:00:00Z", vec![]),
+    ] {
+        let body = list_subscribers(&app, query).await;
+        let mut emails: Vec<&str> = body["subscribers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|subscriber| subscriber["email"].as_str().unwrap())
+            .collect();
+        emails.sort();
+
+        assert_eq!(emails, expected, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_with_a_cursor() {
+    let app = App::new().await;
+    for i in 0..5 {
+        app.insert_confirmed_subscriber(&format!("reader{}@example.com", i))
+            .await;
+    }
+
+    let mut emails = Vec::new();
+    let mut query = "limit=2".to_string();
+    let mut pages = 0;
+    loop {
+        let body = list_subscribers(&app, &query).await;
+        pages += 1;
+        for subscriber in body["subscribers"].as_array().unwrap() {
+            emails.push(subscriber["email"].as_str().unwrap().to_owned());
+        }
+        match body["next_cursor"].as_str() {
+            Some(cursor) => query = format!("limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+
+    emails.sort();
+    emails.dedup();
+    assert_eq!(emails.len(), 5);
+    assert_eq!(pages, 3);
+}
+
+#[tokio::test]
+async fn invalid_cursors_are_rejected() {
+    let app = App::new().await;
+
+    let response = app
+        .authenticated_request(Method::GET, "/admin/subscribers?cursor=invalid")
+        .await
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn subscriber_details_include_lists() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("reader@example.com").await;
+
+    let response = subscriber_request(&app, Method::GET, subscriber_id)
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email"], "reader@example.com");
+    assert_eq!(
+        body["lists"],
+        serde_json::json!([{ "slug": "newsletter", "status": "subscribed" }])
+    );
+
+    let response = subscriber_request(&app, Method::GET, Uuid::new_v4())
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn subscribers_are_updated_with_validated_values() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("reader@example.com").await;
+    app.insert_confirmed_subscriber("taken@example.com").await;
+    app.authenticated_request(Method::POST, "/admin/suppressions")
+        .await
+        .json(&serde_json::json!({ "email": "suppressed@example.com" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    for (body, expected, field) in [
+        (
+            serde_json::json!({ "name": "" }),
+            StatusCode::BAD_REQUEST,
+            Some("name"),
+        ),
+        (
+            serde_json::json!({ "email": "not-an-email" }),
+            StatusCode::BAD_REQUEST,
+            Some("email"),
+        ),
+        (
+            serde_json::json!({ "email": "reader@mailinator.com" }),
+            StatusCode::BAD_REQUEST,
+            Some("email"),
+        ),
+        (
+            serde_json::json!({ "email": "Suppressed@Example.com" }),
+            StatusCode::BAD_REQUEST,
+            Some("email"),
+        ),
+        (
+            serde_json::json!({ "email": "taken@example.com" }),
+            StatusCode::CONFLICT,
+            None,
+        ),
+        (
+            serde_json::json!({ "name": "Arine You", "email": "new@example.com" }),
+            StatusCode::OK,
+            None,
+        ),
+    ] {
+        let response = subscriber_request(&app, Method::PATCH, subscriber_id)
+            .await
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected, "{}", body);
+        if let Some(field) = field {
+            let problem: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(problem["errors"][0]["field"], field, "{}", body);
+        }
+    }
+
+    let saved = sqlx::query!(
+        "SELECT name, email, status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.name, "Arine You");
+    assert_eq!(saved.email, "new@example.com");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribers_confirm_a_new_email_address_again() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("reader@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Changing only the spelling of the address keeps the subscriber confirmed.
+    for email in ["Reader@example.com", "new@example.com"] {
+        let response = subscriber_request(&app, Method::PATCH, subscriber_id)
+            .await
+            .json(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let body: serde_json::Value = subscriber_request(&app, Method::GET, subscriber_id)
+        .await
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+
+    // The worker sends the confirmation email to the new address.
+    let mut email_requests = Vec::new();
+    for _ in 0..50 {
+        email_requests = app.email_server.received_requests().await.unwrap();
+        if !email_requests.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let email_body: serde_json::Value = email_requests[0].body_json().unwrap();
+    assert_eq!(email_body["To"], "new@example.com");
+    let confirmation_links = app.get_confirmation_links(&email_requests[0]);
+    let response = reqwest::get(confirmation_links.in_html).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let status = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap()
+    .status;
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_their_deliveries() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("reader@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "content": { "html": "<p>Newsletter body as HTML</p>" },
+    }))
+    .await;
+
+    let response = subscriber_request(&app, Method::DELETE, subscriber_id)
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let remaining = sqlx::query!(
+        r#"SELECT (SELECT COUNT(*) FROM subscriptions) + (SELECT COUNT(*) FROM deliveries) AS "count!""#
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining.count, 0);
+
+    let response = subscriber_request(&app, Method::DELETE, subscriber_id)
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn manual_status_changes_record_who_made_them() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("reader@example.com").await;
+    set_status(&app, "reader@example.com", "pending_confirmation").await;
+    let (username, password) = app.add_test_user().await;
+
+    for (action, expected) in [("confirm", "confirmed"), ("unsubscribe", "unsubscribed")] {
+        let response = app
+            .build_request(
+                Method::POST,
+                &format!("/admin/subscribers/{}/{}", subscriber_id, action),
+            )
+            .basic_auth(&username, Some(&password))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["status"], expected);
+    }
+
+    let response = subscriber_request(&app, Method::GET, subscriber_id)
+        .await
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    let changes = body["status_changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0]["old_status"], "pending_confirmation");
+    assert_eq!(changes[1]["new_status"], "unsubscribed");
+    assert_eq!(changes[1]["changed_by"], username.as_str());
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_cannot_be_confirmed_manually() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("reader@example.com").await;
+    set_status(&app, "reader@example.com", "suppressed").await;
+
+    let response = app
+        .authenticated_request(
+            Method::POST,
+            &format!("/admin/subscribers/{}/confirm", subscriber_id),
+        )
+        .await
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_cannot_be_confirmed_manually() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("reader@example.com").await;
+    set_status(&app, "reader@example.com", "unsubscribed").await;
+
+    let response = app
+        .authenticated_request(
+            Method::POST,
+            &format!("/admin/subscribers/{}/confirm", subscriber_id),
+        )
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let consent = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM consent_records WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(consent.count, 0);
+}
+
+async fn list_subscribers(app: &App, query: &str) -> serde_json::Value {
+    let response = app
+        .authenticated_request(Method::GET, &format!("/admin/subscribers?{}", query))
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    response.json().await.unwrap()
+}
+
+async fn subscriber_request(
+    app: &App,
+    method: Method,
+    subscriber_id: Uuid,
+) -> reqwest::RequestBuilder {
+    app.authenticated_request(method, &format!("/admin/subscribers/{}", subscriber_id))
+        .await
+}
+
+async fn set_status(app: &App, email: &str, status: &str) {
+    sqlx::query!(
+        "UPDATE subscriptions SET status = $2 WHERE email = $1",
+        email,
+        status
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+}
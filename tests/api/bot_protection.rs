@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{Method, StatusCode};
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use newsletter::bot_protection::ChallengeVerifier;
+
+use crate::helpers::App;
+
+struct StubChallengeVerifier;
+
+#[async_trait]
+impl ChallengeVerifier for StubChallengeVerifier {
+    async fn verify(
+        &self,
+        response: &str,
+        _ip_address: Option<&str>,
+    ) -> Result<bool, anyhow::Error> {
+        Ok(response == "solved")
+    }
+}
+
+#[tokio::test]
+async fn suspected_bot_submissions_look_successful_but_are_dropped() {
+    let app = App::new().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let fresh_token: serde_json::Value = app
+        .build_request(Method::GET, "/subscriptions/form-token")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let fresh_token = fresh_token["form_token"].as_str().unwrap().to_string();
+    let valid_token = app.form_token();
+
+    let test_cases = [
+        (vec![], "without a form token"),
+        (vec![("form_token", "forged")], "with a forged form token"),
+        (
+            vec![("form_token", fresh_token.as_str())],
+            "submitted right after the form was shown",
+        ),
+        (
+            vec![
+                ("form_token", valid_token.as_str()),
+                ("website", "spam.example"),
+            ],
+            "with the honeypot filled in",
+        ),
+    ];
+
+    for (extra, description) in test_cases {
+        let mut parameter = vec![("name", "arine"), ("email", "arine@gmail.com")];
+        parameter.extend(extra);
+
+        let response = app
+            .build_request(Method::POST, "/subscriptions")
+            .form(&parameter)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "The API did not pretend to accept a submission {}",
+            description
+        );
+        assert_eq!(
+            count_subscribers(&app).await,
+            0,
+            "The API stored a submission {}",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_form_token_can_only_be_used_once() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let form_token = app.form_token();
+    for email in ["arine@gmail.com", "another@gmail.com"] {
+        let response = app
+            .build_request(Method::POST, "/subscriptions")
+            .form(&[
+                ("name", "arine"),
+                ("email", email),
+                ("form_token", &form_token),
+            ])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn a_form_token_rejected_for_invalid_details_can_be_used_again() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let form_token = app.form_token();
+    for (email, status) in [
+        ("arine@", StatusCode::BAD_REQUEST),
+        ("arine@gmail.com", StatusCode::OK),
+    ] {
+        let response = app
+            .build_request(Method::POST, "/subscriptions")
+            .form(&[
+                ("name", "arine"),
+                ("email", email),
+                ("form_token", &form_token),
+            ])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), status);
+    }
+
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn submissions_over_the_rate_limits_are_dropped() {
+    let app = App::new().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for (key, limit, email) in [
+        ("ip:127.0.0.1", 10, "arine@gmail.com"),
+        ("domain:example.com", 100, "reader@example.com"),
+    ] {
+        sqlx::query!("DELETE FROM rate_limits")
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        set_attempts(&app, key, limit).await;
+
+        // The peer is not a trusted proxy, so rotating `X-Forwarded-For` does not help.
+        let response = app
+            .build_request(Method::POST, "/subscriptions")
+            .header("X-Forwarded-For", "203.0.113.7")
+            .form(&[
+                ("name", "arine"),
+                ("email", email),
+                ("form_token", &app.form_token()),
+            ])
+            .send()
+            .await
+            .unwrap();
+
+        // Rate limited submissions look like every other submission to the client.
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(count_subscribers(&app).await, 0, "{} was not limited", key);
+    }
+}
+
+#[tokio::test]
+async fn domains_with_confirmed_subscribers_are_not_rate_limited() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(&[("name", "arine"), ("email", "arine@example.com")])
+        .await;
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    set_attempts(&app, "domain:example.com", 100).await;
+
+    let response = app
+        .post_subscriptions(&[("name", "reader"), ("email", "reader@example.com")])
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(count_subscribers(&app).await, 2);
+}
+
+#[tokio::test]
+async fn stale_rate_limit_windows_are_purged() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!(
+        "INSERT INTO rate_limits (key, window_start, attempts) VALUES ('ip:203.0.113.7', $1, 1)",
+        current_window_start() - chrono::Duration::hours(2),
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    app.post_subscriptions(&[("name", "arine"), ("email", "arine@gmail.com")])
+        .await;
+
+    let stale = sqlx::query!(
+        r#"SELECT count(*) AS "count!" FROM rate_limits WHERE window_start < $1"#,
+        current_window_start(),
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(stale, 0);
+}
+
+#[tokio::test]
+async fn a_challenge_must_be_solved_when_a_verifier_is_configured() {
+    let app = App::with_challenge_verifier(Arc::new(StubChallengeVerifier)).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(&[
+        ("name", "arine"),
+        ("email", "arine@gmail.com"),
+        ("challenge-response", "guessed"),
+    ])
+    .await;
+    assert_eq!(count_subscribers(&app).await, 0);
+
+    let response = app
+        .post_subscriptions(&[
+            ("name", "arine"),
+            ("email", "arine@gmail.com"),
+            ("challenge-response", "solved"),
+        ])
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+async fn count_subscribers(app: &App) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn set_attempts(app: &App, key: &str, attempts: i64) {
+    sqlx::query!(
+        r#"
+            INSERT INTO rate_limits (key, window_start, attempts) VALUES ($1, $2, $3)
+            ON CONFLICT (key, window_start) DO UPDATE SET attempts = EXCLUDED.attempts
+        "#,
+        key,
+        current_window_start(),
+        attempts,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+}
+
+fn current_window_start() -> DateTime<Utc> {
+    let now = Utc::now().timestamp();
+    DateTime::from_timestamp(now - now.rem_euclid(3600), 0).unwrap()
+}
@@ -0,0 +1,186 @@
+use reqwest::{Method, StatusCode};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use newsletter::consent::TrustedProxies;
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn subscribing_and_confirming_record_consent_evidence() {
+    let app = App::spawn(|app_state| {
+        app_state.trusted_proxies = TrustedProxies(vec!["127.0.0.1/32".parse().unwrap()]);
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .build_request(Method::POST, "/subscriptions")
+        .header("User-Agent", "test-browser/1.0")
+        .header("X-Forwarded-For", "198.51.100.1, 203.0.113.7")
+        .form(&[
+            ("name", "arine"),
+            ("email", "arine@gmail.com"),
+            ("source", "footer-form"),
+            ("form_token", &app.form_token()),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let links = app.get_confirmation_links(email_request);
+    let response = app
+        .client
+        .get(links.in_text)
+        .header("User-Agent", "mail-client/2.0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .id;
+    let detail: serde_json::Value = app
+        .authenticated_request(
+            Method::GET,
+            &format!("/admin/subscribers/{}", subscriber_id),
+        )
+        .await
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let consent = detail["consent"].as_array().unwrap();
+    assert_eq!(consent.len(), 2);
+    assert_eq!(consent[0]["event"], "subscribed");
+    assert_eq!(consent[0]["source"], "footer-form");
+    assert_eq!(consent[0]["ip_address"], "203.0.113.7");
+    assert_eq!(consent[0]["user_agent"], "test-browser/1.0");
+    assert_eq!(
+        consent[0]["consent_text"],
+        "I agree to receive the newsletter and can unsubscribe at any time."
+    );
+    assert_eq!(consent[1]["event"], "confirmed");
+    assert_eq!(consent[1]["source"], "confirmation_link");
+    assert_eq!(consent[1]["ip_address"], "127.0.0.1");
+    assert_eq!(consent[1]["user_agent"], "mail-client/2.0");
+}
+
+#[tokio::test]
+async fn consent_evidence_cannot_be_modified() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(&[("name", "arine"), ("email", "arine@gmail.com")])
+        .await;
+
+    let result = sqlx::query!("UPDATE consent_records SET source = 'forged'")
+        .execute(&app.pool)
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn consent_evidence_is_included_in_exports() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(&[("name", "arine"), ("email", "arine@gmail.com")])
+        .await;
+
+    let body = app
+        .authenticated_request(Method::GET, "/admin/exports/subscribers?format=ndjson")
+        .await
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let subscriber: serde_json::Value = serde_json::from_str(body.lines().next().unwrap()).unwrap();
+    assert_eq!(subscriber["consent"][0]["event"], "subscribed");
+    assert_eq!(subscriber["consent"][0]["source"], "subscription_form");
+}
+
+#[tokio::test]
+async fn following_the_confirmation_link_again_records_no_more_consent() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(&[("name", "arine"), ("email", "arine@gmail.com")])
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let links = app.get_confirmation_links(email_request);
+    for _ in 0..3 {
+        let response = app.client.get(links.in_text.clone()).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let events = sqlx::query!("SELECT event FROM consent_records ORDER BY id")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        events.into_iter().map(|r| r.event).collect::<Vec<_>>(),
+        vec!["subscribed", "confirmed"]
+    );
+}
+
+#[tokio::test]
+async fn forwarded_addresses_from_untrusted_peers_are_not_recorded() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.build_request(Method::POST, "/subscriptions")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .form(&[
+            ("name", "arine"),
+            ("email", "arine@gmail.com"),
+            ("form_token", &app.form_token()),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    let ip_address = sqlx::query!("SELECT ip_address FROM consent_records")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+        .ip_address;
+    assert_eq!(ip_address.as_deref(), Some("127.0.0.1"));
+}
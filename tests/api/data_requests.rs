@@ -0,0 +1,219 @@
+use reqwest::{Method, StatusCode};
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn subscribers_can_download_the_data_held_on_them() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("arine@gmail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_data_request(&app, "arine@gmail.com", "access").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let link = app.get_confirmation_links(email_request).in_text;
+    assert_eq!(link.path(), "/subscriptions/data");
+
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["email"], "arine@gmail.com");
+    assert_eq!(data["subscriptions"][0]["id"], subscriber_id.to_string());
+    assert_eq!(data["list_memberships"][0]["list"], "newsletter");
+    assert!(data["deliveries"].as_array().unwrap().is_empty());
+    assert!(data["engagement"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn data_requests_for_unknown_addresses_look_successful_but_send_nothing() {
+    let app = App::new().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_data_request(&app, "nobody@example.com", "erasure").await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn data_requests_match_every_spelling_of_an_address() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("arine@xn--bcher-kva.de")
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_data_request(&app, "Arine@Bücher.de", "access").await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn data_requests_without_a_form_token_send_nothing() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("arine@gmail.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .build_request(Method::POST, "/subscriptions/data-requests")
+        .form(&[("email", "arine@gmail.com"), ("kind", "access")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn an_address_receives_a_limited_number_of_data_request_emails() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("arine@gmail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..3 {
+        let response = post_data_request(&app, "arine@gmail.com", "access").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = post_data_request(&app, "arine@gmail.com", "access").await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "rate_limited");
+}
+
+#[tokio::test]
+async fn erasure_removes_the_subscriber_and_leaves_a_hashed_tombstone() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("arine@gmail.com").await;
+    sqlx::query!(
+        r#"
+            WITH import AS (
+                INSERT INTO subscriber_imports (
+                    import_id, format, initial_status, list_id, created_at
+                )
+                SELECT gen_random_uuid(), 'csv', 'pending_confirmation', list_id, now()
+                FROM lists WHERE slug = 'newsletter'
+                RETURNING import_id
+            )
+            INSERT INTO subscriber_import_errors (import_id, row_number, email, error)
+            SELECT import_id, 1, ' Arine@GMAIL.com', 'duplicate' FROM import
+        "#
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    post_data_request(&app, "arine@gmail.com", "erasure").await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let link = app.get_confirmation_links(email_request).in_text;
+    assert_eq!(link.path(), "/subscriptions/erasure");
+
+    // Following the link only asks for confirmation.
+    let response = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        sqlx::query!("SELECT id FROM subscriptions WHERE id = $1", subscriber_id)
+            .fetch_optional(&app.pool)
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    let token = link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .to_string();
+    let response = app
+        .build_request(Method::POST, "/subscriptions/erasure")
+        .form(&[("token", &token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+    let tombstone = sqlx::query!("SELECT email, reason FROM suppressions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(tombstone.email, None);
+    assert_eq!(tombstone.reason, "erased");
+    let import_error = sqlx::query!("SELECT email FROM subscriber_import_errors")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(import_error.email, None);
+
+    // The erased address is never sent another confirmation email.
+    let response = app
+        .post_subscriptions(&[("name", "arine"), ("email", "arine@gmail.com")])
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn data_request_links_reject_invalid_tokens() {
+    let app = App::new().await;
+
+    for (method, path) in [
+        (Method::GET, "/subscriptions/data?token=invalid"),
+        (Method::GET, "/subscriptions/erasure?token=invalid"),
+    ] {
+        let response = app.build_request(method, path).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_link");
+    }
+}
+
+async fn post_data_request(app: &App, email: &str, kind: &str) -> reqwest::Response {
+    app.build_request(Method::POST, "/subscriptions/data-requests")
+        .form(&[
+            ("email", email),
+            ("kind", kind),
+            ("form_token", &app.form_token()),
+        ])
+        .send()
+        .await
+        .unwrap()
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use reqwest::StatusCode;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use newsletter::deliverability::{DeliverabilityMode, MailDomainResolver};
+
+use crate::helpers::App;
+
+// Only `gmail.com` has mail servers.
+struct StubResolver;
+
+#[async_trait]
+impl MailDomainResolver for StubResolver {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        Ok(domain == "gmail.com")
+    }
+}
+
+#[tokio::test]
+async fn subscribe_rejects_domains_without_mail_servers_in_reject_mode() {
+    let app =
+        App::with_mail_domain_resolver(DeliverabilityMode::Reject, Arc::new(StubResolver)).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[("name", "arine"), ("email", "arine@no-mail.example")])
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let message = body["detail"].as_str().unwrap();
+    assert!(message.contains("no-mail.example does not accept email"));
+
+    let response = app
+        .post_subscriptions(&[("name", "arine"), ("email", "arine@gmail.com")])
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn subscribe_accepts_domains_without_mail_servers_in_warn_mode() {
+    let app =
+        App::with_mail_domain_resolver(DeliverabilityMode::Warn, Arc::new(StubResolver)).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[("name", "arine"), ("email", "arine@no-mail.example")])
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,89 @@
+use newsletter::email_normalisation::{normalise_subscriber_emails, DuplicateResolution};
+use newsletter::email_policy::{EmailPolicy, LocalPartCase};
+use uuid::Uuid;
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn subscribers_sharing_an_address_are_reported_and_deleted_on_request() {
+    let app = App::new().await;
+    let oldest = insert_subscriber(&app, "arine@example.com", "pending_confirmation", 3).await;
+    let confirmed = insert_subscriber(&app, "Arine@EXAMPLE.com", "confirmed", 2).await;
+    let other = insert_subscriber(&app, "Other@Example.com", "confirmed", 1).await;
+    let email_policy = EmailPolicy::new(LocalPartCase::Lowercase, vec![]);
+
+    let report = normalise_subscriber_emails(&app.pool, &email_policy, DuplicateResolution::Report)
+        .await
+        .unwrap();
+
+    assert!(!report.applied);
+    assert_eq!(report.subscribers, 3);
+    assert_eq!(report.changed, 2);
+    assert_eq!(report.duplicates.len(), 1);
+    assert_eq!(report.duplicates[0].normalised_email, "arine@example.com");
+    assert_eq!(report.duplicates[0].kept, confirmed);
+    assert_eq!(report.duplicates[0].duplicates, [oldest]);
+    assert_eq!(normalised_email(&app, other).await, "Other@Example.com");
+
+    let report = normalise_subscriber_emails(&app.pool, &email_policy, DuplicateResolution::Delete)
+        .await
+        .unwrap();
+
+    assert!(report.applied);
+    assert!(report.duplicates[0].deleted);
+    assert_eq!(normalised_email(&app, other).await, "other@example.com");
+    assert_eq!(normalised_email(&app, confirmed).await, "arine@example.com");
+    let remaining = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count, 2);
+}
+
+#[tokio::test]
+async fn changing_the_local_part_case_renormalises_every_subscriber() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("Arine@example.com").await;
+    let email_policy = EmailPolicy::new(LocalPartCase::Preserve, vec![]);
+
+    let report = normalise_subscriber_emails(&app.pool, &email_policy, DuplicateResolution::Report)
+        .await
+        .unwrap();
+
+    assert!(report.applied);
+    assert_eq!(report.changed, 1);
+    assert_eq!(
+        normalised_email(&app, subscriber_id).await,
+        "Arine@example.com"
+    );
+}
+
+async fn insert_subscriber(app: &App, email: &str, status: &str, days_ago: i32) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriptions (id, email, normalised_email, name, subscribed_at, status)
+            VALUES ($1, $2, $2, 'arine', now() - make_interval(days => $3), $4)
+        "#,
+        subscriber_id,
+        email,
+        days_ago,
+        status,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    subscriber_id
+}
+
+async fn normalised_email(app: &App, subscriber_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT normalised_email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap()
+    .normalised_email
+}
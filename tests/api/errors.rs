@@ -0,0 +1,104 @@
+use reqwest::{Method, Response, StatusCode};
+use uuid::Uuid;
+
+use crate::helpers::App;
+
+async fn problem(response: Response) -> serde_json::Value {
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn subscribe_reports_every_invalid_field() {
+    let app = App::new().await;
+
+    let response = app
+        .post_subscriptions(&[("name", " "), ("email", "definitely-not-an-email")])
+        .await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = problem(response).await;
+    assert_eq!(body["status"], 400);
+    assert_eq!(body["code"], "validation_failed");
+    let fields: Vec<_> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["name", "email"]);
+}
+
+#[tokio::test]
+async fn unexpected_errors_are_reported_without_their_cause() {
+    let app = App::new().await;
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token CASCADE")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let response = app.get_subscriptions_confirm("some-token").await;
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body = problem(response).await;
+    assert_eq!(body["code"], "internal_error");
+    assert_eq!(body["detail"], "Something went wrong");
+    assert!(body["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn authentication_failures_are_problems_with_a_challenge() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::POST, "/newsletters")
+        .basic_auth(Uuid::new_v4().to_string(), Some("password"))
+        .json(&serde_json::json!({
+            "title": "newsletter",
+            "content": {"text": "hi", "html": "there"},
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="publish""#
+    );
+    let body = problem(response).await;
+    assert_eq!(body["code"], "unauthorized");
+    assert_eq!(body["title"], "Unauthorized");
+}
+
+#[tokio::test]
+async fn login_failures_are_problems_instead_of_redirects() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::POST, "/login")
+        .form(&[("username", "nobody"), ("password", "wrong")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response.headers().get("Location").is_none());
+    let body = problem(response).await;
+    assert_eq!(body["code"], "unauthorized");
+}
+
+#[tokio::test]
+async fn missing_resources_are_problems() {
+    let app = App::new().await;
+
+    let response = app.get_newsletter(&Uuid::new_v4().to_string(), &[]).await;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = problem(response).await;
+    assert_eq!(body["code"], "not_found");
+    assert_eq!(body["detail"], "Newsletter issue not found");
+}
@@ -0,0 +1,127 @@
+use reqwest::{Method, StatusCode};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("arine@gmail.com").await;
+
+    let response = get_export(&app, "").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/csv; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"subscribers.csv\""
+    );
+
+    let body = response.text().await.unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(
+        lines[0],
+        "id,email,name,status,subscribed_at,confirmed_at,lists,consent"
+    );
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with(&format!(
+        "{},arine@gmail.com,arine,confirmed,",
+        subscriber_id
+    )));
+    assert!(lines[1].ends_with(",newsletter,[]"));
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_ndjson() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("arine@gmail.com").await;
+    app.insert_confirmed_subscriber("reader@example.com").await;
+
+    let response = get_export(&app, "?format=ndjson").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    let body = response.text().await.unwrap();
+    let subscribers: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(subscribers.len(), 2);
+    assert_eq!(subscribers[0]["email"], "arine@gmail.com");
+    assert_eq!(subscribers[0]["lists"], serde_json::json!(["newsletter"]));
+}
+
+#[tokio::test]
+async fn exports_can_be_filtered_by_segment_list_and_status() {
+    let app = App::new().await;
+    let beta_id = app.insert_confirmed_subscriber("beta@example.com").await;
+    app.insert_confirmed_subscriber("reader@example.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET tags = ARRAY['beta'] WHERE id = $1",
+        beta_id
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE email = 'reader@example.com'"
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .authenticated_request(Method::POST, "/admin/segments")
+        .await
+        .json(&serde_json::json!({ "name": "beta", "query": "tag = beta" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    for (query, expected) in [
+        ("?format=ndjson&segment=beta", vec!["beta@example.com"]),
+        (
+            "?format=ndjson&status=unsubscribed",
+            vec!["reader@example.com"],
+        ),
+        (
+            "?format=ndjson&list=newsletter",
+            vec!["beta@example.com", "reader@example.com"],
+        ),
+    ] {
+        let body = get_export(&app, query).await.text().await.unwrap();
+        let emails: Vec<String> = body
+            .lines()
+            .map(|line| {
+                let subscriber: serde_json::Value = serde_json::from_str(line).unwrap();
+                subscriber["email"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(emails, expected, "Unexpected export for {}", query);
+    }
+}
+
+#[tokio::test]
+async fn exports_with_an_unknown_segment_or_list_are_rejected() {
+    let app = App::new().await;
+
+    for query in ["?segment=missing", "?list=missing", "?format=xml"] {
+        let response = get_export(&app, query).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "The API did not reject an export with {}",
+            query
+        );
+    }
+}
+
+async fn get_export(app: &App, query: &str) -> reqwest::Response {
+    app.authenticated_request(Method::GET, &format!("/admin/exports/subscribers{}", query))
+        .await
+        .send()
+        .await
+        .unwrap()
+}
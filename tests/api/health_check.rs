@@ -1,3 +1,10 @@
+use std::time::Duration;
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use newsletter::migrations::latest_version;
+
 use crate::helpers::App;
 
 #[tokio::test]
@@ -9,3 +16,142 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(response.content_length(), Some(0));
 }
+
+#[tokio::test]
+async fn ready_reports_the_schema_version() {
+    let app = App::new().await;
+
+    let response = app.get_health("/health/ready").await;
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["schema_version"], latest_version().unwrap());
+    assert_eq!(body["latest_schema_version"], latest_version().unwrap());
+}
+
+#[tokio::test]
+async fn liveness_does_not_depend_on_the_database() {
+    let app = App::new().await;
+    app.pool.close().await;
+
+    let response = app.get_health("/health/live").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn ready_reports_every_component_as_up() {
+    let app = App::new().await;
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.get_health("/health/ready").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "up");
+    for component in ["database", "email_transport", "workers"] {
+        assert_eq!(body["components"][component]["status"], "up");
+        assert!(body["components"][component]["latency_in_milliseconds"].is_u64());
+    }
+}
+
+#[tokio::test]
+async fn ready_returns_503_when_the_database_is_down() {
+    let app = App::new().await;
+    Mock::given(path("/server"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.pool.close().await;
+
+    let response = app.get_health("/health/ready").await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["components"]["database"]["status"], "down");
+    assert!(body["components"]["database"]["error"].is_string());
+    assert!(body["schema_version"].is_null());
+    assert_eq!(body["components"]["email_transport"]["status"], "up");
+}
+
+#[tokio::test]
+async fn ready_returns_503_when_the_email_provider_rejects_the_check() {
+    let app = App::new().await;
+    Mock::given(path("/server"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.get_health("/health/ready").await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["components"]["email_transport"]["status"], "down");
+    assert_eq!(body["components"]["database"]["status"], "up");
+}
+
+#[tokio::test]
+async fn ready_returns_503_when_the_workers_stop_beating() {
+    let app = App::spawn(|app_state| {
+        app_state.health_check.worker_heartbeat_timeout = Duration::ZERO;
+    })
+    .await;
+    Mock::given(path("/server"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.get_health("/health/ready").await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["components"]["workers"]["status"], "down");
+}
+
+#[tokio::test]
+async fn workers_delivering_a_long_issue_stay_ready() {
+    let app = App::spawn(|app_state| {
+        app_state.health_check.worker_heartbeat_timeout = Duration::from_secs(1);
+    })
+    .await;
+    Mock::given(path("/server"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .mount(&app.email_server)
+        .await;
+    for i in 0..6 {
+        app.insert_confirmed_subscriber(&format!("reader{}@example.com", i))
+            .await;
+    }
+
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339(),
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now()")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    // Sending takes about three seconds, well past the heartbeat timeout.
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+
+    let response = app.get_health("/health/ready").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
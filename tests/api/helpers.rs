@@ -1,28 +1,56 @@
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
 
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
+use chrono::{Duration, Utc};
 use once_cell::sync::Lazy;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::TracerProvider;
 use reqwest::{Client, Method, Response};
 use secrecy::ExposeSecret;
 use serde::Serialize;
 use sqlx::{Connection, Executor, PgConnection, Pool, Postgres};
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 use wiremock::MockServer;
 
-use newsletter::{configuration, startup, telemetry};
+use newsletter::{
+    bot_protection::{form_token, ChallengeVerifier},
+    configuration::{self, WebhookSettings},
+    deliverability::{DeliverabilityCheck, DeliverabilityMode, MailDomainResolver},
+    email_client::EmailClient,
+    migrations,
+    startup::{self, AccessUrl, HmacSecret},
+    telemetry,
+};
+
+// Spans get a trace context, so that its propagation can be tested, but are not exported.
+// Tracers only hold a weak reference to their provider, which has to be kept alive.
+static TRACER_PROVIDER: Lazy<TracerProvider> = Lazy::new(|| TracerProvider::builder().build());
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
     let subscriber_name = "test".to_string();
 
+    let tracer = TRACER_PROVIDER.tracer("test");
+
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber =
-            telemetry::get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber = telemetry::get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            Some(tracer),
+        );
         telemetry::initialize_subscriber(subscriber);
     } else {
-        let subscriber =
-            telemetry::get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = telemetry::get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::sink,
+            Some(tracer),
+        );
         telemetry::initialize_subscriber(subscriber);
     };
 });
@@ -32,17 +60,75 @@ pub struct App {
     pub client: Client,
     pub pool: Pool<Postgres>,
     pub email_server: MockServer,
+    pub email_client: EmailClient,
+    pub access_url: AccessUrl,
+    pub hmac_secret: HmacSecret,
+    pub webhooks: WebhookSettings,
+    pub shutdown: CancellationToken,
+    pub metrics_address: Option<SocketAddr>,
 }
 
 impl App {
     pub async fn new() -> Self {
+        App::spawn(|_| {}).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn with_challenge_verifier(challenge_verifier: Arc<dyn ChallengeVerifier>) -> Self {
+        App::spawn(|app_state| {
+            app_state.bot_protection.challenge_verifier = Some(challenge_verifier);
+            app_state.bot_protection.challenge_response_field = "challenge-response".into();
+        })
+        .await
+    }
+
+    #[allow(dead_code)]
+    pub async fn with_mail_domain_resolver(
+        mode: DeliverabilityMode,
+        resolver: Arc<dyn MailDomainResolver>,
+    ) -> Self {
+        App::spawn(|app_state| {
+            app_state.deliverability_check = DeliverabilityCheck::new(
+                mode,
+                resolver,
+                StdDuration::from_millis(500),
+                StdDuration::from_secs(60),
+            );
+        })
+        .await
+    }
+
+    #[allow(dead_code)]
+    pub async fn with_separate_metrics_port() -> Self {
+        App::launch(|_| {}, true).await
+    }
+
+    pub async fn spawn(configure: impl FnOnce(&mut startup::AppState)) -> Self {
+        App::launch(configure, false).await
+    }
+
+    async fn launch(
+        configure: impl FnOnce(&mut startup::AppState),
+        separate_metrics_port: bool,
+    ) -> Self {
         Lazy::force(&TRACING);
 
-        // configure listener
+        // configure listeners
         let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
             .await
             .expect("Failed to start an test application");
         let address = listener.local_addr().unwrap();
+        let metrics_listener = match separate_metrics_port {
+            true => Some(
+                TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+                    .await
+                    .expect("Failed to start a metrics listener"),
+            ),
+            false => None,
+        };
+        let metrics_address = metrics_listener
+            .as_ref()
+            .map(|listener| listener.local_addr().unwrap());
 
         // run email server
         let email_server = MockServer::start().await;
@@ -53,24 +139,30 @@ impl App {
         configuration.application.access_url = format!("http://{}", address);
         configuration.database.database = Uuid::new_v4().to_string();
         configuration.email_client.access_url = email_server.uri();
+        configuration.scheduler.poll_interval_in_milliseconds = 100;
+        configuration.webhooks.soft_bounce_threshold = 2;
 
         // initialise randomise database
         App::initialise_database(&configuration).await;
 
         // configure app state
-        let app_state = startup::get_app_state(&configuration).await;
+        let mut app_state = startup::get_app_state(&configuration).await;
+        configure(&mut app_state);
 
-        // get database pool
+        // get shared application state
         let pool = app_state.pool.clone();
+        let email_client = app_state.email_client.clone();
+        let access_url = app_state.access_url.clone();
+        let hmac_secret = app_state.hmac_secret.clone();
+        let shutdown = app_state.shutdown.clone();
 
         // migrate database
-        sqlx::migrate!("./migrations")
-            .run(&pool.clone())
+        migrations::prepare_database(&pool, true, &hmac_secret)
             .await
             .expect("Failed to migrate the database");
 
         // start a server
-        tokio::spawn(startup::run(listener, app_state));
+        tokio::spawn(startup::run(listener, metrics_listener, app_state));
 
         // provide a reqwest client
         let client = Client::new();
@@ -80,20 +172,21 @@ impl App {
             client,
             pool,
             email_server,
+            email_client,
+            access_url,
+            hmac_secret,
+            webhooks: configuration.webhooks,
+            shutdown,
+            metrics_address,
         }
     }
 
     async fn initialise_database(configuration: &configuration::Settings) {
         // create a connection to postgres database
         // and create randomised database
-        let mut connection = PgConnection::connect(
-            configuration
-                .database
-                .connection_string_without_database()
-                .expose_secret(),
-        )
-        .await
-        .expect("Failed to connect to Postgres");
+        let mut connection = PgConnection::connect_with(&configuration.database.without_db())
+            .await
+            .expect("Failed to connect to Postgres");
 
         connection
             .execute(format!(r#"CREATE DATABASE "{}";"#, configuration.database.database).as_str())
@@ -110,26 +203,45 @@ impl App {
             self.client.get(url)
         } else if method == Method::POST {
             self.client.post(url)
+        } else if method == Method::PUT {
+            self.client.put(url)
+        } else if method == Method::PATCH {
+            self.client.patch(url)
+        } else if method == Method::DELETE {
+            self.client.delete(url)
         } else {
             panic!("No implementation for this request method {}", method)
         }
     }
 
     pub async fn get_health_check(&self) -> Response {
-        self.build_request(Method::GET, "/health_check")
-            .send()
-            .await
-            .unwrap()
+        self.get_health("/health_check").await
+    }
+
+    pub async fn get_health(&self, path: &str) -> Response {
+        self.build_request(Method::GET, path).send().await.unwrap()
     }
 
+    // Submits the form as if it had been shown to a person for a while.
     pub async fn post_subscriptions<T: Serialize + ?Sized>(&self, parameter: &T) -> Response {
+        let body = format!(
+            "{}&{}",
+            serde_urlencoded::to_string(parameter).unwrap(),
+            serde_urlencoded::to_string([("form_token", self.form_token())]).unwrap(),
+        );
+
         self.build_request(Method::POST, "/subscriptions")
-            .form(parameter)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
             .send()
             .await
             .unwrap()
     }
 
+    pub fn form_token(&self) -> String {
+        form_token(&self.hmac_secret, Utc::now() - Duration::seconds(10))
+    }
+
     #[allow(dead_code)]
     pub async fn get_subscriptions_confirm(&self, subscription_token: &str) -> Response {
         self.build_request(Method::GET, "/subscriptions/confirm")
@@ -149,6 +261,78 @@ impl App {
             .await
             .unwrap()
     }
+
+    pub async fn authenticated_request(
+        &self,
+        method: Method,
+        path: &str,
+    ) -> reqwest::RequestBuilder {
+        let (username, password) = self.add_test_user().await;
+
+        self.build_request(method, path)
+            .basic_auth(username, Some(password))
+    }
+
+    pub async fn post_postmark_webhook(&self, body: &serde_json::Value) -> Response {
+        self.build_request(Method::POST, "/webhooks/postmark")
+            .json(body)
+            .basic_auth(
+                &self.webhooks.username,
+                Some(self.webhooks.password.expose_secret()),
+            )
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_newsletter(
+        &self,
+        newsletter_issue_id: &str,
+        query: &[(&str, &str)],
+    ) -> Response {
+        let (username, password) = self.add_test_user().await;
+
+        self.build_request(
+            Method::GET,
+            &format!("/newsletters/{}", newsletter_issue_id),
+        )
+        .query(query)
+        .basic_auth(username, Some(password))
+        .send()
+        .await
+        .unwrap()
+    }
+
+    pub async fn patch_newsletter(
+        &self,
+        newsletter_issue_id: &str,
+        body: &serde_json::Value,
+    ) -> Response {
+        let (username, password) = self.add_test_user().await;
+
+        self.build_request(
+            Method::PATCH,
+            &format!("/newsletters/{}", newsletter_issue_id),
+        )
+        .json(body)
+        .basic_auth(username, Some(password))
+        .send()
+        .await
+        .unwrap()
+    }
+
+    pub async fn delete_newsletter(&self, newsletter_issue_id: &str) -> Response {
+        let (username, password) = self.add_test_user().await;
+
+        self.build_request(
+            Method::DELETE,
+            &format!("/newsletters/{}", newsletter_issue_id),
+        )
+        .basic_auth(username, Some(password))
+        .send()
+        .await
+        .unwrap()
+    }
 }
 
 pub struct ConfirmationLinks {
@@ -203,4 +387,33 @@ impl App {
 
         (username, password)
     }
+
+    pub async fn insert_confirmed_subscriber(&self, email: &str) -> Uuid {
+        let subscriber_id = Uuid::new_v4();
+
+        sqlx::query!(
+            r#"
+                INSERT INTO subscriptions (id, email, normalised_email, name, subscribed_at, status)
+                VALUES ($1, $2, lower($2), 'arine', now(), 'confirmed')
+            "#,
+            subscriber_id,
+            email,
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to create confirmed subscriber");
+
+        sqlx::query!(
+            r#"
+                INSERT INTO list_memberships (list_id, subscriber_id, status, updated_at)
+                SELECT list_id, $1, 'subscribed', now() FROM lists WHERE slug = 'newsletter'
+            "#,
+            subscriber_id,
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to subscribe to the default list");
+
+        subscriber_id
+    }
 }
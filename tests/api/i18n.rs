@@ -0,0 +1,176 @@
+use reqwest::{Method, StatusCode};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn subscribers_get_the_confirmation_email_and_page_in_their_language() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .build_request(Method::POST, "/subscriptions")
+        .header("Accept-Language", "de-DE, en;q=0.5")
+        .form(&[
+            ("name", "arine"),
+            ("email", "arine@gmail.com"),
+            ("form_token", &app.form_token()),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["Subject"], "Willkommen!");
+
+    let links = app.get_confirmation_links(email_request);
+    let page = app
+        .client
+        .get(links.in_text)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains(r#"<html lang="de">"#));
+    assert!(page.contains("Abonnement bestätigt"));
+}
+
+#[tokio::test]
+async fn the_locale_chosen_on_the_form_wins_over_the_browser_language() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.build_request(Method::POST, "/subscriptions")
+        .header("Accept-Language", "de")
+        .form(&[
+            ("name", "arine"),
+            ("email", "arine@gmail.com"),
+            ("locale", "fr-CA"),
+            ("form_token", &app.form_token()),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "fr");
+}
+
+#[tokio::test]
+async fn validation_errors_are_returned_in_the_browser_language() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::POST, "/subscriptions")
+        .header("Accept-Language", "fr")
+        .form(&[
+            ("name", "arine"),
+            ("email", "definitely-not-an-email"),
+            ("form_token", &app.form_token()),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let message = body["detail"].as_str().unwrap();
+    assert_eq!(
+        message,
+        "definitely-not-an-email n'est pas une adresse e-mail valide"
+    );
+}
+
+#[tokio::test]
+async fn subscribers_receive_the_newsletter_variant_for_their_locale() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("english@gmail.com").await;
+    let german = app.insert_confirmed_subscriber("german@gmail.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET locale = 'de' WHERE id = $1",
+        german
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter body</p>" },
+            "variants": {
+                "de": {
+                    "title": "Newsletter-Titel",
+                    "content": { "html": "<p>Newsletter-Inhalt</p>" }
+                }
+            }
+        }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for request in app.email_server.received_requests().await.unwrap() {
+        let email: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let (subject, content, footer) = match email["To"].as_str().unwrap() {
+            "german@gmail.com" => (
+                "Newsletter-Titel",
+                "Newsletter-Inhalt",
+                "Abonnement-Einstellungen verwalten",
+            ),
+            _ => (
+                "Newsletter title",
+                "Newsletter body",
+                "Manage your subscription preferences",
+            ),
+        };
+        assert_eq!(email["Subject"], subject);
+        assert!(email["HtmlBody"].as_str().unwrap().contains(content));
+        assert!(email["HtmlBody"].as_str().unwrap().contains(footer));
+    }
+}
+
+#[tokio::test]
+async fn newsletter_variants_must_use_a_supported_locale() {
+    let app = App::new().await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter body</p>" },
+            "variants": {
+                "tlh": {
+                    "title": "Klingon title",
+                    "content": { "html": "<p>Klingon body</p>" }
+                }
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
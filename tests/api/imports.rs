@@ -0,0 +1,271 @@
+use reqwest::{Method, StatusCode};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn confirmed_imports_report_invalid_and_duplicate_rows() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("existing@example.com")
+        .await;
+
+    let import_id = create_import(
+        &app,
+        serde_json::json!({
+            "format": "csv",
+            "status": "confirmed",
+            "consent_source": "old provider export",
+        }),
+    )
+    .await;
+    let report = upload_rows(
+        &app,
+        &import_id,
+        "email,name\n\
+         first@example.com,First\n\
+         \"second@example.com\",\"Second, Reader\"\n\
+         not-an-email,Third\n\
+         Existing@example.com,Existing\n\
+         first@example.com,First again\n",
+    )
+    .await;
+
+    assert_eq!(report["rows"], 6);
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["duplicates"], 2);
+    assert_eq!(report["failed"], 1);
+    let rows: Vec<i64> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["row"].as_i64().unwrap())
+        .collect();
+    assert_eq!(rows, [4, 5, 6]);
+
+    let imported = sqlx::query!(
+        r#"
+            SELECT name, status, consent_source
+            FROM subscriptions
+            WHERE email = 'second@example.com'
+        "#
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(imported.name, "Second, Reader");
+    assert_eq!(imported.status, "confirmed");
+    assert_eq!(
+        imported.consent_source.as_deref(),
+        Some("old provider export")
+    );
+}
+
+#[tokio::test]
+async fn confirmed_imports_require_a_consent_source() {
+    let app = App::new().await;
+
+    let response = app
+        .authenticated_request(Method::POST, "/admin/imports")
+        .await
+        .json(&serde_json::json!({ "format": "csv", "status": "confirmed" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn pending_imports_send_confirmation_emails() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let import_id = create_import(
+        &app,
+        serde_json::json!({ "format": "jsonl", "status": "pending" }),
+    )
+    .await;
+    let report = upload_rows(
+        &app,
+        &import_id,
+        "{\"email\": \"first@example.com\", \"name\": \"First\"}\n\
+         {\"email\": \"second@example.com\", \"name\": \"Second\"}\n\
+         {\"email\": \"third@example.com\"}\n",
+    )
+    .await;
+
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["failed"], 1);
+
+    // The confirmation emails are sent by the worker once the rows are imported.
+    let email_requests = wait_for_emails(&app, 2).await;
+    let email_request = &email_requests[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = reqwest::get(confirmation_links.in_html).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_not_sent_confirmation_emails_when_imported() {
+    let app = App::new().await;
+    app.authenticated_request(Method::POST, "/admin/suppressions")
+        .await
+        .json(&serde_json::json!({ "email": "suppressed@example.com" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let import_id = create_import(
+        &app,
+        serde_json::json!({ "format": "jsonl", "status": "pending" }),
+    )
+    .await;
+    let report = upload_rows(
+        &app,
+        &import_id,
+        "{\"email\": \"Suppressed@Example.com\", \"name\": \"Suppressed\"}\n\
+         {\"email\": \"allowed@example.com\", \"name\": \"Allowed\"}\n",
+    )
+    .await;
+
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["errors"][0]["error"], "Email address is suppressed");
+
+    // Any email to the suppressed address would be queued before the allowed one.
+    let email_requests = wait_for_emails(&app, 1).await;
+    let body: serde_json::Value = serde_json::from_slice(&email_requests[0].body).unwrap();
+    assert_eq!(body["To"], "allowed@example.com");
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+}
+
+#[tokio::test]
+async fn csv_records_spanning_several_lines_are_one_row() {
+    let app = App::new().await;
+
+    let import_id = create_import(
+        &app,
+        serde_json::json!({
+            "format": "csv",
+            "status": "confirmed",
+            "consent_source": "old provider export",
+        }),
+    )
+    .await;
+    let report = upload_rows(
+        &app,
+        &import_id,
+        "email,name\r\n\
+         first@example.com,\"First\r\nReader\"\r\n\
+         not-an-email,Second\r\n",
+    )
+    .await;
+
+    assert_eq!(report["rows"], 3);
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["errors"][0]["row"], 3);
+    assert_eq!(report["errors"][0]["email"], "not-an-email");
+}
+
+#[tokio::test]
+async fn interrupted_imports_can_be_resumed() {
+    let app = App::new().await;
+    let file = "email,name\n\
+                first@example.com,First\n\
+                second@example.com,Second\n\
+                third@example.com,Third\n";
+
+    let import_id = create_import(
+        &app,
+        serde_json::json!({
+            "format": "csv",
+            "status": "confirmed",
+            "consent_source": "old provider export",
+        }),
+    )
+    .await;
+    let partial = &file[..file.find("third").unwrap()];
+    let report = upload_rows(&app, &import_id, partial).await;
+    assert_eq!(report["imported"], 2);
+
+    let report = upload_rows(&app, &import_id, file).await;
+    assert_eq!(report["rows"], 4);
+    assert_eq!(report["imported"], 3);
+    assert_eq!(report["duplicates"], 0);
+}
+
+#[tokio::test]
+async fn csv_files_without_required_columns_are_rejected() {
+    let app = App::new().await;
+
+    let import_id = create_import(
+        &app,
+        serde_json::json!({ "format": "csv", "status": "pending" }),
+    )
+    .await;
+    let response = app
+        .authenticated_request(Method::POST, &format!("/admin/imports/{}/rows", import_id))
+        .await
+        .body("email,country\nfirst@example.com,DE\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+async fn create_import(app: &App, body: serde_json::Value) -> String {
+    let response = app
+        .authenticated_request(Method::POST, "/admin/imports")
+        .await
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["import_id"].as_str().unwrap().to_owned()
+}
+
+async fn upload_rows(app: &App, import_id: &str, rows: &str) -> serde_json::Value {
+    let response = app
+        .authenticated_request(Method::POST, &format!("/admin/imports/{}/rows", import_id))
+        .await
+        .body(rows.to_owned())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    response.json().await.unwrap()
+}
+
+async fn wait_for_emails(app: &App, expected: usize) -> Vec<wiremock::Request> {
+    for _ in 0..50 {
+        let requests = app.email_server.received_requests().await.unwrap();
+        if requests.len() >= expected {
+            return requests;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("The worker did not send {} emails", expected);
+}
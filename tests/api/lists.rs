@@ -0,0 +1,311 @@
+use reqwest::{Method, StatusCode};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn subscribing_to_a_list_records_a_membership() {
+    let app = App::new().await;
+    create_list(&app, "weekly").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[
+            ("name", "arine"),
+            ("email", "arine@gmail.com"),
+            ("list", "weekly"),
+        ])
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(
+        membership_status(&app, "arine@gmail.com", "weekly").await,
+        Some("subscribed".into())
+    );
+    assert_eq!(
+        membership_status(&app, "arine@gmail.com", "newsletter").await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = App::new().await;
+
+    let response = app
+        .post_subscriptions(&[
+            ("name", "arine"),
+            ("email", "arine@gmail.com"),
+            ("list", "unknown"),
+        ])
+        .await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_join_another_list_by_following_the_link_sent_to_them() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("arine@gmail.com").await;
+    create_list(&app, "security").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[
+            ("name", "arine"),
+            ("email", "arine@gmail.com"),
+            ("list", "security"),
+            ("source", "blog"),
+        ])
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Nothing changes until the subscriber follows the link.
+    assert_eq!(
+        membership_status(&app, "arine@gmail.com", "security").await,
+        None
+    );
+    assert!(consent_sources(&app, subscriber_id).await.is_empty());
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let link = app.get_confirmation_links(email_request).in_text;
+    assert_eq!(link.path(), "/subscriptions/join");
+    for _ in 0..2 {
+        let response = reqwest::get(link.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    assert_eq!(
+        membership_status(&app, "arine@gmail.com", "security").await,
+        Some("subscribed".into())
+    );
+    assert_eq!(
+        membership_status(&app, "arine@gmail.com", "newsletter").await,
+        Some("subscribed".into())
+    );
+    assert_eq!(consent_sources(&app, subscriber_id).await, ["blog"]);
+}
+
+#[tokio::test]
+async fn lists_left_on_the_preferences_page_are_not_joined_again_by_the_form() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("arine@gmail.com").await;
+    sqlx::query!("UPDATE list_memberships SET status = 'unsubscribed'")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(&[("name", "arine"), ("email", "arine@gmail.com")])
+        .await;
+
+    assert_eq!(
+        membership_status(&app, "arine@gmail.com", "newsletter").await,
+        Some("unsubscribed".into())
+    );
+}
+
+#[tokio::test]
+async fn list_invitation_links_are_verified() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::GET, "/subscriptions/join")
+        .query(&[("token", format!("{}.invalid", Uuid::new_v4()))])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_only_to_members_of_targeted_lists() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("reader@example.com").await;
+    app.insert_confirmed_subscriber("security@example.com")
+        .await;
+    create_list(&app, "security").await;
+    join_list(&app, "security@example.com", "security").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&newsletter_request_body(serde_json::json!(["security"])))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+    assert_eq!(body["To"], "security@example.com");
+}
+
+#[tokio::test]
+async fn newsletters_targeting_unknown_lists_are_rejected() {
+    let app = App::new().await;
+
+    for lists in [serde_json::json!(["unknown"]), serde_json::json!([])] {
+        let response = app.post_newsletters(&newsletter_request_body(lists)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_leave_a_list_from_the_preferences_page() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("reader@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(&newsletter_request_body(serde_json::json!(["newsletter"])))
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let preferences_link = linkify::LinkFinder::new()
+        .links(body["TextBody"].as_str().unwrap())
+        .map(|link| link.as_str().to_owned())
+        .find(|link| link.contains("/subscriptions/preferences"))
+        .unwrap();
+
+    let response = reqwest::get(&preferences_link).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().await.unwrap().contains(" checked"));
+
+    let token = reqwest::Url::parse(&preferences_link)
+        .unwrap()
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    let response = app
+        .build_request(Method::POST, "/subscriptions/preferences")
+        .form(&[("token", token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        membership_status(&app, "reader@example.com", "newsletter").await,
+        Some("unsubscribed".into())
+    );
+
+    app.post_newsletters(&newsletter_request_body(serde_json::json!(["newsletter"])))
+        .await;
+}
+
+#[tokio::test]
+async fn preferences_page_rejects_invalid_tokens() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::GET, "/subscriptions/preferences")
+        .query(&[("token", format!("{}.invalid", Uuid::new_v4()))])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn newsletter_request_body(lists: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "lists": lists,
+    })
+}
+
+async fn create_list(app: &App, slug: &str) {
+    let response = app
+        .authenticated_request(Method::POST, "/admin/lists")
+        .await
+        .json(&serde_json::json!({ "slug": slug, "name": slug }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+async fn join_list(app: &App, email: &str, slug: &str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(&[("name", "arine"), ("email", email), ("list", slug)])
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let link = app.get_confirmation_links(email_request).in_text;
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+async fn consent_sources(app: &App, subscriber_id: Uuid) -> Vec<String> {
+    sqlx::query!(
+        "SELECT source FROM consent_records WHERE subscriber_id = $1 ORDER BY id",
+        subscriber_id
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.source)
+    .collect()
+}
+
+async fn membership_status(app: &App, email: &str, slug: &str) -> Option<String> {
+    sqlx::query!(
+        r#"
+            SELECT m.status
+            FROM list_memberships m
+            JOIN lists l ON l.list_id = m.list_id
+            JOIN subscriptions s ON s.id = m.subscriber_id
+            WHERE s.email = $1 AND l.slug = $2
+        "#,
+        email,
+        slug,
+    )
+    .fetch_optional(&app.pool)
+    .await
+    .unwrap()
+    .map(|r| r.status)
+}
@@ -1,5 +1,25 @@
+mod admin_subscribers;
+mod bot_protection;
+mod consent;
+mod data_requests;
+mod deliverability;
+mod email_normalisation;
+mod errors;
+mod exports;
 mod health_check;
 mod helpers;
+mod i18n;
+mod imports;
+mod lists;
+mod metrics;
+mod migrations;
 mod newsletter;
+mod request_id;
+mod segments;
+mod shutdown;
 mod subscription_confirm;
 mod subscriptions;
+mod suppressions;
+mod telemetry;
+mod tracking;
+mod webhooks;
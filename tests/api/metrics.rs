@@ -0,0 +1,98 @@
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::App;
+
+impl App {
+    async fn get_metrics(&self) -> String {
+        let response = self
+            .build_request(Method::GET, "/metrics")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+
+        response.text().await.unwrap()
+    }
+}
+
+#[tokio::test]
+async fn http_requests_are_labelled_by_route_and_status() {
+    let app = App::new().await;
+
+    app.build_request(
+        Method::GET,
+        &format!("/admin/subscribers/{}", Uuid::new_v4()),
+    )
+    .send()
+    .await
+    .unwrap();
+
+    let metrics = app.get_metrics().await;
+    assert!(metrics.contains(
+        r#"http_requests_total{method="GET",path="/admin/subscribers/:subscriber_id",status="400"} 1"#
+    ));
+    assert!(metrics.contains(
+        r#"http_request_duration_seconds_count{method="GET",path="/admin/subscribers/:subscriber_id",status="400"} 1"#
+    ));
+}
+
+#[tokio::test]
+async fn sent_confirmation_emails_are_counted_by_transport_and_kind() {
+    let app = App::new().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[("name", "arine"), ("email", "peppydays@gmail.com")])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let metrics = app.get_metrics().await;
+    assert!(metrics
+        .contains(r#"emails_total{kind="confirmation",outcome="sent",transport="postmark"} 1"#));
+}
+
+#[tokio::test]
+async fn subscribers_queue_and_pool_are_reported() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("arine@example.com").await;
+
+    let metrics = app.get_metrics().await;
+
+    assert!(metrics.contains(r#"subscribers{status="confirmed"} 1"#));
+    assert!(metrics.contains("delivery_queue_depth 0"));
+    assert!(metrics.contains("delivery_queue_oldest_age_seconds 0"));
+    assert!(metrics.contains("db_pool_max_connections 10"));
+    assert!(metrics.contains(r#"db_pool_connections{state="in_use"}"#));
+}
+
+#[tokio::test]
+async fn metrics_can_be_served_on_a_separate_port() {
+    let app = App::with_separate_metrics_port().await;
+
+    let on_application_port = app
+        .build_request(Method::GET, "/metrics")
+        .send()
+        .await
+        .unwrap();
+    let on_metrics_port = app
+        .client
+        .get(format!("http://{}/metrics", app.metrics_address.unwrap()))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(on_application_port.status().as_u16(), 404);
+    assert_eq!(on_metrics_port.status().as_u16(), 200);
+    assert!(on_metrics_port
+        .text()
+        .await
+        .unwrap()
+        .contains("# TYPE db_pool_max_connections gauge"));
+}
@@ -0,0 +1,111 @@
+use sqlx::{Connection, Executor, PgConnection};
+use uuid::Uuid;
+
+use newsletter::configuration::get_configuration;
+use newsletter::migrations::{latest_version, prepare_database, schema_version, MigrationError};
+use newsletter::startup::HmacSecret;
+use newsletter::suppression::{email_hash, is_suppressed};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn migrating_an_up_to_date_database_changes_nothing() {
+    let app = App::new().await;
+    let version = schema_version(&app.pool).await.unwrap();
+
+    prepare_database(&app.pool, true, &app.hmac_secret)
+        .await
+        .unwrap();
+
+    assert_eq!(schema_version(&app.pool).await.unwrap(), version);
+}
+
+#[tokio::test]
+async fn concurrent_replicas_migrate_an_empty_database_once() {
+    let mut configuration = get_configuration().unwrap();
+    configuration.database.database = Uuid::new_v4().to_string();
+    PgConnection::connect_with(&configuration.database.without_db())
+        .await
+        .unwrap()
+        .execute(format!(r#"CREATE DATABASE "{}";"#, configuration.database.database).as_str())
+        .await
+        .unwrap();
+    let pool = configuration
+        .database
+        .pool_options()
+        .connect_with(configuration.database.with_db())
+        .await
+        .unwrap();
+    assert_eq!(schema_version(&pool).await.unwrap(), None);
+
+    let hmac_secret = HmacSecret(configuration.application.hmac_secret.clone());
+
+    let results =
+        futures_util::future::join_all((0..3).map(|_| prepare_database(&pool, true, &hmac_secret)))
+            .await;
+
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(schema_version(&pool).await.unwrap(), latest_version());
+}
+
+#[tokio::test]
+async fn databases_with_unknown_migrations_are_refused() {
+    let app = App::new().await;
+    sqlx::query(
+        r#"
+            INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+            VALUES (99990101000000, 'from a newer release', true, '\x00', 0)
+        "#,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let error = prepare_database(&app.pool, false, &app.hmac_secret)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        MigrationError::UnknownMigrations(versions) if versions == vec![99990101000000]
+    ));
+}
+
+#[tokio::test]
+async fn suppressions_hashed_before_they_were_keyed_are_rehashed() {
+    let app = App::new().await;
+    sqlx::query!(
+        r#"
+            INSERT INTO suppressions (email_hash, reason, source, created_at, legacy_email_hash)
+            VALUES (
+                encode(sha256(convert_to('legacy@example.com', 'UTF8')), 'hex'),
+                'hard_bounce',
+                'migration',
+                now(),
+                TRUE
+            )
+        "#,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    prepare_database(&app.pool, true, &app.hmac_secret)
+        .await
+        .unwrap();
+
+    let suppression = sqlx::query!("SELECT email_hash, legacy_email_hash FROM suppressions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        suppression.email_hash,
+        email_hash(&app.hmac_secret, "legacy@example.com")
+    );
+    assert!(!suppression.legacy_email_hash);
+    assert!(
+        is_suppressed(&app.pool, &app.hmac_secret, "legacy@example.com")
+            .await
+            .unwrap()
+    );
+}
@@ -1,4 +1,12 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use newsletter::health::Heartbeat;
+use newsletter::issue_delivery::{deliver_issue, IssueLease};
+use newsletter::scheduler::{try_execute_task, ExecutionOutcome};
 use reqwest::StatusCode;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 use wiremock::{
     matchers::{any, method, path},
@@ -92,7 +100,7 @@ async fn requests_missing_authorization_are_rejected() {
 
     let response = app
         .client
-        .post(&format!("http://{}{}", app.address, "/newsletters"))
+        .post(format!("http://{}{}", app.address, "/newsletters"))
         .json(&body)
         .send()
         .await
@@ -117,7 +125,7 @@ async fn non_existing_user_is_rejected() {
 
     let response = app
         .client
-        .post(&format!("http://{}{}", app.address, "/newsletters"))
+        .post(format!("http://{}{}", app.address, "/newsletters"))
         .json(&body)
         .basic_auth(username, Some(password))
         .send()
@@ -127,7 +135,7 @@ async fn non_existing_user_is_rejected() {
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
     assert_eq!(
         response.headers()["WWW-Authenticate"],
-        r#"Basic realm="publish"#
+        r#"Basic realm="publish""#
     );
 }
 
@@ -146,7 +154,7 @@ async fn invalid_password_is_rejected() {
 
     let response = app
         .client
-        .post(&format!("http://{}{}", app.address, "/newsletters"))
+        .post(format!("http://{}{}", app.address, "/newsletters"))
         .json(&body)
         .basic_auth(username, Some(String::from("123")))
         .send()
@@ -156,10 +164,505 @@ async fn invalid_password_is_rejected() {
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
     assert_eq!(
         response.headers()["WWW-Authenticate"],
-        r#"Basic realm="publish"#
+        r#"Basic realm="publish""#
     );
 }
 
+#[tokio::test]
+async fn scheduled_newsletters_are_not_delivered_immediately() {
+    let app = App::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&scheduled_newsletter_request_body(Duration::from_secs(
+            3600,
+        )))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "scheduled");
+    let status = get_issue_status(&app, body["newsletter_issue_id"].as_str().unwrap()).await;
+    assert_eq!(status, "scheduled");
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_delivered_once_they_are_due() {
+    let app = App::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&scheduled_newsletter_request_body(Duration::from_secs(5)))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    wait_for_issue_status(&app, body["newsletter_issue_id"].as_str().unwrap(), "sent").await;
+}
+
+#[tokio::test]
+async fn due_newsletters_are_claimed_by_a_single_scheduler() {
+    let app = App::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&scheduled_newsletter_request_body(Duration::from_secs(
+            3600,
+        )))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+    make_issue_due(&app, newsletter_issue_id).await;
+
+    let handles: Vec<_> = (0..5)
+        .map(|_| {
+            let pool = app.pool.clone();
+            let email_client = app.email_client.clone();
+            let access_url = app.access_url.clone();
+            let hmac_secret = app.hmac_secret.clone();
+            tokio::spawn(async move {
+                try_execute_task(
+                    &pool,
+                    &email_client,
+                    &access_url,
+                    &hmac_secret,
+                    Duration::from_secs(300),
+                    &Heartbeat::new(),
+                    &CancellationToken::new(),
+                )
+                .await
+            })
+        })
+        .collect();
+    for handle in handles {
+        let outcome = handle.await.unwrap().unwrap();
+        assert!(matches!(
+            outcome,
+            ExecutionOutcome::TaskCompleted | ExecutionOutcome::EmptyQueue
+        ));
+    }
+
+    wait_for_issue_status(&app, newsletter_issue_id, "sent").await;
+}
+
+#[tokio::test]
+async fn issues_left_sending_by_a_stopped_worker_are_reclaimed() {
+    let app = App::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&scheduled_newsletter_request_body(Duration::from_secs(
+            3600,
+        )))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+    set_issue_heartbeat(&app, newsletter_issue_id, "1 minute").await;
+
+    // The worker delivering the issue may still be alive.
+    let outcome = try_execute_task(
+        &app.pool,
+        &app.email_client,
+        &app.access_url,
+        &app.hmac_secret,
+        Duration::from_secs(300),
+        &Heartbeat::new(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(outcome, ExecutionOutcome::EmptyQueue));
+    assert_eq!(get_issue_status(&app, newsletter_issue_id).await, "sending");
+
+    set_issue_heartbeat(&app, newsletter_issue_id, "1 hour").await;
+    wait_for_issue_status(&app, newsletter_issue_id, "sent").await;
+
+    let started_at = sqlx::query!(
+        "SELECT started_at FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        Uuid::parse_str(newsletter_issue_id).unwrap(),
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap()
+    .started_at
+    .unwrap();
+    assert!(started_at < Utc::now() - chrono::Duration::minutes(30));
+}
+
+#[tokio::test]
+async fn workers_whose_lease_ran_out_leave_the_issue_to_the_one_which_reclaimed_it() {
+    let app = App::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&scheduled_newsletter_request_body(Duration::from_secs(
+            3600,
+        )))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+    set_issue_heartbeat(&app, newsletter_issue_id, "1 minute").await;
+
+    // The heartbeat the first worker wrote before another one reclaimed the issue.
+    let lease = IssueLease {
+        newsletter_issue_id: Uuid::parse_str(newsletter_issue_id).unwrap(),
+        heartbeat_at: Utc::now() - chrono::Duration::hours(1),
+    };
+    deliver_issue(
+        &app.pool,
+        &app.email_client,
+        &app.access_url.0,
+        &app.hmac_secret,
+        lease,
+        None,
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_issue_status(&app, newsletter_issue_id).await, "sending");
+}
+
+#[tokio::test]
+async fn newsletters_scheduled_without_time_zone_are_rejected() {
+    let app = App::new().await;
+
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": "2099-01-05T09:00:00"
+    });
+
+    let response = app.post_newsletters(&body).await;
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn newsletters_scheduled_in_the_past_are_rejected() {
+    let app = App::new().await;
+
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": "2020-01-06T09:00:00+01:00"
+    });
+
+    let response = app.post_newsletters(&body).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_can_be_cancelled() {
+    let app = App::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&scheduled_newsletter_request_body(Duration::from_secs(
+            3600,
+        )))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+
+    let response = app.delete_newsletter(newsletter_issue_id).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    make_issue_due(&app, newsletter_issue_id).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(
+        get_issue_status(&app, newsletter_issue_id).await,
+        "cancelled"
+    );
+}
+
+#[tokio::test]
+async fn newsletters_which_are_not_scheduled_cannot_be_cancelled() {
+    let app = App::new().await;
+
+    let response = app
+        .post_newsletters(&scheduled_newsletter_request_body(Duration::from_secs(
+            3600,
+        )))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+    app.delete_newsletter(newsletter_issue_id).await;
+
+    let response = app.delete_newsletter(newsletter_issue_id).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = app.delete_newsletter(&Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_can_be_rescheduled() {
+    let app = App::new().await;
+
+    let response = app
+        .post_newsletters(&scheduled_newsletter_request_body(Duration::from_secs(
+            3600,
+        )))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+
+    let response = app
+        .patch_newsletter(
+            newsletter_issue_id,
+            &serde_json::json!({ "send_at": "2099-01-05T09:00:00+09:00" }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let saved = sqlx::query!(
+        "SELECT send_at FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        Uuid::parse_str(newsletter_issue_id).unwrap(),
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        saved.send_at.unwrap().to_rfc3339(),
+        "2099-01-05T00:00:00+00:00"
+    );
+
+    app.delete_newsletter(newsletter_issue_id).await;
+    let response = app
+        .patch_newsletter(
+            newsletter_issue_id,
+            &serde_json::json!({ "send_at": "2099-01-05T09:00:00+09:00" }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn deliveries_are_recorded_for_each_recipient() {
+    let app = App::new().await;
+    create_unconfirmed_subscriber(&app).await;
+    let confirmed_subscriber_id = app
+        .insert_confirmed_subscriber("confirmed@example.com")
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            "ErrorCode": 0,
+            "Message": "OK"
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+
+    let response = app.get_newsletter(newsletter_issue_id, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["status"], "sent");
+    assert_eq!(report["progress"]["total"], 1);
+    assert_eq!(report["progress"]["sent"], 1);
+    assert_eq!(
+        report["deliveries"][0]["subscriber_id"],
+        confirmed_subscriber_id.to_string()
+    );
+    assert_eq!(report["deliveries"][0]["status"], "sent");
+    assert_eq!(
+        report["deliveries"][0]["provider_message_id"],
+        "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"
+    );
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_recorded_without_stopping_the_issue() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("first@example.com").await;
+    app.insert_confirmed_subscriber("second@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    let report: serde_json::Value = app
+        .get_newsletter(body["newsletter_issue_id"].as_str().unwrap(), &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["progress"]["total"], 2);
+    assert_eq!(report["progress"]["failed"], 2);
+    assert!(report["deliveries"][0]["error"].is_string());
+}
+
+#[tokio::test]
+async fn deliveries_of_a_newsletter_issue_are_paginated() {
+    let app = App::new().await;
+    for email in ["a@example.com", "b@example.com", "c@example.com"] {
+        app.insert_confirmed_subscriber(email).await;
+    }
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+
+    let report: serde_json::Value = app
+        .get_newsletter(newsletter_issue_id, &[("page", "2"), ("per_page", "2")])
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(report["progress"]["sent"], 3);
+    assert_eq!(report["deliveries"].as_array().unwrap().len(), 1);
+    assert_eq!(report["deliveries"][0]["email"], "c@example.com");
+}
+
+#[tokio::test]
+async fn progress_of_unknown_newsletter_issue_is_not_found() {
+    let app = App::new().await;
+
+    let response = app.get_newsletter(&Uuid::new_v4().to_string(), &[]).await;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    })
+}
+
+fn scheduled_newsletter_request_body(delay: Duration) -> serde_json::Value {
+    let send_at = Utc::now() + delay;
+
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": send_at.to_rfc3339(),
+    })
+}
+
+async fn make_issue_due(app: &App, newsletter_issue_id: &str) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET send_at = now() WHERE newsletter_issue_id = $1",
+        Uuid::parse_str(newsletter_issue_id).unwrap(),
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+}
+
+// Leaves the issue as a worker which stopped `age` ago would have.
+async fn set_issue_heartbeat(app: &App, newsletter_issue_id: &str, age: &str) {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = 'sending',
+                started_at = now() - $2::text::interval,
+                heartbeat_at = now() - $2::text::interval
+            WHERE newsletter_issue_id = $1
+        "#,
+        Uuid::parse_str(newsletter_issue_id).unwrap(),
+        age,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+}
+
+async fn get_issue_status(app: &App, newsletter_issue_id: &str) -> String {
+    sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        Uuid::parse_str(newsletter_issue_id).unwrap(),
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap()
+    .status
+}
+
+async fn wait_for_issue_status(app: &App, newsletter_issue_id: &str, expected: &str) {
+    for _ in 0..100 {
+        if get_issue_status(app, newsletter_issue_id).await == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("Newsletter issue never reached status {}", expected);
+}
+
 async fn create_unconfirmed_subscriber(app: &App) -> ConfirmationLinks {
     let parameter = [("name", "arine"), ("email", "peppydays@gmail.com")];
 
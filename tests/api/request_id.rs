@@ -0,0 +1,135 @@
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::App;
+
+fn request_id(response: &reqwest::Response) -> String {
+    response.headers()["x-request-id"]
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test]
+async fn a_request_id_is_generated_when_none_is_given() {
+    let app = App::new().await;
+
+    let response = app.get_health("/health/live").await;
+
+    assert!(Uuid::parse_str(&request_id(&response)).is_ok());
+}
+
+#[tokio::test]
+async fn a_valid_incoming_request_id_is_echoed() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::GET, "/health/live")
+        .header("X-Request-Id", "lb-7f3a9c")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(request_id(&response), "lb-7f3a9c");
+}
+
+#[tokio::test]
+async fn an_invalid_incoming_request_id_is_replaced() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::GET, "/health/live")
+        .header("X-Request-Id", "a".repeat(200))
+        .send()
+        .await
+        .unwrap();
+
+    assert!(Uuid::parse_str(&request_id(&response)).is_ok());
+}
+
+#[tokio::test]
+async fn subscribe_errors_include_the_request_id() {
+    let app = App::new().await;
+
+    let response = app
+        .post_subscriptions(&[("name", "arine"), ("email", "not-an-email")])
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let request_id = request_id(&response);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["request_id"], request_id);
+}
+
+#[tokio::test]
+async fn publish_errors_include_the_request_id() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::POST, "/newsletters")
+        .header("X-Request-Id", "support-ticket-42")
+        .basic_auth("nobody", Some("wrong"))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter body as HTML</p>" },
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["request_id"], "support-ticket-42");
+}
+
+#[tokio::test]
+async fn login_errors_include_the_request_id() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::POST, "/login")
+        .header("X-Request-Id", "support-ticket-43")
+        .form(&[("username", "nobody"), ("password", "wrong")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["request_id"], "support-ticket-43");
+}
+
+#[tokio::test]
+async fn the_request_id_is_forwarded_to_the_email_provider() {
+    let app = App::new().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.build_request(Method::POST, "/subscriptions")
+        .header("X-Request-Id", "support-ticket-44")
+        .form(&[
+            ("name", "arine"),
+            ("email", "peppydays@gmail.com"),
+            ("form_token", &app.form_token()),
+        ])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let header = email_request
+        .headers
+        .get(&"x-request-id".parse().unwrap())
+        .unwrap();
+    assert_eq!(header.last().as_str(), "support-ticket-44");
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Metadata"]["request_id"], "support-ticket-44");
+}
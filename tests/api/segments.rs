@@ -0,0 +1,229 @@
+use reqwest::{Method, StatusCode};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn tags_and_custom_fields_are_stored_at_subscribe_time() {
+    let app = App::new().await;
+    add_custom_field(&app, "country", "text").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[
+            ("name", "arine"),
+            ("email", "arine@gmail.com"),
+            ("tags", "Beta, early"),
+            ("fields[country]", "DE"),
+        ])
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let saved = sqlx::query!("SELECT tags, custom_fields FROM subscriptions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.tags, vec!["beta", "early"]);
+    assert_eq!(saved.custom_fields, serde_json::json!({ "country": "DE" }));
+}
+
+#[tokio::test]
+async fn subscribing_with_invalid_tags_or_custom_fields_is_rejected() {
+    let app = App::new().await;
+    add_custom_field(&app, "age", "number").await;
+
+    let test_cases = [
+        (("tags", "beta testers"), "invalid tag"),
+        (("fields[country]", "DE"), "unknown custom field"),
+        (("fields[age]", "many"), "invalid number"),
+    ];
+
+    for (parameter, description) in test_cases {
+        let response = app
+            .post_subscriptions(&[("name", "arine"), ("email", "arine@gmail.com"), parameter])
+            .await;
+
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "The API did not reject a subscription with {}",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn tags_and_custom_fields_can_be_updated_by_admins() {
+    let app = App::new().await;
+    add_custom_field(&app, "country", "text").await;
+    add_custom_field(&app, "age", "number").await;
+    let subscriber_id = app.insert_confirmed_subscriber("reader@example.com").await;
+
+    let response = app
+        .authenticated_request(
+            Method::PUT,
+            &format!("/admin/subscribers/{}/tags", subscriber_id),
+        )
+        .await
+        .json(&serde_json::json!({ "tags": ["beta", "Beta", "vip"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["tags"], serde_json::json!(["beta", "vip"]));
+
+    for (fields, expected) in [
+        (
+            serde_json::json!({ "country": "DE", "age": 30 }),
+            serde_json::json!({ "country": "DE", "age": 30 }),
+        ),
+        (
+            serde_json::json!({ "age": null }),
+            serde_json::json!({ "country": "DE" }),
+        ),
+    ] {
+        let response = patch_fields(&app, subscriber_id, &fields).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.json::<serde_json::Value>().await.unwrap(),
+            expected
+        );
+    }
+
+    let response = patch_fields(&app, subscriber_id, &serde_json::json!({ "age": "old" })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = patch_fields(&app, Uuid::new_v4(), &serde_json::json!({ "age": 1 })).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected() {
+    let app = App::new().await;
+
+    for query in ["country = DE", "tag beta", "tag = beta and"] {
+        let response = add_segment(&app, "germany", query).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_only_to_subscribers_in_the_segment() {
+    let app = App::new().await;
+    add_custom_field(&app, "country", "text").await;
+    let german = app.insert_confirmed_subscriber("german@example.com").await;
+    let beta = app.insert_confirmed_subscriber("beta@example.com").await;
+    app.insert_confirmed_subscriber("other@example.com").await;
+    patch_fields(&app, german, &serde_json::json!({ "country": "DE" })).await;
+    app.authenticated_request(Method::PUT, &format!("/admin/subscribers/{}/tags", beta))
+        .await
+        .json(&serde_json::json!({ "tags": ["beta"] }))
+        .send()
+        .await
+        .unwrap();
+
+    let response = add_segment(&app, "germany-or-beta", "country = DE or tag = beta").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = app
+        .authenticated_request(Method::GET, "/admin/segments/germany-or-beta/preview")
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["recipients"], 2);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter body as HTML</p>" },
+            "segment": "germany-or-beta",
+        }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    recipients.sort();
+    assert_eq!(recipients, ["beta@example.com", "german@example.com"]);
+}
+
+#[tokio::test]
+async fn newsletters_targeting_unknown_segments_are_rejected() {
+    let app = App::new().await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter body as HTML</p>" },
+            "segment": "unknown",
+        }))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+async fn add_custom_field(app: &App, name: &str, field_type: &str) {
+    let response = app
+        .authenticated_request(Method::POST, "/admin/fields")
+        .await
+        .json(&serde_json::json!({ "name": name, "type": field_type }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+async fn add_segment(app: &App, name: &str, query: &str) -> reqwest::Response {
+    app.authenticated_request(Method::POST, "/admin/segments")
+        .await
+        .json(&serde_json::json!({ "name": name, "query": query }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn patch_fields(
+    app: &App,
+    subscriber_id: Uuid,
+    fields: &serde_json::Value,
+) -> reqwest::Response {
+    app.authenticated_request(
+        Method::PATCH,
+        &format!("/admin/subscribers/{}/fields", subscriber_id),
+    )
+    .await
+    .json(fields)
+    .send()
+    .await
+    .unwrap()
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn shutting_down_closes_the_pool_and_stops_accepting_requests() {
+    let app = App::new().await;
+
+    app.shutdown.cancel();
+
+    for _ in 0..50 {
+        if app.pool.is_closed() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(app.pool.is_closed());
+    assert!(app
+        .build_request(reqwest::Method::GET, "/health/live")
+        .send()
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn publishing_stops_after_the_email_in_flight_when_shutting_down() {
+    let app = App::new().await;
+    for i in 0..3 {
+        app.insert_confirmed_subscriber(&format!("arine{}@example.com", i))
+            .await;
+    }
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": { "html": "<p>Newsletter body as HTML</p>" },
+    });
+    let publish = app.post_newsletters(&body);
+    // Shut down while the first email is being sent.
+    let cancel = async {
+        while app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        app.shutdown.cancel();
+    };
+    let (response, _) = tokio::join!(publish, cancel);
+
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "scheduled");
+}
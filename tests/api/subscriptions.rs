@@ -112,7 +112,7 @@ async fn subscribe_fails_if_there_is_fatal_database_error() {
     let app = App::new().await;
     let parameter = [("name", "arine"), ("email", "peppydays@gmail.com")];
 
-    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token")
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token CASCADE")
         .execute(&app.pool)
         .await
         .unwrap();
@@ -121,3 +121,58 @@ async fn subscribe_fails_if_there_is_fatal_database_error() {
 
     assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
 }
+
+#[tokio::test]
+async fn subscribe_treats_case_variants_of_an_email_as_the_same_subscriber() {
+    let app = App::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["Peppydays@Gmail.com", "peppydays@GMAIL.COM"] {
+        let response = app
+            .post_subscriptions(&[("name", "arine"), ("email", email)])
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let saved = sqlx::query!("SELECT email, normalised_email FROM subscriptions")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "Peppydays@gmail.com");
+    assert_eq!(saved[0].normalised_email, "peppydays@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribe_suggests_a_correction_for_typo_domains() {
+    let app = App::new().await;
+
+    let response = app
+        .post_subscriptions(&[("name", "arine"), ("email", "peppydays@gmial.com")])
+        .await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let message = body["detail"].as_str().unwrap();
+    assert!(
+        message.contains("Did you mean peppydays@gmail.com?"),
+        "{}",
+        message
+    );
+}
+
+#[tokio::test]
+async fn subscribe_rejects_disposable_email_domains() {
+    let app = App::new().await;
+
+    let response = app
+        .post_subscriptions(&[("name", "arine"), ("email", "peppydays@mailinator.com")])
+        .await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
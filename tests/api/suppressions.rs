@@ -0,0 +1,232 @@
+use reqwest::{Method, StatusCode};
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use uuid::Uuid;
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_addresses() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("suppressed@example.com")
+        .await;
+    add_suppression(&app, "Suppressed@Example.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            }
+        }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    let report: serde_json::Value = app
+        .get_newsletter(body["newsletter_issue_id"].as_str().unwrap(), &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["progress"]["skipped"], 1);
+    assert_eq!(report["progress"]["failed"], 0);
+    assert_eq!(report["deliveries"][0]["status"], "skipped");
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_not_sent_to_suppressed_addresses() {
+    let app = App::new().await;
+    add_suppression(&app, "peppydays@gmail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(&[("name", "arine"), ("email", "peppydays@gmail.com")])
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn queued_confirmation_emails_to_addresses_suppressed_since_are_skipped() {
+    let app = App::new().await;
+    add_suppression(&app, "peppydays@gmail.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Queued as if the subscriber had signed up before the address was suppressed.
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriptions (id, email, normalised_email, name, subscribed_at, status)
+            VALUES ($1, 'peppydays@gmail.com', 'peppydays@gmail.com', 'arine', now(),
+                'pending_confirmation')
+        "#,
+        subscriber_id,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ('queued', $1)",
+        subscriber_id,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    sqlx::query!("INSERT INTO confirmation_email_queue (subscription_token) VALUES ('queued')")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    for _ in 0..50 {
+        let status = sqlx::query!("SELECT status FROM confirmation_email_queue")
+            .fetch_one(&app.pool)
+            .await
+            .unwrap()
+            .status;
+        if status == "skipped" {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("The worker did not skip the confirmation email");
+}
+
+#[tokio::test]
+async fn suppressions_can_be_listed_added_and_removed() {
+    let app = App::new().await;
+
+    let response = app
+        .authenticated_request(Method::POST, "/admin/suppressions")
+        .await
+        .json(&serde_json::json!({ "email": "Someone@Example.com", "reason": "requested" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let suppressions: serde_json::Value = app
+        .authenticated_request(Method::GET, "/admin/suppressions")
+        .await
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(suppressions[0]["email"], "someone@example.com");
+    assert_eq!(suppressions[0]["reason"], "requested");
+    assert_eq!(suppressions[0]["source"], "admin");
+
+    let response = app
+        .authenticated_request(Method::DELETE, "/admin/suppressions/someone@example.com")
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .authenticated_request(Method::DELETE, "/admin/suppressions/someone@example.com")
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn suppressions_are_removed_whatever_the_spelling_of_the_address() {
+    let app = App::new().await;
+
+    let response = app
+        .authenticated_request(Method::POST, "/admin/suppressions")
+        .await
+        .json(&serde_json::json!({ "email": "someone@bücher.example" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    for (path, expected) in [
+        ("/admin/suppressions/not-an-email", StatusCode::BAD_REQUEST),
+        (
+            "/admin/suppressions/%20Someone@B%C3%BCcher.example%20",
+            StatusCode::OK,
+        ),
+    ] {
+        let response = app
+            .authenticated_request(Method::DELETE, path)
+            .await
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn invalid_email_addresses_cannot_be_suppressed() {
+    let app = App::new().await;
+
+    let response = app
+        .authenticated_request(Method::POST, "/admin/suppressions")
+        .await
+        .json(&serde_json::json!({ "email": "definitely-not-an-email" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn suppression_endpoints_require_authentication() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::GET, "/admin/suppressions")
+        .basic_auth("unknown", Some("password"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="admin""#
+    );
+}
+
+async fn add_suppression(app: &App, email: &str) {
+    app.authenticated_request(Method::POST, "/admin/suppressions")
+        .await
+        .json(&serde_json::json!({ "email": email }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
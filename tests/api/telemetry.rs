@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use reqwest::Method;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use newsletter::configuration::OtlpSettings;
+use newsletter::telemetry::{get_subscriber, otlp_tracer, OtlpProtocol};
+use tracing::instrument::WithSubscriber;
+
+use crate::helpers::App;
+
+const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+
+#[tokio::test]
+async fn the_trace_context_of_a_request_is_forwarded_to_the_email_provider() {
+    let app = App::new().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .build_request(Method::POST, "/subscriptions")
+        .header(
+            "traceparent",
+            format!("00-{}-b7ad6b7169203331-01", TRACE_ID),
+        )
+        .form(&[
+            ("name", "arine"),
+            ("email", "peppydays@gmail.com"),
+            ("form_token", &app.form_token()),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request
+        .headers
+        .get(&"traceparent".parse().unwrap())
+        .unwrap();
+    assert!(traceparent
+        .last()
+        .as_str()
+        .starts_with(&format!("00-{}-", TRACE_ID)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spans_are_exported_to_an_otlp_collector() {
+    let collector = MockServer::start().await;
+    Mock::given(path("/v1/traces"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&collector)
+        .await;
+    let tracer = otlp_tracer(
+        "test",
+        &OtlpSettings {
+            protocol: OtlpProtocol::Http,
+            endpoint: collector.uri(),
+            timeout_in_milliseconds: 1000,
+        },
+    )
+    .unwrap();
+    let provider = tracer.provider().unwrap();
+    let subscriber = get_subscriber("test".into(), "info".into(), std::io::sink, Some(tracer));
+
+    async {
+        let span = tracing::info_span!("Exported span");
+        let _guard = span.enter();
+        tracing::info!("inside the exported span");
+    }
+    .with_subscriber(subscriber)
+    .await;
+    tokio::task::spawn_blocking(move || provider.force_flush())
+        .await
+        .unwrap();
+
+    for _ in 0..50 {
+        if !collector.received_requests().await.unwrap().is_empty() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("No spans were exported to the collector");
+}
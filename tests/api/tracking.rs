@@ -0,0 +1,147 @@
+use reqwest::{redirect::Policy, Client, Method, StatusCode};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn tracked_newsletters_rewrite_links_and_include_a_pixel() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("reader@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(&newsletter_request_body(true)).await;
+
+    let html = sent_html(&app).await;
+    assert!(!html.contains("https://example.com/article"));
+    assert!(html.contains(&format!(r#"href="{}/t/c/"#, app.access_url.0)));
+    assert!(html.contains(&format!(r#"<img src="{}/t/o/"#, app.access_url.0)));
+}
+
+#[tokio::test]
+async fn untracked_newsletters_are_sent_unchanged() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("reader@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(&newsletter_request_body(false)).await;
+
+    let html = sent_html(&app).await;
+    assert!(html.starts_with(r#"<p>Read <a href="https://example.com/article">this</a></p>"#));
+    assert!(!html.contains("/t/"));
+}
+
+#[tokio::test]
+async fn clicks_and_opens_are_recorded_and_reported() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("reader@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(&newsletter_request_body(true)).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+
+    let html = sent_html(&app).await;
+    let click_link = extract_url(&html, "href=\"");
+    let pixel_link = extract_url(&html, "src=\"");
+
+    let client = Client::builder().redirect(Policy::none()).build().unwrap();
+    let response = client.get(&click_link).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/article"
+    );
+
+    let response = client.get(&pixel_link).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+
+    let report: serde_json::Value = app
+        .authenticated_request(
+            Method::GET,
+            &format!("/admin/newsletters/{}/engagement", newsletter_issue_id),
+        )
+        .await
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["delivered"], 1);
+    assert_eq!(report["unique_opens"], 1);
+    assert_eq!(report["open_rate"], 1.0);
+    assert_eq!(report["click_rate"], 1.0);
+    assert_eq!(report["top_links"][0]["url"], "https://example.com/article");
+    assert_eq!(report["top_links"][0]["clicks"], 1);
+}
+
+#[tokio::test]
+async fn tampered_click_tokens_are_not_redirected() {
+    let app = App::new().await;
+    app.insert_confirmed_subscriber("reader@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(&newsletter_request_body(true)).await;
+
+    let html = sent_html(&app).await;
+    let click_link = extract_url(&html, "href=\"");
+    let (token_payload, signature) = click_link.rsplit_once('.').unwrap();
+    let tampered_link = format!("{}x.{}", token_payload, signature);
+
+    let client = Client::builder().redirect(Policy::none()).build().unwrap();
+    let response = client.get(&tampered_link).send().await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(response.headers().get("Location").is_none());
+}
+
+fn newsletter_request_body(tracking: bool) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": r#"<p>Read <a href="https://example.com/article">this</a></p>"#
+        },
+        "tracking": tracking,
+    })
+}
+
+async fn sent_html(app: &App) -> String {
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+    body["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+fn extract_url(html: &str, attribute: &str) -> String {
+    let start = html.find(attribute).unwrap() + attribute.len();
+    let end = start + html[start..].find('"').unwrap();
+
+    html[start..end].to_owned()
+}
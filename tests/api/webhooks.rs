@@ -0,0 +1,305 @@
+use reqwest::{Method, StatusCode};
+use uuid::Uuid;
+
+use crate::helpers::App;
+
+#[tokio::test]
+async fn hard_bounces_mark_the_delivery_and_suppress_the_subscriber() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("bounced@example.com").await;
+    insert_sent_delivery(&app, subscriber_id, "message-1").await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce",
+            "TypeCode": 1,
+            "MessageID": "message-1",
+            "Email": "bounced@example.com",
+            "BouncedAt": "2026-10-19T09:00:00Z"
+        }))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let delivery = sqlx::query!(
+        "SELECT status, bounce_type, bounced_at FROM deliveries WHERE provider_message_id = 'message-1'"
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "bounced");
+    assert_eq!(delivery.bounce_type.as_deref(), Some("HardBounce"));
+    assert_eq!(
+        delivery.bounced_at.unwrap().to_rfc3339(),
+        "2026-10-19T09:00:00+00:00"
+    );
+    assert_eq!(
+        get_subscriber_status(&app, subscriber_id).await,
+        "suppressed"
+    );
+    let suppression =
+        sqlx::query!("SELECT reason, source FROM suppressions WHERE email = 'bounced@example.com'")
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(suppression.reason, "hard_bounce");
+    assert_eq!(suppression.source, "postmark_webhook");
+}
+
+#[tokio::test]
+async fn soft_bounces_suppress_the_subscriber_once_the_threshold_is_reached() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("soft@example.com").await;
+    insert_sent_delivery(&app, subscriber_id, "message-1").await;
+    insert_sent_delivery(&app, subscriber_id, "message-2").await;
+
+    for (message_id, expected_status) in [("message-1", "confirmed"), ("message-2", "suppressed")] {
+        app.post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "SoftBounce",
+            "TypeCode": 4096,
+            "MessageID": message_id,
+            "Email": "soft@example.com"
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+        assert_eq!(
+            get_subscriber_status(&app, subscriber_id).await,
+            expected_status
+        );
+    }
+}
+
+#[tokio::test]
+async fn soft_bounces_outside_the_window_do_not_count() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("soft@example.com").await;
+    insert_sent_delivery(&app, subscriber_id, "message-1").await;
+    insert_sent_delivery(&app, subscriber_id, "message-2").await;
+    sqlx::query!(
+        r#"
+            UPDATE deliveries
+            SET status = 'bounced', bounce_type = 'SoftBounce', bounced_at = now() - interval '60 days'
+            WHERE provider_message_id = 'message-1'
+        "#
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    app.post_postmark_webhook(&serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "SoftBounce",
+        "TypeCode": 4096,
+        "MessageID": "message-2",
+        "Email": "soft@example.com"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    assert_eq!(
+        get_subscriber_status(&app, subscriber_id).await,
+        "confirmed"
+    );
+}
+
+#[tokio::test]
+async fn spam_complaints_suppress_the_subscriber() {
+    let app = App::new().await;
+    let subscriber_id = app
+        .insert_confirmed_subscriber("complaint@example.com")
+        .await;
+    insert_sent_delivery(&app, subscriber_id, "message-1").await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Type": "SpamComplaint",
+            "TypeCode": 512,
+            "MessageID": "message-1",
+            "Email": "complaint@example.com"
+        }))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let delivery = sqlx::query!(
+        "SELECT complained_at FROM deliveries WHERE provider_message_id = 'message-1'"
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert!(delivery.complained_at.is_some());
+    assert_eq!(
+        get_subscriber_status(&app, subscriber_id).await,
+        "suppressed"
+    );
+}
+
+#[tokio::test]
+async fn bounces_for_unknown_messages_suppress_the_subscriber_by_address() {
+    let app = App::new().await;
+    let subscriber_id = app.insert_confirmed_subscriber("bounced@example.com").await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce",
+            "MessageID": "unknown-message",
+            "Email": "bounced@example.com"
+        }))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        get_subscriber_status(&app, subscriber_id).await,
+        "suppressed"
+    );
+}
+
+#[tokio::test]
+async fn bounces_match_the_subscriber_whatever_the_spelling_of_the_address() {
+    let app = App::new().await;
+    let subscriber_id = app
+        .insert_confirmed_subscriber("bounced@xn--bcher-kva.de")
+        .await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce",
+            "MessageID": "unknown-message",
+            "Email": "Bounced@Bücher.de"
+        }))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        get_subscriber_status(&app, subscriber_id).await,
+        "suppressed"
+    );
+    let suppressed = app
+        .authenticated_request(Method::GET, "/admin/suppressions")
+        .await
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(suppressed[0]["email"], "bounced@xn--bcher-kva.de");
+}
+
+#[tokio::test]
+async fn delivery_events_record_the_delivery_time() {
+    let app = App::new().await;
+    let subscriber_id = app
+        .insert_confirmed_subscriber("delivered@example.com")
+        .await;
+    insert_sent_delivery(&app, subscriber_id, "message-1").await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": "message-1",
+            "Recipient": "delivered@example.com",
+            "DeliveredAt": "2026-10-19T09:00:01Z"
+        }))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let delivery = sqlx::query!(
+        "SELECT status, delivered_at FROM deliveries WHERE provider_message_id = 'message-1'"
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(
+        delivery.delivered_at.unwrap().to_rfc3339(),
+        "2026-10-19T09:00:01+00:00"
+    );
+    assert_eq!(
+        get_subscriber_status(&app, subscriber_id).await,
+        "confirmed"
+    );
+}
+
+#[tokio::test]
+async fn unsupported_record_types_are_acknowledged() {
+    let app = App::new().await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Open",
+            "MessageID": "message-1"
+        }))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn webhooks_with_invalid_credentials_are_rejected() {
+    let app = App::new().await;
+
+    let response = app
+        .build_request(Method::POST, "/webhooks/postmark")
+        .json(&serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": "message-1"
+        }))
+        .basic_auth(&app.webhooks.username, Some("wrong-password"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="webhooks""#
+    );
+}
+
+async fn insert_sent_delivery(app: &App, subscriber_id: Uuid, provider_message_id: &str) {
+    let newsletter_issue_id = Uuid::new_v4();
+
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_issues
+                (newsletter_issue_id, title, html_content, status, published_at)
+            VALUES ($1, 'Title', '<p>Content</p>', 'sent', now())
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    sqlx::query!(
+        r#"
+            INSERT INTO deliveries
+                (newsletter_issue_id, subscriber_id, status, provider_message_id, queued_at, sent_at)
+            VALUES ($1, $2, 'sent', $3, now(), now())
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+        provider_message_id,
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+}
+
+async fn get_subscriber_status(app: &App, subscriber_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap()
+    .status
+}